@@ -0,0 +1,162 @@
+//! Typed views of the Jira responses listing attachments. GitLab and GitHub uploads are turned
+//! into the same [`Attachment`] records.
+
+use serde::{Deserialize, Deserializer};
+use time::OffsetDateTime;
+
+/// An attachment of an issue, as listed by Jira.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    pub filename: String,
+    /// URL of the attachment's content.
+    pub content: String,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    /// Creation time in Jira's format, e.g. `2024-05-01T10:00:00.000+0200`.
+    #[serde(default)]
+    pub created: Option<String>,
+    /// URL of Jira's preview of an image attachment.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+}
+
+impl Attachment {
+    /// The thumbnail URL if the attachment is an image that has one.
+    pub fn image_thumbnail(&self) -> Option<&str> {
+        let is_image = self.mime_type.as_deref().is_some_and(|mime| mime.starts_with("image/"));
+        self.thumbnail.as_deref().filter(|_| is_image)
+    }
+
+    pub fn created_time(&self) -> Option<OffsetDateTime> {
+        self.created.as_deref().and_then(crate::fetch::parse_jira_time)
+    }
+}
+
+/// The parts of `/rest/api/2/issue/{key}?fields=attachment,summary,status,assignee` getlogs
+/// reads.
+#[derive(Deserialize, Debug, Default)]
+pub struct IssueResponse {
+    #[serde(default)]
+    pub fields: IssueFields,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct IssueFields {
+    /// Missing if the account may not see attachments.
+    pub attachment: Option<Vec<Attachment>>,
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub status: Option<Named>,
+    /// `null` for unassigned issues.
+    #[serde(default)]
+    pub assignee: Option<User>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Named {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub display_name: String,
+}
+
+/// A page of `/rest/api/2/issue/{key}/attachments`. Some servers return a plain array instead.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum AttachmentPage {
+    #[serde(rename_all = "camelCase")]
+    Paged {
+        values: Vec<Attachment>,
+        #[serde(default)]
+        is_last: bool,
+    },
+    Plain(Vec<Attachment>),
+}
+
+impl AttachmentPage {
+    /// The attachments of the page, and whether the server marked it as the last one.
+    pub fn into_parts(self) -> (Vec<Attachment>, bool) {
+        match self {
+            AttachmentPage::Paged { values, is_last } => (values, is_last),
+            AttachmentPage::Plain(values) => (values, false),
+        }
+    }
+}
+
+/// Jira sends IDs as strings, some proxies and older servers as numbers.
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        String(String),
+        Number(u64),
+    }
+
+    Ok(match Id::deserialize(deserializer)? {
+        Id::String(id) => id,
+        Id::Number(id) => id.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_issues_with_missing_and_numeric_fields() {
+        let issue: IssueResponse = serde_json::from_value(json!({
+            "fields": {
+                "attachment": [
+                    { "id": "10", "filename": "a.log", "content": "https://jira/a", "size": 12, "mimeType": "text/plain", "created": "2024-05-01T10:00:00.000+0200" },
+                    { "id": 11, "filename": "b.png", "content": "https://jira/b", "mimeType": "image/png", "thumbnail": "https://jira/t" }
+                ],
+                "summary": "Crash",
+                "assignee": null
+            }
+        })).unwrap();
+
+        let attachments = issue.fields.attachment.unwrap();
+        assert_eq!(attachments[0].id, "10");
+        assert_eq!(attachments[0].size, Some(12));
+        assert_eq!(attachments[0].created_time(), Some(time::macros::datetime!(2024-05-01 8:00 UTC)));
+        assert_eq!(attachments[0].image_thumbnail(), None);
+        assert_eq!(attachments[1].id, "11");
+        assert_eq!(attachments[1].size, None);
+        assert_eq!(attachments[1].image_thumbnail(), Some("https://jira/t"));
+        assert!(issue.fields.assignee.is_none());
+        assert!(issue.fields.status.is_none());
+    }
+
+    #[test]
+    fn tells_hidden_attachments_from_none() {
+        let hidden: IssueResponse = serde_json::from_value(json!({ "fields": {} })).unwrap();
+        assert!(hidden.fields.attachment.is_none());
+        let empty: IssueResponse = serde_json::from_value(json!({ "fields": { "attachment": [] } })).unwrap();
+        assert_eq!(empty.fields.attachment, Some(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_attachments_without_required_fields() {
+        assert!(serde_json::from_value::<Attachment>(json!({ "id": "1", "filename": "a.log" })).is_err());
+        assert!(serde_json::from_value::<Attachment>(json!({ "id": true, "filename": "a.log", "content": "x" })).is_err());
+    }
+
+    #[test]
+    fn reads_paged_and_plain_attachment_pages() {
+        let att = json!({ "id": "1", "filename": "a.log", "content": "x" });
+        let (values, is_last) = serde_json::from_value::<AttachmentPage>(json!({ "values": [att], "isLast": true })).unwrap().into_parts();
+        assert_eq!((values.len(), is_last), (1, true));
+        let (values, is_last) = serde_json::from_value::<AttachmentPage>(json!([att, att])).unwrap().into_parts();
+        assert_eq!((values.len(), is_last), (2, false));
+    }
+}
@@ -0,0 +1,132 @@
+//! Limits on the bytes downloaded and the time spent by a whole run, for unattended batches on
+//! metered or small machines.
+
+use std::{sync::atomic::{AtomicU64, Ordering}, time::{Duration, Instant}};
+use anyhow::bail;
+
+/// Bytes and time a run may use. Without limits, nothing is checked.
+#[derive(Debug)]
+pub struct Budget {
+    max_bytes: Option<u64>,
+    max_duration: Option<Duration>,
+    start: Instant,
+    used: AtomicU64,
+}
+
+/// The run was stopped because it would have exceeded its [`Budget`].
+#[derive(Debug)]
+pub struct BudgetExceeded(String);
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget::new(None, None)
+    }
+}
+
+impl Budget {
+    pub fn new(max_bytes: Option<u64>, max_duration: Option<Duration>) -> Self {
+        Budget { max_bytes, max_duration, start: Instant::now(), used: AtomicU64::new(0) }
+    }
+
+    /// Bytes downloaded so far.
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    /// Fails with [`BudgetExceeded`] once the time is up or more bytes than allowed were
+    /// downloaded.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if let Some(max) = self.max_bytes && self.used() > max {
+            return Err(BudgetExceeded(format!("Stopped after downloading {} bytes, more than --max-total-bytes {}", self.used(), max)).into());
+        }
+
+        if let Some(max) = self.max_duration && self.start.elapsed() > max {
+            return Err(BudgetExceeded(format!(
+                "Stopped, the run took longer than --max-duration {}s ({} bytes downloaded)",
+                max.as_secs(), self.used()
+            )).into());
+        }
+
+        Ok(())
+    }
+
+    /// Fails with [`BudgetExceeded`] if downloading `bytes` more would exceed the byte limit.
+    pub fn reserve(&self, bytes: u64) -> anyhow::Result<()> {
+        let used = self.used();
+        if let Some(max) = self.max_bytes && used + bytes > max {
+            return Err(BudgetExceeded(format!(
+                "Stopped, the next download of {} bytes would exceed --max-total-bytes {} ({} bytes downloaded)",
+                bytes, max, used
+            )).into());
+        }
+
+        Ok(())
+    }
+
+    /// Counts `bytes` as downloaded.
+    pub fn add(&self, bytes: u64) {
+        self.used.fetch_add(bytes, Ordering::SeqCst);
+    }
+}
+
+/// Durations like `90s`, `30m`, `2h` or `1d`; plain numbers are seconds.
+pub fn parse_duration(value: &str) -> anyhow::Result<Duration> {
+    let (amount, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+
+    let amount: u64 = amount.parse()?;
+    let factor = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("Unknown unit `{}`, expected s, m, h or d", unit),
+    };
+    let Some(seconds) = amount.checked_mul(factor) else {
+        bail!("{} is too long", value);
+    };
+
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(2 * 3600));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86_400));
+    }
+
+    #[test]
+    fn rejects_bad_durations_without_panicking() {
+        for value in ["", "m", "1w", "1.5h", "-1s", "1ä", "ä", "99999999999999999d", "1 h"] {
+            assert!(parse_duration(value).is_err(), "{} should not parse", value);
+        }
+    }
+
+    #[test]
+    fn stops_once_the_bytes_are_used_up() {
+        let budget = Budget::new(Some(100), None);
+        budget.add(60);
+        assert!(budget.check().is_ok());
+        assert!(budget.reserve(40).is_ok());
+        assert!(budget.reserve(41).unwrap_err().is::<BudgetExceeded>());
+        budget.add(41);
+        assert!(budget.check().unwrap_err().is::<BudgetExceeded>());
+    }
+}
@@ -0,0 +1,84 @@
+//! The `<ISSUE>-logs.tar.gz` bundle of the extracted logs of an issue, for handing them on as one
+//! file.
+
+use std::{fs, path::{Path, PathBuf}};
+use flate2::{Compression, write::GzEncoder};
+use crate::manifest;
+
+/// Packs `dir` into a gzip compressed tarball below `root`, keeping relative paths and mtimes.
+/// Written to a `.part` file renamed to `archive` when done, so a failure leaves no truncated bundle.
+pub fn bundle_logs(dir: &Path, archive: &Path, root: &str) -> anyhow::Result<()> {
+    let part_path = PathBuf::from(format!("{}{}", archive.display(), manifest::PART_SUFFIX));
+
+    let written = write_bundle(dir, &part_path, root);
+    if written.is_err() {
+        let _ = fs::remove_file(&part_path);
+    }
+    written?;
+    fs::rename(&part_path, archive)?;
+
+    Ok(())
+}
+
+fn write_bundle(dir: &Path, archive: &Path, root: &str) -> anyhow::Result<()> {
+    let file = fs::File::create(archive)?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    tar.append_dir_all(root, dir)?;
+    tar.into_inner()?.finish()?;
+
+    Ok(())
+}
+
+/// File name of the bundle of the issue folder `folder`.
+pub fn archive_name(folder: &str) -> String {
+    format!("{}-logs.tar.gz", folder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The files in `archive`, without the directories.
+    fn files(archive: &Path) -> Vec<String> {
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(archive).unwrap()));
+        let mut names: Vec<String> = tar.entries().unwrap()
+            .map(Result::unwrap)
+            .filter(|entry| entry.header().entry_type().is_file())
+            .map(|entry| entry.path().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn packs_the_folder_below_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let extracted = dir.path().join("logs-extracted");
+        fs::create_dir_all(extracted.join("sub")).unwrap();
+        fs::write(extracted.join("main.log"), "main\n").unwrap();
+        fs::write(extracted.join("sub/app.log"), "app\n").unwrap();
+        let archive = dir.path().join(archive_name("ISSUE-1"));
+
+        bundle_logs(&extracted, &archive, "ISSUE-1-logs").unwrap();
+
+        assert_eq!(files(&archive), ["ISSUE-1-logs/main.log", "ISSUE-1-logs/sub/app.log"]);
+        assert!(!dir.path().join("ISSUE-1-logs.tar.gz.part").exists());
+    }
+
+    #[test]
+    fn keeps_the_previous_bundle_when_writing_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let extracted = dir.path().join("logs-extracted");
+        fs::create_dir(&extracted).unwrap();
+        fs::write(extracted.join("main.log"), "main\n").unwrap();
+        let archive = dir.path().join(archive_name("ISSUE-1"));
+        bundle_logs(&extracted, &archive, "ISSUE-1-logs").unwrap();
+        let previous = fs::read(&archive).unwrap();
+
+        assert!(bundle_logs(&dir.path().join("missing"), &archive, "ISSUE-1-logs").is_err());
+
+        assert_eq!(fs::read(&archive).unwrap(), previous);
+        assert!(!dir.path().join("ISSUE-1-logs.tar.gz.part").exists());
+    }
+}
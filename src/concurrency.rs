@@ -0,0 +1,198 @@
+//! How many downloads run at the same time: `max_concurrent_downloads`, or with
+//! `--concurrency-auto` a number adjusted to the throughput it achieves.
+
+use std::time::{Duration, Instant};
+
+/// Downloads running at the same time unless configured otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 1;
+
+/// Cap of the adaptive concurrency unless `max_concurrent_downloads` is set.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 8;
+
+/// Time over which the throughput is measured before the number of downloads is changed.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Improvement of the throughput needed to keep adding downloads.
+const MIN_GAIN: f64 = 1.1;
+
+/// Samples after settling on a number until one more download is tried again, as the network
+/// may have changed.
+const PROBE_INTERVAL: u32 = 6;
+
+/// The number of downloads to run at the same time.
+#[derive(Debug)]
+pub struct Concurrency {
+    limit: usize,
+    max: usize,
+    adaptive: Option<Adaptive>,
+}
+
+/// State of the hill climbing of the adaptive concurrency: starting with one download, one more
+/// is added as long as each addition raises the throughput by 10%. Once one does not, the
+/// number goes back by one and is kept for a while. Errors halve it.
+#[derive(Debug)]
+struct Adaptive {
+    /// Time and bytes downloaded at the start of the current sample.
+    started: Instant,
+    downloaded: u64,
+    /// Throughput in bytes per second before the last download was added.
+    best_rate: f64,
+    /// Samples since the number was last kept, `None` while climbing.
+    settled: Option<u32>,
+}
+
+impl Concurrency {
+    pub fn fixed(limit: usize) -> Self {
+        Concurrency { limit: limit.max(1), max: limit.max(1), adaptive: None }
+    }
+
+    /// Starts with one download and adapts up to `max`.
+    pub fn adaptive(max: usize) -> Self {
+        let adaptive = Adaptive { started: Instant::now(), downloaded: 0, best_rate: 0.0, settled: None };
+        Concurrency { limit: 1, max: max.max(1), adaptive: Some(adaptive) }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Takes a sample once [`SAMPLE_INTERVAL`] has passed since the last, given the `downloaded`
+    /// bytes so far and the number of downloads `running`. While fewer ran than allowed, the
+    /// throughput says nothing about the limit and the sample is discarded.
+    pub fn sample(&mut self, downloaded: u64, running: usize) {
+        self.sample_at(Instant::now(), downloaded, running);
+    }
+
+    /// [`Concurrency::sample`] taken at `now`.
+    fn sample_at(&mut self, now: Instant, downloaded: u64, running: usize) {
+        let Some(adaptive) = &mut self.adaptive else {
+            return;
+        };
+        let elapsed = now.saturating_duration_since(adaptive.started);
+        if elapsed < SAMPLE_INTERVAL {
+            return;
+        }
+
+        let rate = downloaded.saturating_sub(adaptive.downloaded) as f64 / elapsed.as_secs_f64();
+        adaptive.started = now;
+        adaptive.downloaded = downloaded;
+        if running < self.limit {
+            return;
+        }
+
+        match adaptive.settled {
+            None if rate >= adaptive.best_rate * MIN_GAIN && self.limit < self.max => {
+                adaptive.best_rate = rate;
+                self.limit += 1;
+            }
+            None => {
+                if rate < adaptive.best_rate * MIN_GAIN && self.limit > 1 {
+                    self.limit -= 1;
+                }
+                adaptive.settled = Some(0);
+            }
+            Some(samples) if samples + 1 >= PROBE_INTERVAL && self.limit < self.max => {
+                adaptive.best_rate = rate;
+                adaptive.settled = None;
+                self.limit += 1;
+            }
+            Some(samples) => adaptive.settled = Some(samples + 1),
+        }
+    }
+
+    /// Halves the number of downloads after a timeout, a rate limit or a dropped connection.
+    pub fn back_off(&mut self) {
+        let Some(adaptive) = &mut self.adaptive else {
+            return;
+        };
+
+        self.limit = (self.limit / 2).max(1);
+        adaptive.settled = Some(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds samples one [`SAMPLE_INTERVAL`] apart with all allowed downloads running.
+    struct Clock {
+        now: Instant,
+        downloaded: u64,
+    }
+
+    impl Clock {
+        fn new(concurrency: &Concurrency) -> Self {
+            Clock { now: concurrency.adaptive.as_ref().unwrap().started, downloaded: 0 }
+        }
+
+        /// Takes a sample of `rate` bytes per second and returns the new limit.
+        fn sample(&mut self, concurrency: &mut Concurrency, rate: u64) -> usize {
+            self.now += SAMPLE_INTERVAL;
+            self.downloaded += rate * SAMPLE_INTERVAL.as_secs();
+            concurrency.sample_at(self.now, self.downloaded, concurrency.limit());
+            concurrency.limit()
+        }
+    }
+
+    #[test]
+    fn adds_downloads_while_the_throughput_grows() {
+        let mut concurrency = Concurrency::adaptive(4);
+        let mut clock = Clock::new(&concurrency);
+
+        assert_eq!(clock.sample(&mut concurrency, 100), 2);
+        assert_eq!(clock.sample(&mut concurrency, 150), 3);
+        // Less than 10% more: back to two, kept for a while
+        assert_eq!(clock.sample(&mut concurrency, 160), 2);
+        for _ in 1..PROBE_INTERVAL {
+            assert_eq!(clock.sample(&mut concurrency, 150), 2);
+        }
+        assert_eq!(clock.sample(&mut concurrency, 150), 3);
+    }
+
+    #[test]
+    fn stops_at_the_maximum() {
+        let mut concurrency = Concurrency::adaptive(2);
+        let mut clock = Clock::new(&concurrency);
+
+        assert_eq!(clock.sample(&mut concurrency, 100), 2);
+        assert_eq!(clock.sample(&mut concurrency, 1000), 2);
+        assert_eq!(clock.sample(&mut concurrency, 10_000), 2);
+    }
+
+    #[test]
+    fn discards_early_and_partial_samples() {
+        let mut concurrency = Concurrency::adaptive(4);
+        let started = concurrency.adaptive.as_ref().unwrap().started;
+
+        concurrency.sample_at(started + SAMPLE_INTERVAL / 2, 1000, 1);
+        assert_eq!(concurrency.limit(), 1);
+        concurrency.sample_at(started + SAMPLE_INTERVAL, 1000, 0);
+        assert_eq!(concurrency.limit(), 1);
+        concurrency.sample_at(started + SAMPLE_INTERVAL * 2, 2000, 1);
+        assert_eq!(concurrency.limit(), 2);
+    }
+
+    #[test]
+    fn halves_the_downloads_on_errors() {
+        let mut concurrency = Concurrency::adaptive(8);
+        let mut clock = Clock::new(&concurrency);
+        for rate in [100, 200, 400, 800] {
+            clock.sample(&mut concurrency, rate);
+        }
+        assert_eq!(concurrency.limit(), 5);
+
+        concurrency.back_off();
+        assert_eq!(concurrency.limit(), 2);
+        concurrency.back_off();
+        concurrency.back_off();
+        assert_eq!(concurrency.limit(), 1);
+        // Settled after the error instead of climbing right away
+        assert_eq!(clock.sample(&mut concurrency, 10_000), 1);
+
+        let mut fixed = Concurrency::fixed(4);
+        fixed.back_off();
+        fixed.sample(u64::MAX, 4);
+        assert_eq!(fixed.limit(), 4);
+    }
+}
@@ -0,0 +1,890 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque, hash_map::Entry},
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}},
+    thread,
+};
+use anyhow::{Context, bail};
+use regex::Regex;
+use serde_json::Value;
+use crate::{config::ConvertRule, disk::{self, DiskError}, dlt, fibex::Fibex, filename::Sanitizer, interrupt::{self, Interrupted}, limit, manifest::{self, Combined, Manifest}, output, pcap::{self, PcapWriter}, perfetto::TraceWriter, redact::Redactor};
+
+/// A conversion from one log format into a readable output file.
+pub trait Converter: Sync {
+    /// Name used to select the converter in `convert_rules`, e.g. `dlt`.
+    fn name(&self) -> &'static str;
+
+    /// Whether `path` is in the format this converter reads.
+    fn can_handle(&self, path: &Path) -> bool;
+
+    /// Where the converted output of `input` is written.
+    fn output_path(&self, input: &Path, options: &ConvertOptions) -> PathBuf;
+
+    fn convert(&self, input: &Path, output: &Path, options: &ConvertOptions) -> anyhow::Result<()>;
+}
+
+/// Limits on the lines written per converted file, and descriptions needed for decoding.
+#[derive(Default)]
+pub struct ConvertOptions {
+    /// Stop after the first N lines.
+    pub head: Option<usize>,
+    /// Only keep the last N lines.
+    pub tail: Option<usize>,
+    /// Decodes non-verbose DLT messages, which are written as ID and hex without it.
+    pub fibex: Option<Fibex>,
+    /// Routing by file name, consulted before the converters' own detection.
+    pub rules: Vec<Rule>,
+    /// Output format of the DLT converter.
+    pub format: Format,
+    /// Number of files converted at the same time.
+    pub jobs: usize,
+    /// Order in which files are converted.
+    pub order: ConvertOrder,
+    /// Scrubs sensitive data from the converted text.
+    pub redactor: Option<Redactor>,
+    /// Writes DLT messages into one logcat file per application or context ID.
+    pub split_by: Option<SplitBy>,
+    /// ECU IDs whose DLT messages are converted, all if empty.
+    pub ecus: Vec<String>,
+    /// Encoding of the converted text logs.
+    pub encoding: OutputEncoding,
+    /// Name of the converted files relative to the converted folder, see [`output_name`].
+    pub output_template: Option<String>,
+    /// Stop at the first file that fails to convert instead of converting the others.
+    pub fail_fast: bool,
+}
+
+/// Text encoding of converted logs. UTF-16 output starts with a byte order mark.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputEncoding {
+    #[default]
+    Utf8,
+    Utf16le,
+    Utf16be,
+}
+
+/// What DLT logcat output is split by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SplitBy {
+    AppId,
+    ContextId,
+}
+
+impl SplitBy {
+    /// Stands for the ID in the output path until the file of each ID is created.
+    fn placeholder(self) -> &'static str {
+        match self {
+            SplitBy::AppId => "app-id",
+            SplitBy::ContextId => "context-id",
+        }
+    }
+}
+
+/// Order in which files are converted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConvertOrder {
+    /// Alphabetically by file name.
+    #[default]
+    Name,
+    /// Largest files first, so the longest conversions do not start last.
+    Largest,
+}
+
+/// What DLT traces are converted into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Text in logcat's `threadtime` layout.
+    #[default]
+    Logcat,
+    /// Chrome trace event JSON for the Perfetto UI.
+    Perfetto,
+    /// libpcap captures of the CAN and Ethernet frames in network trace messages, one per bus.
+    Pcap,
+}
+
+/// What to do with a file whose name matches a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Convert with the named converter, even if it would not detect the file by itself.
+    Convert(String),
+    /// Keep the file as it is, without converting it.
+    Copy,
+    /// Ignore the file.
+    Skip,
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(action: &str) -> anyhow::Result<Self> {
+        Ok(match action {
+            "copy" => Action::Copy,
+            "skip" => Action::Skip,
+            _ => match action.strip_prefix("convert:") {
+                Some(name) => Action::Convert(name.to_string()),
+                None => bail!("Unknown action `{}`, expected `convert:<name>`, `copy` or `skip`", action),
+            },
+        })
+    }
+}
+
+/// A `convert_rules` entry with its pattern compiled.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub pattern: Regex,
+    pub action: Action,
+}
+
+impl Rule {
+    /// Compiles the rules from the config, checking that every converter they name exists.
+    pub fn parse_all(rules: &[ConvertRule]) -> anyhow::Result<Vec<Rule>> {
+        let converters = converters();
+
+        rules.iter()
+            .map(|rule| {
+                let action: Action = rule.action.parse()?;
+                if let Action::Convert(name) = &action && !converters.iter().any(|c| c.name() == name) {
+                    let names: Vec<&str> = converters.iter().map(|c| c.name()).collect();
+                    bail!("Unknown converter `{}` in convert_rules, known converters: {}", name, names.join(", "));
+                }
+
+                let pattern = Regex::new(&rule.pattern)
+                    .map_err(|e| anyhow::anyhow!("Invalid pattern `{}` in convert_rules: {}", rule.pattern, e))?;
+
+                Ok(Rule { pattern, action })
+            })
+            .collect()
+    }
+}
+
+/// All known converters, in the order they are tried.
+pub fn converters() -> Vec<Box<dyn Converter>> {
+    vec![Box::new(DltConverter), Box::new(EvtxConverter)]
+}
+
+/// Returns the first registered converter that accepts `path`.
+pub fn find_converter<'a>(converters: &'a [Box<dyn Converter>], path: &Path) -> Option<&'a dyn Converter> {
+    converters.iter().find(|c| c.can_handle(path)).map(|c| c.as_ref())
+}
+
+/// The action for `path`: that of the first rule matching its file name, otherwise conversion by
+/// the first converter that detects the format.
+fn route<'a>(converters: &'a [Box<dyn Converter>], rules: &[Rule], path: &Path) -> Option<&'a dyn Converter> {
+    let name = path.file_name()?.to_string_lossy();
+
+    match rules.iter().find(|r| r.pattern.is_match(&name)).map(|r| &r.action) {
+        Some(Action::Convert(converter)) => converters.iter().find(|c| c.name() == converter).map(|c| c.as_ref()),
+        Some(Action::Copy) => {
+            output::success(format!("Kept {} unconverted", path.display()));
+            None
+        }
+        Some(Action::Skip) => None,
+        None => find_converter(converters, path),
+    }
+}
+
+/// The files [`convert_logs`] converted and those it could not.
+#[derive(Debug, Default)]
+pub struct ConvertReport {
+    /// `(input, output)` pairs of the converted files, in the order they were started.
+    pub converted: Vec<(PathBuf, PathBuf)>,
+    /// Names of the files that failed to convert, each reported with its reason when it failed.
+    pub failed: Vec<String>,
+}
+
+impl ConvertReport {
+    /// The error listing the failed files of `dir`, if any failed.
+    pub fn failure(&self, dir: &Path) -> Option<anyhow::Error> {
+        if self.failed.is_empty() {
+            return None;
+        }
+
+        let total = self.converted.len() + self.failed.len();
+        Some(anyhow::anyhow!("{} of {} files could not be converted in {}: {}", self.failed.len(), total, dir.display(), self.failed.join(", ")))
+    }
+}
+
+/// Converts every file in `dir` as routed by the rules in `options`, or that a registered
+/// converter understands. Files are converted on up to `options.jobs` threads, started in
+/// `options.order`. A file that fails is reported, its partial output removed, and the others
+/// are still converted; with `options.fail_fast`, on Ctrl-C or a full disk the first failure
+/// stops the remaining conversions and is returned as the error.
+pub fn convert_logs(dir: &Path, options: &ConvertOptions) -> anyhow::Result<ConvertReport> {
+    let converters = converters();
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.is_file() {
+            files.push((path, entry.metadata()?.len()));
+        }
+    }
+
+    match options.order {
+        ConvertOrder::Name => files.sort(),
+        ConvertOrder::Largest => files.sort_by(|(a, a_size), (b, b_size)| b_size.cmp(a_size).then_with(|| a.cmp(b))),
+    }
+
+    let issue = dir.parent().and_then(Path::file_name).map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut outputs: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut jobs: Vec<(PathBuf, &dyn Converter, PathBuf)> = Vec::new();
+    for (path, _) in files {
+        let Some(converter) = route(&converters, &options.rules, &path) else {
+            continue;
+        };
+
+        let out_path = match &options.output_template {
+            Some(template) => output_name(template, &path, &converter.output_path(&path, options), &issue, options)?,
+            None => converter.output_path(&path, options),
+        };
+        jobs.push((path, converter, out_path));
+    }
+
+    for (path, _, out_path) in &jobs {
+        if let Some(other) = outputs.insert(out_path.clone(), path) {
+            bail!(
+                "{} and {} would both be converted to {}, add {{basename}} to the output name template",
+                other.display(), path.display(), out_path.display()
+            );
+        }
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let failed = Mutex::new(Vec::new());
+    let token = interrupt::stage_token();
+    let worker = || interrupt::with_stage_token(token.clone(), || -> anyhow::Result<Vec<(usize, PathBuf, PathBuf)>> {
+        let mut converted = Vec::new();
+
+        while !stop.load(Ordering::SeqCst) {
+            interrupt::check()?;
+
+            let index = next.fetch_add(1, Ordering::SeqCst);
+            let Some((path, converter, out_path)) = jobs.get(index) else {
+                break;
+            };
+
+            let _permit = limit::acquire();
+            if let Err(err) = converter.convert(path, out_path, options) {
+                remove_partial(out_path);
+
+                // Ctrl-C and a full disk would fail the other files as well
+                let err = disk::classify(err, dir);
+                if options.fail_fast || err.is::<Interrupted>() || err.is::<DiskError>() {
+                    stop.store(true, Ordering::SeqCst);
+                    return Err(err.context(format!("Converting {}", path.display())));
+                }
+
+                output::failure(format!("Failed to convert {}: {:#}", path.display(), err));
+                failed.lock().unwrap().push((index, path.file_name().unwrap_or_default().to_string_lossy().into_owned()));
+                continue;
+            }
+
+            output::success(format!("Converted {} to {}", path.display(), out_path.display()));
+            converted.push((index, path.clone(), out_path.clone()));
+        }
+
+        Ok(converted)
+    });
+
+    let threads = options.jobs.min(limit::max_parallelism().unwrap_or(usize::MAX)).clamp(1, jobs.len().max(1));
+    let results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|_| scope.spawn(worker)).collect();
+        workers.into_iter().map(|w| w.join().expect("Conversion thread panicked")).collect()
+    });
+
+    let mut converted = Vec::new();
+    for result in results {
+        converted.extend(result?);
+    }
+    converted.sort_by_key(|(index, _, _)| *index);
+
+    let mut failed = failed.into_inner().unwrap();
+    failed.sort();
+
+    Ok(ConvertReport {
+        converted: converted.into_iter().map(|(_, input, output)| (input, output)).collect(),
+        failed: failed.into_iter().map(|(_, name)| name).collect(),
+    })
+}
+
+/// Removes what a failed conversion wrote to `output`, so it is not taken for a converted log.
+/// Outputs with a placeholder in their name are removed by the writer that created them.
+fn remove_partial(output: &Path) {
+    if output.is_file() && let Err(err) = fs::remove_file(output) {
+        output::warning(format!("Cannot remove the partial output {}: {}", output.display(), err));
+    }
+}
+
+/// Removes the files at `paths`, the outputs a failed conversion started.
+fn remove_outputs(paths: &[PathBuf]) {
+    for path in paths {
+        remove_partial(path);
+    }
+}
+
+/// The output path of `input` from the output name `template`, relative to the folder of
+/// `input`. `default` is where the converter would write: its name is `<basename>.<ext>`, with
+/// placeholders like `{bus}` that were filled in per output file kept in `{ext}`. Replaces
+/// `{issue}` by the issue folder name, `{appid}` by the application ID with
+/// `--split-by app-id` and by nothing otherwise.
+fn output_name(template: &str, input: &Path, default: &Path, issue: &str, options: &ConvertOptions) -> anyhow::Result<PathBuf> {
+    let basename = input.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let default_name = default.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = default_name.strip_prefix(&format!("{}.", basename)).unwrap_or(&default_name);
+    let appid = match options.split_by {
+        Some(SplitBy::AppId) => format!("{{{}}}", SplitBy::AppId.placeholder()),
+        _ => String::new(),
+    };
+
+    let name = template
+        .replace("{issue}", issue)
+        .replace("{basename}", &basename)
+        .replace("{appid}", &appid)
+        .replace("{ext}", ext);
+
+    let relative = Path::new(&name);
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) || name.is_empty() {
+        bail!("The output name {} of {} must be a relative path without `..`", name, input.display());
+    }
+    for placeholder in ["{app-id}", "{context-id}", "{bus}"] {
+        if default_name.contains(placeholder) && !name.contains(placeholder) {
+            bail!("The output name template {} would write all outputs of {} to one file, add {{ext}}", template, input.display());
+        }
+    }
+
+    Ok(input.with_file_name(relative))
+}
+
+/// The output path of the `pattern` of [`Converter::output_path`] with `placeholder` replaced by `id`.
+fn fill_placeholder(pattern: &Path, placeholder: &str, id: &str) -> PathBuf {
+    let name = pattern.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    pattern.with_file_name(name.replace(&format!("{{{}}}", placeholder), id))
+}
+
+/// Name of the combined log of the issue folder `folder`.
+pub fn combined_name(folder: &str) -> String {
+    format!("{}.combined.log", folder)
+}
+
+/// Appends the output of every `(input, output)` pair of `converted` to the combined log of the
+/// issue folder `dir`, unless the same input was appended before. Inputs are recorded by name and
+/// hash in the manifest. With `rebuild`, the combined log is written from scratch. With `header`,
+/// the log starts with a block describing the issue and listing all sources. With `follow`, the
+/// appended content is written to stdout as well, so repeated runs only show what is new.
+pub fn append_combined(dir: &Path, converted: &[(PathBuf, PathBuf)], rebuild: bool, header: bool, follow: bool) -> anyhow::Result<PathBuf> {
+    let folder = dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let path = dir.join(combined_name(&folder));
+    let mut manifest = Manifest::load(dir)?;
+
+    if rebuild {
+        manifest.combined.clear();
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+    }
+
+    let mut converted: Vec<&(PathBuf, PathBuf)> = converted.iter().collect();
+    converted.sort();
+
+    let mut combined = OpenOptions::new().create(true).append(true).open(&path)?;
+    let mut appended = 0;
+    for (input, output) in converted {
+        let source = input.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let sha256 = manifest::hash_file(input)?;
+        if manifest.combined.iter().any(|c| c.source == source && c.sha256 == sha256) {
+            continue;
+        }
+
+        io::copy(&mut fs::File::open(output)?, &mut combined)?;
+        if follow {
+            io::copy(&mut fs::File::open(output)?, &mut io::stdout().lock())?;
+        }
+        manifest.combined.push(Combined { source, sha256 });
+        appended += 1;
+    }
+    combined.flush()?;
+    drop(combined);
+    if follow {
+        io::stdout().flush()?;
+    }
+
+    if header {
+        write_header(&path, &folder, &manifest)?;
+    }
+
+    manifest.save(dir)?;
+    output::success(format!("Appended {} new log{} to {}", appended, if appended == 1 { "" } else { "s" }, path.display()));
+
+    Ok(path)
+}
+
+/// First and last line of the header of a combined log.
+const HEADER_START: &str = "# getlogs combined log";
+const HEADER_END: &str = "# ---";
+
+/// Puts a header with the issue details and sources of `manifest` in front of the combined log
+/// at `path`, replacing the header of an earlier run.
+fn write_header(path: &Path, folder: &str, manifest: &Manifest) -> anyhow::Result<()> {
+    let details = manifest.details.clone().unwrap_or_default();
+    let mut header = vec![HEADER_START.to_string()];
+    header.push(format!("# Issue: {}", if details.key.is_empty() { folder } else { &details.key }));
+    for (name, value) in [("Summary", &details.summary), ("Status", &details.status), ("Assignee", &details.assignee), ("Fetched", &details.fetched)] {
+        if let Some(value) = value {
+            header.push(format!("# {}: {}", name, value));
+        }
+    }
+    header.push("# Sources:".to_string());
+    header.extend(manifest.combined.iter().map(|c| format!("#   {}", c.source)));
+    header.push(HEADER_END.to_string());
+
+    let tmp_path = PathBuf::from(format!("{}.writing", path.display()));
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut out = BufWriter::new(fs::File::create(&tmp_path)?);
+    for line in &header {
+        writeln!(out, "{}", line)?;
+    }
+
+    let mut first = Vec::new();
+    reader.read_until(b'\n', &mut first)?;
+    if first.trim_ascii_end() == HEADER_START.as_bytes() {
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line)? > 0 && line.trim_ascii_end() != HEADER_END.as_bytes() {
+            line.clear();
+        }
+    } else {
+        out.write_all(&first)?;
+    }
+    io::copy(&mut reader, &mut out)?;
+    out.flush()?;
+    drop(out);
+
+    fs::rename(&tmp_path, path).with_context(|| format!("Replacing {}", path.display()))?;
+
+    Ok(())
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().map(|e| e.eq_ignore_ascii_case(extension)).unwrap_or(false)
+}
+
+fn starts_with(path: &Path, magic: &[u8]) -> bool {
+    let mut head = vec![0u8; magic.len()];
+
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut head))
+        .map(|_| head == magic)
+        .unwrap_or(false)
+}
+
+/// Converts DLT traces into logcat style text, one line per message.
+pub struct DltConverter;
+
+impl Converter for DltConverter {
+    fn name(&self) -> &'static str {
+        "dlt"
+    }
+
+    fn can_handle(&self, path: &Path) -> bool {
+        has_extension(path, "dlt") || starts_with(path, dlt::STORAGE_MAGIC)
+    }
+
+    fn output_path(&self, input: &Path, options: &ConvertOptions) -> PathBuf {
+        match options.format {
+            Format::Logcat => match options.split_by {
+                Some(split) => input.with_extension(format!("{{{}}}.logcat", split.placeholder())),
+                None => input.with_extension("logcat"),
+            },
+            Format::Perfetto => input.with_extension("perfetto.json"),
+            Format::Pcap => input.with_extension("{bus}.pcap"),
+        }
+    }
+
+    fn convert(&self, input: &Path, output: &Path, options: &ConvertOptions) -> anyhow::Result<()> {
+        let reader = dlt::Reader::new(fs::File::open(input)?)
+            .filter(|message| !matches!(message, Ok(message) if !options.ecus.is_empty() && !options.ecus.contains(&message.ecu)));
+
+        match options.format {
+            Format::Logcat => {
+                let lines = reader.map(|message| {
+                    let message = message?;
+                    let decoded = Decoded::new(&message, options.fibex.as_ref());
+                    let line = logcat_line(&message, &decoded);
+
+                    Ok(match options.split_by {
+                        Some(SplitBy::AppId) => (decoded.app_id, line),
+                        Some(SplitBy::ContextId) => (decoded.context_id, line),
+                        None => (String::new(), line),
+                    })
+                });
+
+                match options.split_by {
+                    Some(split) => write_split(lines, output, split, options),
+                    None => write_lines(lines.map(|line| Ok(line?.1)), output, options),
+                }
+            }
+            Format::Perfetto => {
+                let mut trace = TraceWriter::new(BufWriter::new(fs::File::create(output)?))?;
+                let messages = reader.map(|message| Ok(message?));
+
+                for_each_limited(messages, options, |message| {
+                    let decoded = Decoded::new(&message, options.fibex.as_ref());
+                    let micros = message.seconds as u64 * 1_000_000 + message.microseconds as u64;
+                    let process = format!("{} {}", message.ecu, decoded.app_id);
+
+                    let text = redacted(&decoded.text, options);
+                    Ok(trace.instant(micros, &process, &decoded.context_id, level_name(decoded.level), &text)?)
+                })?;
+
+                trace.finish()?;
+                Ok(())
+            }
+            Format::Pcap => write_pcap(reader.map(|message| Ok(message?)), input, output, options),
+        }
+    }
+}
+
+/// Writes `lines` to `output`, limited to the head or tail requested in `options`.
+fn write_lines(lines: impl Iterator<Item = anyhow::Result<String>>, output: &Path, options: &ConvertOptions) -> anyhow::Result<()> {
+    let mut out = TextWriter::create(output, options.encoding)?;
+    for_each_limited(lines, options, |line| Ok(out.line(&redacted(&line, options))?))?;
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Writes each of the `(id, line)` pairs of `lines` to `output` with the placeholder of `split`
+/// replaced by the ID, limited to the head or tail requested in `options`.
+fn write_split(lines: impl Iterator<Item = anyhow::Result<(String, String)>>, output: &Path, split: SplitBy, options: &ConvertOptions) -> anyhow::Result<()> {
+    let mut outputs: HashMap<String, TextWriter> = HashMap::new();
+    let mut created = Vec::new();
+
+    let written = for_each_limited(lines, options, |(id, line)| {
+        let out = match outputs.entry(id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let id = if entry.key().is_empty() { "none".to_string() } else { Sanitizer::Portable.sanitize(entry.key()) };
+                let path = fill_placeholder(output, split.placeholder(), &id);
+                let out = TextWriter::create(&path, options.encoding)?;
+                created.push(path);
+                entry.insert(out)
+            }
+        };
+
+        Ok(out.line(&redacted(&line, options))?)
+    })
+    .and_then(|()| outputs.values_mut().try_for_each(|out| Ok(out.flush()?)));
+
+    if written.is_err() {
+        drop(outputs);
+        remove_outputs(&created);
+    }
+
+    written
+}
+
+/// Bus of the frames in a network trace message.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Bus {
+    Can,
+    Ethernet,
+}
+
+impl Bus {
+    fn name(self) -> &'static str {
+        match self {
+            Bus::Can => "can",
+            Bus::Ethernet => "ethernet",
+        }
+    }
+
+    fn link_type(self) -> u32 {
+        match self {
+            Bus::Can => pcap::LINKTYPE_CAN_SOCKETCAN,
+            Bus::Ethernet => pcap::LINKTYPE_ETHERNET,
+        }
+    }
+}
+
+/// Writes the frames of the CAN and Ethernet network trace messages in `messages` to `output`
+/// with `{bus}` replaced by `can` and `ethernet`, limited to the head or tail of frames requested
+/// in `options`. Other messages are skipped.
+fn write_pcap(messages: impl Iterator<Item = anyhow::Result<dlt::Message>>, input: &Path, output: &Path, options: &ConvertOptions) -> anyhow::Result<()> {
+    let mut outputs: HashMap<Bus, PcapWriter<BufWriter<fs::File>>> = HashMap::new();
+    let mut created = Vec::new();
+    let mut unsupported = 0;
+
+    let frames = messages.filter_map(|message| match message {
+        Ok(message) => {
+            let dlt::MessageType::NetworkTrace(mtin) = message.message_type else {
+                return None;
+            };
+            let frame = network_frame(&message, mtin);
+            if frame.is_none() {
+                unsupported += 1;
+            }
+            frame.map(|(bus, data)| Ok((bus, message.seconds, message.microseconds, data)))
+        }
+        Err(err) => Some(Err(err)),
+    });
+
+    let written = for_each_limited(frames, options, |(bus, seconds, microseconds, data)| {
+        let out = match outputs.entry(bus) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = fill_placeholder(output, "bus", bus.name());
+                let file = BufWriter::new(fs::File::create(&path)?);
+                created.push(path);
+                entry.insert(PcapWriter::new(file, bus.link_type())?)
+            }
+        };
+
+        Ok(out.packet(seconds, microseconds, &data)?)
+    });
+    if let Err(err) = written {
+        drop(outputs);
+        remove_outputs(&created);
+        return Err(err);
+    }
+
+    if unsupported > 0 {
+        output::warning(format!("Skipped {} network trace messages of {} that are not CAN or Ethernet frames", unsupported, input.display()));
+    }
+    if outputs.is_empty() {
+        output::warning(format!("{} contains no CAN or Ethernet trace messages", input.display()));
+    }
+
+    let finished = outputs.into_values().try_for_each(|out| -> anyhow::Result<()> {
+        out.finish()?;
+        Ok(())
+    });
+    if finished.is_err() {
+        remove_outputs(&created);
+    }
+
+    finished
+}
+
+/// The bus and frame of a verbose network trace message, whose arguments are the raw network
+/// header and payload. The header of a CAN frame is its ID, the one of an Ethernet frame the MAC
+/// header. `None` for other types and messages that do not have this layout.
+fn network_frame(message: &dlt::Message, mtin: u8) -> Option<(Bus, Vec<u8>)> {
+    let arguments = message.arguments().ok()?;
+    let [dlt::Argument::Raw(header), dlt::Argument::Raw(payload)] = arguments.as_slice() else {
+        return None;
+    };
+
+    match mtin {
+        dlt::NW_TRACE_CAN if (1..=4).contains(&header.len()) => {
+            let mut id = [0; 4];
+            if message.big_endian {
+                id[4 - header.len()..].copy_from_slice(header);
+                Some((Bus::Can, pcap::socketcan_frame(u32::from_be_bytes(id), payload)))
+            } else {
+                id[..header.len()].copy_from_slice(header);
+                Some((Bus::Can, pcap::socketcan_frame(u32::from_le_bytes(id), payload)))
+            }
+        }
+        dlt::NW_TRACE_ETHERNET => Some((Bus::Ethernet, [header.as_slice(), payload].concat())),
+        _ => None,
+    }
+}
+
+/// A converted text log being written in the requested encoding.
+struct TextWriter {
+    out: BufWriter<fs::File>,
+    encoding: OutputEncoding,
+}
+
+impl TextWriter {
+    fn create(path: &Path, encoding: OutputEncoding) -> io::Result<Self> {
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        match encoding {
+            OutputEncoding::Utf8 => {}
+            OutputEncoding::Utf16le => out.write_all(&[0xFF, 0xFE])?,
+            OutputEncoding::Utf16be => out.write_all(&[0xFE, 0xFF])?,
+        }
+
+        Ok(TextWriter { out, encoding })
+    }
+
+    /// Writes `line` and a line feed.
+    fn line(&mut self, line: &str) -> io::Result<()> {
+        match self.encoding {
+            OutputEncoding::Utf8 => writeln!(self.out, "{}", line),
+            OutputEncoding::Utf16le => line.encode_utf16().chain([u16::from(b'\n')]).try_for_each(|unit| self.out.write_all(&unit.to_le_bytes())),
+            OutputEncoding::Utf16be => line.encode_utf16().chain([u16::from(b'\n')]).try_for_each(|unit| self.out.write_all(&unit.to_be_bytes())),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn redacted<'a>(line: &'a str, options: &ConvertOptions) -> Cow<'a, str> {
+    match &options.redactor {
+        Some(redactor) => redactor.redact(line),
+        None => Cow::Borrowed(line),
+    }
+}
+
+/// Passes the items of `items` to `f`, only the first or last ones if `options` asks for a head
+/// or tail.
+fn for_each_limited<T>(
+    items: impl Iterator<Item = anyhow::Result<T>>,
+    options: &ConvertOptions,
+    mut f: impl FnMut(T) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut tail = VecDeque::with_capacity(options.tail.unwrap_or(0));
+
+    for item in items.take(options.head.unwrap_or(usize::MAX)) {
+        let item = item?;
+
+        match options.tail {
+            Some(0) => {}
+            Some(n) => {
+                if tail.len() == n {
+                    tail.pop_front();
+                }
+                tail.push_back(item);
+            }
+            None => f(item)?,
+        }
+    }
+
+    tail.into_iter().try_for_each(f)
+}
+
+/// Application and context ID, level and text of a message. Non-verbose messages described in
+/// the FIBEX are decoded, taking IDs and level from the description where the message has none.
+struct Decoded {
+    app_id: String,
+    context_id: String,
+    level: Option<dlt::LogLevel>,
+    text: String,
+}
+
+impl Decoded {
+    fn new(message: &dlt::Message, fibex: Option<&Fibex>) -> Self {
+        let frame = message.non_verbose().and_then(|(id, data)| Some((fibex?.frame(id)?, data)));
+        let or_frame = |value: &str, from_frame: Option<&String>| match from_frame {
+            Some(id) if value.is_empty() => id.clone(),
+            _ => value.to_string(),
+        };
+
+        match frame {
+            Some((frame, data)) => Decoded {
+                app_id: or_frame(&message.app_id, frame.app_id.as_ref()),
+                context_id: or_frame(&message.context_id, frame.context_id.as_ref()),
+                level: message.level().or(frame.level),
+                text: frame.decode(data, message.big_endian),
+            },
+            None => Decoded {
+                app_id: message.app_id.clone(),
+                context_id: message.context_id.clone(),
+                level: message.level(),
+                text: message.text(),
+            },
+        }
+    }
+}
+
+/// Trace event category for a message level.
+fn level_name(level: Option<dlt::LogLevel>) -> &'static str {
+    match level {
+        Some(dlt::LogLevel::Fatal) => "fatal",
+        Some(dlt::LogLevel::Error) => "error",
+        Some(dlt::LogLevel::Warn) => "warn",
+        Some(dlt::LogLevel::Info) => "info",
+        Some(dlt::LogLevel::Debug) => "debug",
+        Some(dlt::LogLevel::Verbose) => "verbose",
+        None => "none",
+    }
+}
+
+/// Formats a message like logcat's `threadtime` format, with ECU, application and context ID in
+/// place of process and thread ID.
+fn logcat_line(message: &dlt::Message, decoded: &Decoded) -> String {
+    let time = time::OffsetDateTime::from_unix_timestamp(message.seconds as i64)
+        .unwrap_or(time::OffsetDateTime::UNIX_EPOCH);
+
+    format!(
+        "{:02}-{:02} {:02}:{:02}:{:02}.{:03} {:<4} {:<4} {:<4} {} {}",
+        time.month() as u8, time.day(), time.hour(), time.minute(), time.second(), message.microseconds / 1000,
+        message.ecu, decoded.app_id, decoded.context_id, decoded.level.map(|l| l.letter()).unwrap_or('-'), decoded.text
+    )
+}
+
+/// Converts Windows event logs into text, one line per event with time, level, provider, event
+/// ID and the event data.
+pub struct EvtxConverter;
+
+const EVTX_MAGIC: &[u8] = b"ElfFile\0";
+
+impl Converter for EvtxConverter {
+    fn name(&self) -> &'static str {
+        "evtx"
+    }
+
+    fn can_handle(&self, path: &Path) -> bool {
+        has_extension(path, "evtx") || starts_with(path, EVTX_MAGIC)
+    }
+
+    fn output_path(&self, input: &Path, _options: &ConvertOptions) -> PathBuf {
+        // Keeps `.evtx` in the name, an extracted `System.txt` next to `System.evtx` is not overwritten
+        input.with_extension("evtx.txt")
+    }
+
+    fn convert(&self, input: &Path, output: &Path, options: &ConvertOptions) -> anyhow::Result<()> {
+        let mut parser = evtx::EvtxParser::from_path(input)?;
+        let lines = parser.records_json_value().map(|record| {
+            let record = record?;
+            Ok(format!("{} {}", record.timestamp, event_line(&record.data["Event"])))
+        });
+
+        write_lines(lines, output, options)
+    }
+}
+
+/// Formats the level, provider, event ID and `EventData` values of an event. Values shaped
+/// `{"#attributes": ..., "#text": ...}` are reduced to their text.
+fn event_line(event: &Value) -> String {
+    let system = &event["System"];
+    let text = |value: &Value| match value.get("#text").unwrap_or(value) {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+
+    let level = match system["Level"].as_u64() {
+        Some(1) => 'F',
+        Some(2) => 'E',
+        Some(3) => 'W',
+        Some(5) => 'V',
+        _ => 'I',
+    };
+
+    let data: Vec<String> = match &event["EventData"] {
+        Value::Object(fields) => fields.iter()
+            .filter(|(key, _)| *key != "#attributes")
+            .map(|(key, value)| format!("{}={}", key, text(value)))
+            .collect(),
+        Value::Null => Vec::new(),
+        other => vec![text(other)],
+    };
+
+    format!(
+        "{} {} {}: {}",
+        level, system["Provider"]["#attributes"]["Name"].as_str().unwrap_or("-"), text(&system["EventID"]), data.join(" ")
+    )
+}
@@ -0,0 +1,210 @@
+//! Detection of repeated content in extracted logs. Rotated captures often overlap: identical
+//! files are removed, long line ranges found in another file are reported.
+
+use std::{collections::{HashMap, hash_map::DefaultHasher}, fs, hash::{Hash, Hasher}, io::{self, BufRead, BufReader, Read}, path::{Path, PathBuf}};
+use crate::{interrupt, manifest};
+
+/// Overlaps shorter than this many lines are not reported.
+pub const MIN_OVERLAP_LINES: usize = 100;
+
+/// A chunk ends after a line whose hash is divisible by this, so chunks average this many lines
+/// and the same content is cut the same way wherever it starts.
+const CHUNK_DIVISOR: u64 = 32;
+const MAX_CHUNK_LINES: usize = 256;
+
+/// What [`dedupe_logs`] found.
+#[derive(Debug, Default)]
+pub struct DedupeReport {
+    /// Removed files, or with `dry_run` the files that would be removed, with the kept file they
+    /// were identical to.
+    pub removed: Vec<(String, String)>,
+    pub overlaps: Vec<Overlap>,
+}
+
+/// Lines `first_line..=last_line` of `file` that also appear in `other`, starting at
+/// `other_line`. Line numbers start at 1.
+#[derive(Debug)]
+pub struct Overlap {
+    pub file: String,
+    pub first_line: usize,
+    pub last_line: usize,
+    pub other: String,
+    pub other_line: usize,
+}
+
+/// Lines of a file cut at content-defined boundaries.
+struct Chunk {
+    hash: u64,
+    first_line: usize,
+    lines: usize,
+}
+
+/// Removes files in `dir` that are byte-identical to another one, keeping the first by name, and
+/// finds line ranges of at least [`MIN_OVERLAP_LINES`] that the remaining text files share. With
+/// `dry_run` the identical files are only reported.
+pub fn dedupe_logs(dir: &Path, dry_run: bool) -> anyhow::Result<DedupeReport> {
+    if !dir.is_dir() {
+        anyhow::bail!("Cannot read {}, extract the logs first", dir.display());
+    }
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<Result<_, _>>()?;
+    paths.retain(|p| p.is_file() && !manifest::is_internal_file(&file_name(p)));
+    paths.sort();
+
+    let mut report = DedupeReport::default();
+    let mut kept: Vec<PathBuf> = Vec::new();
+    let mut by_hash: HashMap<(u64, String), String> = HashMap::new();
+
+    for path in paths {
+        interrupt::check()?;
+
+        let key = (fs::metadata(&path)?.len(), manifest::hash_file(&path)?);
+        match by_hash.get(&key) {
+            Some(original) => {
+                if !dry_run {
+                    fs::remove_file(&path)?;
+                }
+                report.removed.push((file_name(&path), original.clone()));
+            }
+            None => {
+                by_hash.insert(key, file_name(&path));
+                kept.push(path);
+            }
+        }
+    }
+
+    // Chunk hash to the file and line it was first seen at
+    let mut seen: HashMap<u64, (usize, usize)> = HashMap::new();
+
+    for (index, path) in kept.iter().enumerate() {
+        interrupt::check()?;
+
+        let Some(chunks) = chunks(path)? else {
+            continue;
+        };
+
+        // The overlap being extended, the file it is found in and the line its next chunk starts at there
+        let mut run: Option<(Overlap, usize, usize)> = None;
+        for chunk in &chunks {
+            let earlier = seen.get(&chunk.hash).copied().filter(|&(file, _)| file != index);
+            let last_line = chunk.first_line + chunk.lines - 1;
+
+            match (&mut run, earlier) {
+                (Some((overlap, other, next)), Some((file, line))) if *other == file && *next == line => {
+                    overlap.last_line = last_line;
+                    *next = line + chunk.lines;
+                }
+                _ => {
+                    report.overlaps.extend(run.take().map(|(overlap, _, _)| overlap).filter(is_long));
+                    run = earlier.map(|(file, line)| {
+                        let overlap = Overlap {
+                            file: file_name(path),
+                            first_line: chunk.first_line,
+                            last_line,
+                            other: file_name(&kept[file]),
+                            other_line: line,
+                        };
+                        (overlap, file, line + chunk.lines)
+                    });
+                }
+            }
+        }
+        report.overlaps.extend(run.map(|(overlap, _, _)| overlap).filter(is_long));
+
+        for chunk in chunks {
+            seen.entry(chunk.hash).or_insert((index, chunk.first_line));
+        }
+    }
+
+    Ok(report)
+}
+
+/// The chunks of the text file at `path`, `None` for binary files.
+fn chunks(path: &Path) -> io::Result<Option<Vec<Chunk>>> {
+    let mut head = Vec::new();
+    fs::File::open(path)?.take(8192).read_to_end(&mut head)?;
+    if head.contains(&0) {
+        return Ok(None);
+    }
+
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut chunks = Vec::new();
+    let mut line = Vec::new();
+    let mut line_number = 0;
+    let mut chunk_hasher = DefaultHasher::new();
+    let mut chunk_start = 1;
+
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        line_number += 1;
+
+        let mut line_hasher = DefaultHasher::new();
+        line.hash(&mut line_hasher);
+        let line_hash = line_hasher.finish();
+        line_hash.hash(&mut chunk_hasher);
+        line.clear();
+
+        let lines = line_number - chunk_start + 1;
+        if line_hash.is_multiple_of(CHUNK_DIVISOR) || lines == MAX_CHUNK_LINES {
+            chunks.push(Chunk { hash: chunk_hasher.finish(), first_line: chunk_start, lines });
+            chunk_hasher = DefaultHasher::new();
+            chunk_start = line_number + 1;
+        }
+    }
+
+    if chunk_start <= line_number {
+        chunks.push(Chunk { hash: chunk_hasher.finish(), first_line: chunk_start, lines: line_number - chunk_start + 1 });
+    }
+
+    Ok(Some(chunks))
+}
+
+fn is_long(overlap: &Overlap) -> bool {
+    overlap.last_line - overlap.first_line + 1 >= MIN_OVERLAP_LINES
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(range: std::ops::Range<usize>) -> String {
+        range.map(|i| format!("12:00:{:05} boot step {}\n", i, i)).collect()
+    }
+
+    #[test]
+    fn removes_identical_files_keeping_the_first_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("b.log"), "same\n").unwrap();
+        fs::write(dir.path().join("a.log"), "same\n").unwrap();
+        fs::write(dir.path().join("c.log"), "other\n").unwrap();
+
+        let report = dedupe_logs(dir.path(), true).unwrap();
+        assert_eq!(report.removed, [("b.log".to_string(), "a.log".to_string())]);
+        assert!(dir.path().join("b.log").is_file());
+
+        let report = dedupe_logs(dir.path(), false).unwrap();
+        assert_eq!(report.removed, [("b.log".to_string(), "a.log".to_string())]);
+        assert!(!dir.path().join("b.log").exists());
+        assert!(dir.path().join("a.log").is_file() && dir.path().join("c.log").is_file());
+    }
+
+    #[test]
+    fn reports_long_shared_line_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.log"), lines(0..600)).unwrap();
+        fs::write(dir.path().join("b.log"), format!("{}{}", lines(1000..1050), lines(200..600))).unwrap();
+        fs::write(dir.path().join("c.log"), lines(2000..2090) + &lines(100..150)).unwrap();
+
+        let report = dedupe_logs(dir.path(), false).unwrap();
+        assert!(report.removed.is_empty());
+        assert_eq!(report.overlaps.len(), 1, "{:?}", report.overlaps);
+        let overlap = &report.overlaps[0];
+        assert_eq!((overlap.file.as_str(), overlap.other.as_str()), ("b.log", "a.log"));
+        // Line 51 of b.log is line 201 of a.log
+        assert_eq!(overlap.other_line, overlap.first_line + 150);
+        assert!(overlap.first_line < 51 + 64 && overlap.last_line > 450 - 64, "{:?}", overlap);
+    }
+}
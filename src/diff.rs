@@ -0,0 +1,73 @@
+//! Comparison of the files of two issues, for regression triage between a good and a bad ticket.
+
+use std::{collections::BTreeSet, fs, path::Path, time::Duration};
+use similar::TextDiff;
+use crate::manifest;
+
+/// Longest time spent on diffing one pair of files before settling for a coarser diff.
+const DIFF_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// File names of two directories, matched by name.
+#[derive(Debug, Default)]
+pub struct Comparison {
+    pub only_left: Vec<String>,
+    pub only_right: Vec<String>,
+    pub common: Vec<String>,
+}
+
+/// Matches the files of `left` and `right` by name, ignoring getlogs' own files. A missing
+/// directory counts as empty.
+pub fn compare_dirs(left: &Path, right: &Path) -> anyhow::Result<Comparison> {
+    let left_names = file_names(left)?;
+    let right_names = file_names(right)?;
+
+    Ok(Comparison {
+        only_left: left_names.difference(&right_names).cloned().collect(),
+        only_right: right_names.difference(&left_names).cloned().collect(),
+        common: left_names.intersection(&right_names).cloned().collect(),
+    })
+}
+
+/// Unified line diff of two text files, labelled with `left_label` and `right_label`. `None` if
+/// they are identical; binary files are only reported as differing.
+pub fn diff_files(left: &Path, right: &Path, left_label: &str, right_label: &str) -> anyhow::Result<Option<String>> {
+    let left_data = fs::read(left)?;
+    let right_data = fs::read(right)?;
+
+    if left_data == right_data {
+        return Ok(None);
+    }
+
+    if is_binary(&left_data) || is_binary(&right_data) {
+        return Ok(Some(format!("Binary files {} and {} differ\n", left_label, right_label)));
+    }
+
+    let left_text = String::from_utf8_lossy(&left_data);
+    let right_text = String::from_utf8_lossy(&right_data);
+    let diff = TextDiff::configure().timeout(DIFF_TIMEOUT).diff_lines(&*left_text, &*right_text);
+
+    Ok(Some(diff.unified_diff().header(left_label, right_label).to_string()))
+}
+
+fn file_names(dir: &Path) -> anyhow::Result<BTreeSet<String>> {
+    if !dir.is_dir() {
+        return Ok(BTreeSet::new());
+    }
+
+    let mut names = BTreeSet::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        if entry.path().is_file() && !manifest::is_internal_file(&name) {
+            names.insert(name);
+        }
+    }
+
+    Ok(names)
+}
+
+/// Text files have no NUL bytes near the start.
+fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8192).any(|&b| b == 0)
+}
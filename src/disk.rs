@@ -0,0 +1,133 @@
+//! Checks of the file system downloads and extracted files are written to, so a full or
+//! read-only volume stops the run with a message saying so rather than an I/O error in the
+//! middle of a download.
+
+use std::{fs, io, path::Path};
+use anyhow::Context;
+use indicatif::HumanBytes;
+
+/// Free space below which no issue is started.
+pub const MIN_FREE_SPACE: u64 = 64 << 20;
+
+/// Probe written to check that a folder can be written to.
+const PROBE_FILE: &str = ".getlogs-write-test";
+
+/// The run was stopped because the destination is full or read-only.
+#[derive(Debug)]
+pub struct DiskError(String);
+
+impl std::fmt::Display for DiskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for DiskError {}
+
+/// Fails with [`DiskError`] if no file can be created in `dir` or it has less than
+/// [`MIN_FREE_SPACE`] free.
+pub fn check_destination(dir: &Path) -> anyhow::Result<()> {
+    let probe = dir.join(PROBE_FILE);
+    fs::write(&probe, b"").and_then(|()| fs::remove_file(&probe))
+        .with_context(|| format!("Cannot write to {}", dir.display()))
+        .map_err(|err| classify(err, dir))?;
+
+    match available_space(dir) {
+        Some(free) if free < MIN_FREE_SPACE => Err(DiskError(format!(
+            "Disk full: only {} are free in {}, issues need at least {}", HumanBytes(free), dir.display(), HumanBytes(MIN_FREE_SPACE)
+        )).into()),
+        _ => Ok(()),
+    }
+}
+
+/// Fails with [`DiskError`] if the file system of `dir` is known to have less than `needed`
+/// bytes free.
+pub fn check_space(dir: &Path, needed: u64) -> anyhow::Result<()> {
+    match available_space(dir) {
+        Some(free) if free < needed => Err(DiskError(format!(
+            "Disk full: the downloads need {} but only {} are free in {}", HumanBytes(needed), HumanBytes(free), dir.display()
+        )).into()),
+        _ => Ok(()),
+    }
+}
+
+/// `err` as a [`DiskError`] if it was caused by the file system of `dir` being full or
+/// read-only, otherwise unchanged.
+pub fn classify(err: anyhow::Error, dir: &Path) -> anyhow::Error {
+    if err.is::<DiskError>() {
+        return err;
+    }
+
+    let cause = err.chain().filter_map(|cause| cause.downcast_ref::<io::Error>()).find_map(|cause| match cause.kind() {
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => Some("Disk full"),
+        io::ErrorKind::ReadOnlyFilesystem => Some("Read-only file system"),
+        _ => None,
+    });
+    match cause {
+        Some(cause) => DiskError(format!("{}, cannot write to {}: {:#}", cause, dir.display(), err)).into(),
+        None => err,
+    }
+}
+
+/// Bytes available to unprivileged users on the file system of `dir`, if the platform says.
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classified(kind: io::ErrorKind) -> anyhow::Error {
+        let err = anyhow::Error::new(io::Error::from(kind)).context("Writing main.log").context("Extracting logs.zip");
+        classify(err, Path::new("/data"))
+    }
+
+    #[test]
+    fn finds_full_and_read_only_file_systems_behind_context() {
+        for (kind, message) in [
+            (io::ErrorKind::StorageFull, "Disk full"),
+            (io::ErrorKind::QuotaExceeded, "Disk full"),
+            (io::ErrorKind::ReadOnlyFilesystem, "Read-only file system"),
+        ] {
+            let err = classified(kind);
+            assert!(err.is::<DiskError>(), "{:?}", kind);
+            assert!(err.to_string().starts_with(&format!("{}, cannot write to /data: Extracting logs.zip: Writing main.log", message)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn leaves_other_errors_alone() {
+        let err = classified(io::ErrorKind::PermissionDenied);
+        assert!(!err.is::<DiskError>());
+        assert_eq!(err.to_string(), "Extracting logs.zip");
+
+        let err = classify(DiskError("Disk full".to_string()).into(), Path::new("/data"));
+        assert_eq!(err.to_string(), "Disk full");
+    }
+
+    #[test]
+    fn checks_a_writable_destination() {
+        let dir = tempfile::tempdir().unwrap();
+
+        check_destination(dir.path()).unwrap();
+        assert!(!dir.path().join(PROBE_FILE).exists());
+        check_space(dir.path(), 1).unwrap();
+        #[cfg(unix)]
+        assert!(check_space(dir.path(), u64::MAX).unwrap_err().is::<DiskError>());
+    }
+}
@@ -0,0 +1,560 @@
+//! Reader for DLT (Diagnostic Log and Trace) files as written by dlt-daemon and dlt-viewer.
+//!
+//! A file is a sequence of messages, each prefixed by a storage header holding the wall clock
+//! time it was received, followed by the standard header, an optional extended header and the
+//! payload.
+
+use std::io::{self, BufReader, ErrorKind, Read};
+
+/// Magic bytes starting every storage header.
+pub const STORAGE_MAGIC: &[u8; 4] = b"DLT\x01";
+
+const STORAGE_HEADER_LEN: usize = 16;
+const EXTENDED_HEADER_LEN: usize = 10;
+
+// Standard header type flags
+const HTYP_UEH: u8 = 0x01;
+const HTYP_MSBF: u8 = 0x02;
+const HTYP_WEID: u8 = 0x04;
+const HTYP_WSID: u8 = 0x08;
+const HTYP_WTMS: u8 = 0x10;
+
+// Verbose argument type info flags
+const TYPE_LENGTH_MASK: u32 = 0x0f;
+const TYPE_BOOL: u32 = 0x10;
+const TYPE_SINT: u32 = 0x20;
+const TYPE_UINT: u32 = 0x40;
+const TYPE_FLOA: u32 = 0x80;
+const TYPE_STRG: u32 = 0x200;
+const TYPE_RAWD: u32 = 0x400;
+const TYPE_VARI: u32 = 0x800;
+const TYPE_FIXP: u32 = 0x1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Fatal,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Verbose,
+}
+
+impl LogLevel {
+    /// Single letter used by logcat for this level.
+    pub fn letter(self) -> char {
+        match self {
+            LogLevel::Fatal => 'F',
+            LogLevel::Error => 'E',
+            LogLevel::Warn => 'W',
+            LogLevel::Info => 'I',
+            LogLevel::Debug => 'D',
+            LogLevel::Verbose => 'V',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Log(Option<LogLevel>),
+    AppTrace(u8),
+    NetworkTrace(u8),
+    Control(u8),
+    Unknown,
+}
+
+/// Network trace message types (MTIN) of CAN and Ethernet frames.
+pub const NW_TRACE_CAN: u8 = 2;
+pub const NW_TRACE_ETHERNET: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Bool(bool),
+    Signed(i64),
+    Unsigned(u64),
+    Float(f64),
+    String(String),
+    Raw(Vec<u8>),
+}
+
+impl std::fmt::Display for Argument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Argument::Bool(value) => write!(f, "{}", value),
+            Argument::Signed(value) => write!(f, "{}", value),
+            Argument::Unsigned(value) => write!(f, "{}", value),
+            Argument::Float(value) => write!(f, "{}", value),
+            Argument::String(value) => f.write_str(value),
+            Argument::Raw(bytes) => f.write_str(&hex(bytes)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Seconds since the epoch from the storage header.
+    pub seconds: u32,
+    pub microseconds: u32,
+    pub ecu: String,
+    pub app_id: String,
+    pub context_id: String,
+    pub message_type: MessageType,
+    pub verbose: bool,
+    pub big_endian: bool,
+    pub argument_count: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    pub fn level(&self) -> Option<LogLevel> {
+        match self.message_type {
+            MessageType::Log(level) => level,
+            _ => None,
+        }
+    }
+
+    /// Decodes the payload arguments of a verbose message.
+    pub fn arguments(&self) -> io::Result<Vec<Argument>> {
+        let mut cursor = Cursor::new(&self.payload, self.big_endian);
+        let mut arguments = Vec::with_capacity(self.argument_count as usize);
+
+        for _ in 0..self.argument_count {
+            arguments.push(cursor.argument()?);
+        }
+
+        Ok(arguments)
+    }
+
+    /// Message ID and remaining payload of a non-verbose message.
+    pub fn non_verbose(&self) -> Option<(u32, &[u8])> {
+        if self.verbose || self.payload.len() < 4 {
+            return None;
+        }
+
+        let id: [u8; 4] = self.payload[..4].try_into().unwrap();
+        let id = if self.big_endian { u32::from_be_bytes(id) } else { u32::from_le_bytes(id) };
+
+        Some((id, &self.payload[4..]))
+    }
+
+    /// Human readable payload: verbose arguments joined by spaces, or the message ID followed by
+    /// the hex encoded arguments for non-verbose messages.
+    pub fn text(&self) -> String {
+        if let Some((id, data)) = self.non_verbose() {
+            return format!("[{}] {}", id, hex(data));
+        }
+
+        match self.arguments() {
+            Ok(arguments) => arguments.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(" "),
+            Err(_) => hex(&self.payload),
+        }
+    }
+}
+
+/// Iterates over the messages of a DLT file.
+pub struct Reader<R: Read> {
+    inner: BufReader<R>,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Reader { inner: BufReader::new(inner) }
+    }
+
+    fn read_message(&mut self) -> io::Result<Option<Message>> {
+        let mut storage = [0u8; STORAGE_HEADER_LEN];
+        if !self.read_storage_header(&mut storage)? {
+            return Ok(None);
+        }
+
+        let mut standard = [0u8; 4];
+        self.inner.read_exact(&mut standard)?;
+
+        let htyp = standard[0];
+        let len = u16::from_be_bytes([standard[2], standard[3]]) as usize;
+        if len < standard.len() {
+            return Err(io::Error::new(ErrorKind::InvalidData, "DLT message shorter than its header"));
+        }
+
+        let mut rest = vec![0u8; len - standard.len()];
+        self.inner.read_exact(&mut rest)?;
+
+        let mut cursor = Cursor { data: &rest, pos: 0, big_endian: true };
+        let mut ecu = id_string(&storage[12..16]);
+
+        if htyp & HTYP_WEID != 0 {
+            ecu = id_string(cursor.take(4)?);
+        }
+
+        // Session ID and ECU uptime are not needed, the storage header carries the wall clock
+        if htyp & HTYP_WSID != 0 {
+            cursor.take(4)?;
+        }
+
+        if htyp & HTYP_WTMS != 0 {
+            cursor.take(4)?;
+        }
+
+        let (mut message_type, mut verbose, mut argument_count) = (MessageType::Unknown, false, 0);
+        let (mut app_id, mut context_id) = (String::new(), String::new());
+
+        if htyp & HTYP_UEH != 0 {
+            let extended = cursor.take(EXTENDED_HEADER_LEN)?;
+            let msin = extended[0];
+
+            verbose = msin & 0x01 != 0;
+            message_type = message_type_of(msin);
+            argument_count = extended[1];
+            app_id = id_string(&extended[2..6]);
+            context_id = id_string(&extended[6..10]);
+        }
+
+        Ok(Some(Message {
+            seconds: u32::from_le_bytes(storage[4..8].try_into().unwrap()),
+            microseconds: u32::from_le_bytes(storage[8..12].try_into().unwrap()),
+            ecu,
+            app_id,
+            context_id,
+            message_type,
+            verbose,
+            big_endian: htyp & HTYP_MSBF != 0,
+            argument_count,
+            payload: rest[cursor.pos..].to_vec(),
+        }))
+    }
+
+    /// Reads the next storage header, skipping garbage until the magic bytes are found.
+    /// Returns false at the end of the input.
+    fn read_storage_header(&mut self, storage: &mut [u8; STORAGE_HEADER_LEN]) -> io::Result<bool> {
+        let mut matched = 0;
+
+        while matched < STORAGE_MAGIC.len() {
+            let mut byte = [0u8];
+            if self.inner.read(&mut byte)? == 0 {
+                return Ok(false);
+            }
+
+            if byte[0] == STORAGE_MAGIC[matched] {
+                matched += 1;
+            } else {
+                matched = usize::from(byte[0] == STORAGE_MAGIC[0]);
+            }
+        }
+
+        storage[..4].copy_from_slice(STORAGE_MAGIC);
+        self.inner.read_exact(&mut storage[4..])?;
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_message() {
+            Ok(message) => message.map(Ok),
+            // A truncated last message is common for captures that were cut off
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+fn message_type_of(msin: u8) -> MessageType {
+    let mtin = msin >> 4;
+
+    match (msin >> 1) & 0x07 {
+        0 => MessageType::Log(match mtin {
+            1 => Some(LogLevel::Fatal),
+            2 => Some(LogLevel::Error),
+            3 => Some(LogLevel::Warn),
+            4 => Some(LogLevel::Info),
+            5 => Some(LogLevel::Debug),
+            6 => Some(LogLevel::Verbose),
+            _ => None,
+        }),
+        1 => MessageType::AppTrace(mtin),
+        2 => MessageType::NetworkTrace(mtin),
+        3 => MessageType::Control(mtin),
+        _ => MessageType::Unknown,
+    }
+}
+
+fn id_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string()
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Reads payload values in the byte order of a message.
+pub(crate) struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Cursor<'a> {
+    pub(crate) fn new(data: &'a [u8], big_endian: bool) -> Self {
+        Cursor { data, pos: 0, big_endian }
+    }
+
+    /// The bytes not read yet.
+    pub(crate) fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.pos + len > self.data.len() {
+            return Err(io::Error::new(ErrorKind::InvalidData, "DLT payload truncated"));
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn uint(&mut self, len: usize) -> io::Result<u64> {
+        let bytes = self.take(len)?;
+        let fold = |acc: u64, b: &u8| (acc << 8) | *b as u64;
+
+        Ok(if self.big_endian { bytes.iter().fold(0, fold) } else { bytes.iter().rev().fold(0, fold) })
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(self.uint(2)? as u16)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(self.uint(4)? as u32)
+    }
+
+    fn argument(&mut self) -> io::Result<Argument> {
+        let type_info = self.u32()?;
+        let len = match type_info & TYPE_LENGTH_MASK {
+            1 => 1,
+            2 => 2,
+            3 => 4,
+            4 => 8,
+            5 => 16,
+            _ => 0,
+        };
+
+        if type_info & (TYPE_STRG | TYPE_RAWD) != 0 {
+            let data_len = self.u16()? as usize;
+            if type_info & TYPE_VARI != 0 {
+                let name_len = self.u16()? as usize;
+                self.take(name_len)?;
+            }
+
+            let data = self.take(data_len)?;
+            if type_info & TYPE_RAWD != 0 {
+                return Ok(Argument::Raw(data.to_vec()));
+            }
+
+            return Ok(Argument::String(String::from_utf8_lossy(data).trim_end_matches('\0').to_string()));
+        }
+
+        if type_info & (TYPE_BOOL | TYPE_SINT | TYPE_UINT | TYPE_FLOA) == 0 {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("unsupported DLT argument type {:#x}", type_info)));
+        }
+
+        if type_info & TYPE_VARI != 0 {
+            let name_len = self.u16()? as usize;
+            let unit_len = if type_info & TYPE_BOOL == 0 { self.u16()? as usize } else { 0 };
+            self.take(name_len + unit_len)?;
+        }
+
+        if type_info & TYPE_FIXP != 0 {
+            // Quantization factor and offset are not applied, skip them
+            self.take(if len > 4 { 4 + len } else { 8 })?;
+        }
+
+        let len = len.max(1);
+        if type_info & TYPE_BOOL != 0 {
+            Ok(Argument::Bool(self.uint(len)? != 0))
+        } else if type_info & TYPE_FLOA != 0 {
+            match len {
+                4 => Ok(Argument::Float(f32::from_bits(self.uint(4)? as u32) as f64)),
+                8 => Ok(Argument::Float(f64::from_bits(self.uint(8)?))),
+                _ => Ok(Argument::Raw(self.take(len)?.to_vec())),
+            }
+        } else if len > 8 {
+            Ok(Argument::Raw(self.take(len)?.to_vec()))
+        } else if type_info & TYPE_SINT != 0 {
+            let shift = 64 - len * 8;
+            Ok(Argument::Signed(((self.uint(len)? << shift) as i64) >> shift))
+        } else {
+            Ok(Argument::Unsigned(self.uint(len)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stored message of ECU1 with the standard header flags `htyp` and `rest` after the
+    /// standard header.
+    fn stored(htyp: u8, rest: &[u8]) -> Vec<u8> {
+        let mut bytes = STORAGE_MAGIC.to_vec();
+        bytes.extend(1_700_000_000u32.to_le_bytes());
+        bytes.extend(250_000u32.to_le_bytes());
+        bytes.extend(b"ECU1");
+        bytes.extend([htyp, 0]);
+        bytes.extend((4 + rest.len() as u16).to_be_bytes());
+        bytes.extend(rest);
+        bytes
+    }
+
+    /// An extended header of a verbose info log message of APP1/CTX with `arguments`.
+    fn info(arguments: u8) -> Vec<u8> {
+        [&[0x41, arguments][..], b"APP1", b"CTX\0"].concat()
+    }
+
+    fn read(bytes: &[u8]) -> Vec<Message> {
+        Reader::new(bytes).collect::<io::Result<_>>().unwrap()
+    }
+
+    fn verbose(payload: Vec<u8>, argument_count: u8, big_endian: bool) -> Message {
+        Message {
+            seconds: 0,
+            microseconds: 0,
+            ecu: String::new(),
+            app_id: String::new(),
+            context_id: String::new(),
+            message_type: MessageType::Log(None),
+            verbose: true,
+            big_endian,
+            argument_count,
+            payload,
+        }
+    }
+
+    #[test]
+    fn reads_the_headers() {
+        let rest = [&b"ECU2"[..], &[0; 8], &info(0), b"payload"].concat();
+        let messages = read(&stored(HTYP_UEH | HTYP_WEID | HTYP_WSID | HTYP_WTMS, &rest));
+
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert_eq!((message.seconds, message.microseconds), (1_700_000_000, 250_000));
+        assert_eq!((message.ecu.as_str(), message.app_id.as_str(), message.context_id.as_str()), ("ECU2", "APP1", "CTX"));
+        assert_eq!(message.level(), Some(LogLevel::Info));
+        assert!(message.verbose && !message.big_endian);
+        assert_eq!(message.payload, b"payload");
+
+        let plain = read(&stored(0, b"xyz"));
+        assert_eq!((plain[0].ecu.as_str(), plain[0].app_id.as_str(), plain[0].message_type), ("ECU1", "", MessageType::Unknown));
+        assert_eq!(plain[0].payload, b"xyz");
+    }
+
+    #[test]
+    fn skips_garbage_before_storage_headers() {
+        let bytes = [&b"\0DLT\x02DL"[..], &stored(0, b"one"), b"DLDL", &stored(0, b"two")].concat();
+
+        let payloads: Vec<Vec<u8>> = read(&bytes).into_iter().map(|message| message.payload).collect();
+        assert_eq!(payloads, [b"one".to_vec(), b"two".to_vec()]);
+    }
+
+    #[test]
+    fn ends_at_a_truncated_last_message() {
+        let second = stored(HTYP_UEH, &info(0));
+        let bytes = [&stored(0, b"one")[..], &second[..second.len() - 3]].concat();
+
+        assert_eq!(read(&bytes).len(), 1);
+    }
+
+    #[test]
+    fn refuses_lengths_shorter_than_the_header() {
+        let mut bytes = stored(0, b"");
+        bytes[18..20].copy_from_slice(&2u16.to_be_bytes());
+
+        let err = Reader::new(&bytes[..]).next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decodes_verbose_arguments() {
+        let mut payload = Vec::new();
+        payload.extend(0x21u32.to_le_bytes());
+        payload.push(0xff);
+        payload.extend(0x22u32.to_le_bytes());
+        payload.extend((-300i16).to_le_bytes());
+        payload.extend(0x43u32.to_le_bytes());
+        payload.extend(3_000_000_000u32.to_le_bytes());
+        payload.extend(0x83u32.to_le_bytes());
+        payload.extend(1.5f32.to_le_bytes());
+        payload.extend(0x84u32.to_le_bytes());
+        payload.extend((-0.25f64).to_le_bytes());
+        payload.extend(0x11u32.to_le_bytes());
+        payload.push(1);
+        payload.extend(TYPE_STRG.to_le_bytes());
+        payload.extend(6u16.to_le_bytes());
+        payload.extend(b"hello\0");
+        payload.extend(TYPE_RAWD.to_le_bytes());
+        payload.extend(2u16.to_le_bytes());
+        payload.extend([0xab, 0xcd]);
+
+        let arguments = verbose(payload, 8, false).arguments().unwrap();
+        assert_eq!(arguments, [
+            Argument::Signed(-1),
+            Argument::Signed(-300),
+            Argument::Unsigned(3_000_000_000),
+            Argument::Float(1.5),
+            Argument::Float(-0.25),
+            Argument::Bool(true),
+            Argument::String("hello".to_string()),
+            Argument::Raw(vec![0xab, 0xcd]),
+        ]);
+        assert_eq!(verbose(Vec::new(), 0, false).text(), "");
+    }
+
+    #[test]
+    fn skips_names_units_and_quantization() {
+        let mut payload = Vec::new();
+        // A named string
+        payload.extend((TYPE_STRG | TYPE_VARI).to_be_bytes());
+        payload.extend(2u16.to_be_bytes());
+        payload.extend(4u16.to_be_bytes());
+        payload.extend(b"key\0ok");
+        // A named unsigned 16-bit value with unit
+        payload.extend((TYPE_UINT | TYPE_VARI | 2).to_be_bytes());
+        payload.extend(2u16.to_be_bytes());
+        payload.extend(3u16.to_be_bytes());
+        payload.extend(b"v\0mA\0");
+        payload.extend(500u16.to_be_bytes());
+        // A fixed-point signed 32-bit value, factor and offset are left out
+        payload.extend((TYPE_SINT | TYPE_FIXP | 3).to_be_bytes());
+        payload.extend(0.5f32.to_be_bytes());
+        payload.extend(10i32.to_be_bytes());
+        payload.extend((-7i32).to_be_bytes());
+
+        let message = verbose(payload, 3, true);
+        assert_eq!(message.arguments().unwrap(), [Argument::String("ok".to_string()), Argument::Unsigned(500), Argument::Signed(-7)]);
+        assert_eq!(message.text(), "ok 500 -7");
+    }
+
+    #[test]
+    fn reports_broken_arguments() {
+        let mut truncated = 0x43u32.to_le_bytes().to_vec();
+        truncated.extend([1, 2]);
+        assert_eq!(verbose(truncated.clone(), 1, false).arguments().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(verbose(truncated, 1, false).text(), "43 00 00 00 01 02");
+
+        let unsupported = 0x2000u32.to_le_bytes().to_vec();
+        assert!(verbose(unsupported, 1, false).arguments().is_err());
+    }
+
+    #[test]
+    fn splits_non_verbose_messages() {
+        let mut message = verbose([&0x1234u32.to_le_bytes()[..], &[1, 2]].concat(), 0, false);
+        message.verbose = false;
+
+        assert_eq!(message.non_verbose(), Some((0x1234, &[1u8, 2][..])));
+        assert_eq!(message.text(), "[4660] 01 02");
+    }
+}
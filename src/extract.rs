@@ -0,0 +1,1298 @@
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use anyhow::{Context, bail};
+use flate2::read::GzDecoder;
+use indicatif::HumanBytes;
+use regex::{Regex, RegexBuilder};
+use zip::{ZipArchive, read::ZipFile, result::ZipError};
+use crate::{bundle, config::{Config, Keep, RotatedLogs}, convert, filename, fsimage::{self, ImageFile}, interrupt::{self, Interrupted}, layout::{self, ArchiveFormat}, limit, manifest, output, pdf};
+
+/// Default read buffer size per extracted file.
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Selects which files of the download folder `extract_logs` considers.
+#[derive(Clone)]
+pub struct ExtractOptions {
+    pub archives_only: bool,
+    pub loose_only: bool,
+    /// Only keep files with a line matching this pattern.
+    pub content_regex: Option<regex::bytes::Regex>,
+    /// How many lines of each file are scanned for `content_regex`, all if `None`.
+    pub content_lines: Option<usize>,
+    /// Only extract these exact archive paths, looked up directly instead of scanning all entries.
+    /// With `stdout`, loose files are only written if their name is listed as well.
+    pub entries: Vec<String>,
+    /// Size of the read buffer per file. Extraction never holds more than two buffers of a file in
+    /// memory, also with `content_regex`.
+    pub buffer_size: usize,
+    /// Link loose files into the destination instead of copying them.
+    pub link: Option<LinkMode>,
+    /// Only list the files that would be extracted, without writing anything.
+    pub dry_run: bool,
+    /// Write the content of the matching file to stdout instead of into the destination. Fails
+    /// if several files match, unless `concat` is set.
+    pub stdout: bool,
+    /// With `stdout`, write all matching files one after the other.
+    pub concat: bool,
+    /// Also write files that are already in the destination with the same size.
+    pub force: bool,
+    /// Compare files already in the destination by SHA-256 before skipping them, not only by size.
+    pub verify: bool,
+}
+
+/// How `--link` puts loose files into the destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LinkMode {
+    Hard,
+    Symbolic,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            archives_only: false,
+            loose_only: false,
+            content_regex: None,
+            content_lines: None,
+            entries: Vec::new(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            link: None,
+            dry_run: false,
+            stdout: false,
+            concat: false,
+            force: false,
+            verify: false,
+        }
+    }
+}
+
+/// Problems that did not stop the extraction.
+#[derive(Default, Debug)]
+pub struct ExtractReport {
+    /// Archive entries that could not be extracted, with the reason.
+    pub failed_entries: Vec<String>,
+    /// Archives that could not be opened at all, e.g. truncated downloads, with the reason.
+    pub failed_archives: Vec<String>,
+    /// PDFs whose text could not be extracted, e.g. scanned documents, with the reason.
+    pub failed_documents: Vec<String>,
+    /// With `dry_run`, the files that would have been extracted, with their uncompressed size.
+    pub planned: Vec<(String, u64)>,
+    /// Files and entries considered per archive, and for the loose files.
+    pub sources: Vec<SourceCount>,
+    /// Files not written again because the destination already had them.
+    pub unchanged: usize,
+}
+
+/// How many files a source contained and how many of them matched by name.
+#[derive(Debug, Clone)]
+pub struct SourceCount {
+    /// Archive name, or `None` for the loose downloaded files.
+    pub archive: Option<String>,
+    pub seen: usize,
+    pub matched: usize,
+}
+
+impl SourceCount {
+    pub fn skipped(&self) -> usize {
+        self.seen - self.matched
+    }
+}
+
+pub fn extract_logs(src: &Path, dest: &Path, config: &Config, options: &ExtractOptions) -> anyhow::Result<ExtractReport> {
+    if options.stdout && !options.dry_run {
+        extract_to_stdout(src, config, options)
+    } else {
+        extract_files(src, dest, config, options)
+    }
+}
+
+fn extract_files(src: &Path, dest: &Path, config: &Config, options: &ExtractOptions) -> anyhow::Result<ExtractReport> {
+    if !options.dry_run && !options.stdout {
+        fs::create_dir_all(dest)?;
+    }
+    let case_insensitive = config.case_insensitive.unwrap_or(false);
+    let exclude_regex = config.exclude_regex.as_deref()
+        .map(|regex| RegexBuilder::new(regex).case_insensitive(case_insensitive).build())
+        .transpose()
+        .context("Invalid exclude_regex")?;
+    let logfile_names = NameFilter {
+        include: RegexBuilder::new(&config.logfile_regex).case_insensitive(case_insensitive).build().context("Invalid logfile_regex")?,
+        exclude: exclude_regex.clone(),
+    };
+    let entry_names = NameFilter {
+        include: RegexBuilder::new(config.archive_regex.as_deref().unwrap_or(&config.logfile_regex))
+            .case_insensitive(case_insensitive)
+            .build()
+            .context("Invalid archive_regex")?,
+        exclude: exclude_regex,
+    };
+    let base64_regex = config.base64_regex.as_deref().map(Regex::new).transpose().context("Invalid base64_regex")?;
+
+    // The bundle and combined log getlogs writes into the issue folder are not downloads
+    let folder = src.file_name().map(|folder| folder.to_string_lossy().into_owned()).unwrap_or_default();
+    let own_files = [bundle::archive_name(&folder), convert::combined_name(&folder)];
+
+    let mut paths = Vec::new();
+    for dir in config.download_layout.unwrap_or_default().download_dirs(src) {
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                paths.push(entry?.path());
+            }
+        }
+    }
+    // Files whose names collide are renamed in this order, whatever order the directory lists
+    paths.sort();
+
+    // With --entry the entries are picked explicitly
+    let rotation = match &config.rotated_logs {
+        Some(rotated) if options.entries.is_empty() => {
+            Some(Rotation::plan(rotated, &paths, &logfile_names, &entry_names, options)?)
+        }
+        _ => None,
+    };
+
+    let mut run = Run {
+        dest,
+        options,
+        entry_names: &entry_names,
+        rotation,
+        // Problems were already reported while planning what to write to stdout
+        quiet: options.stdout && !options.dry_run,
+        report: ExtractReport::default(),
+        found_entries: HashSet::new(),
+        planned: Vec::new(),
+        written: HashSet::new(),
+        unchanged: 0,
+        rotated_out: 0,
+    };
+    let mut loose = SourceCount { archive: None, seen: 0, matched: 0 };
+
+    for path in paths {
+        interrupt::check()?;
+        let _permit = limit::acquire();
+
+        if path.is_file() {
+            let fname = path.file_name().unwrap().to_string_lossy();
+            if manifest::is_internal_file(&fname) || own_files.iter().any(|own| *own == *fname) {
+                continue;
+            }
+
+            let decoded_size = match &base64_regex {
+                Some(regex) if !options.archives_only && regex.is_match(&fname) && logfile_names.matches(&fname) => {
+                    base64_size(fs::File::open(&path)?, options.buffer_size)?
+                }
+                _ => None,
+            };
+
+            if let Some(size) = decoded_size {
+                loose.seen += 1;
+                if run.is_rotated_out(&path, &fname) {
+                    run.rotated_out += 1;
+                    continue;
+                }
+                if options.stdout && !options.entries.is_empty() {
+                    match options.entries.iter().find(|e| **e == *fname) {
+                        Some(entry) => run.found_entries.insert(entry.as_str()),
+                        None => continue,
+                    };
+                }
+
+                // Only counted as a match once the decoded content passed the content filter
+                let out_name = decoded_name(&fname);
+                if options.dry_run {
+                    if content_matches(base64_reader(&path)?, options)? {
+                        loose.matched += 1;
+                        run.planned.push((format!("{} (decoded from {})", out_name, fname), size));
+                    }
+                } else if options.stdout {
+                    if write_if_content_matches(base64_reader(&path)?, options).with_context(|| format!("decoding {}", fname))? {
+                        loose.matched += 1;
+                    }
+                } else {
+                    let out_name = unique_name(&mut run.written, &out_name, &fname);
+                    let out_path = dest.join(&out_name);
+                    if is_extracted(&out_path, size, options) && (!options.verify || same_content(&out_path, base64_reader(&path)?)?) {
+                        loose.matched += 1;
+                        run.skip_unchanged(&fname);
+                        continue;
+                    }
+
+                    if copy_if_content_matches(base64_reader(&path)?, &out_path, options).with_context(|| format!("decoding {}", fname))? {
+                        loose.matched += 1;
+                        output::success(format!("Decoded base64 file {} to {}", fname, out_name));
+                    }
+                }
+            } else if !options.archives_only && pdf::is_pdf(&fname) && logfile_names.matches(&fname) {
+                loose.seen += 1;
+                if options.stdout && !options.entries.is_empty() {
+                    match options.entries.iter().find(|e| **e == *fname) {
+                        Some(entry) => run.found_entries.insert(entry.as_str()),
+                        None => continue,
+                    };
+                }
+                loose.matched += 1;
+                let text = match fs::read(&path).map_err(anyhow::Error::from).and_then(|data| pdf::text(&data)) {
+                    Ok(text) if !text.trim().is_empty() => text,
+                    Ok(_) => {
+                        run.report.failed_documents.push(format!("{}: no text layer, it may be a scanned document", fname));
+                        continue;
+                    }
+                    Err(err) => {
+                        run.report.failed_documents.push(format!("{}: {:#}", fname, err));
+                        continue;
+                    }
+                };
+
+                let out_name = pdf::text_name(&fname);
+                if options.dry_run {
+                    if content_matches(text.as_bytes(), options)? {
+                        run.planned.push((format!("{} (text of {})", out_name, fname), text.len() as u64));
+                    }
+                } else if options.stdout {
+                    write_if_content_matches(text.as_bytes(), options)?;
+                } else {
+                    let out_name = unique_name(&mut run.written, &out_name, &fname);
+                    let out_path = dest.join(&out_name);
+                    if is_extracted(&out_path, text.len() as u64, options) && (!options.verify || same_content(&out_path, text.as_bytes())?) {
+                        run.skip_unchanged(&fname);
+                        continue;
+                    }
+
+                    copy_if_content_matches(text.as_bytes(), &out_path, options)?;
+                    output::success(format!("Extracted the text of {} to {}", fname, out_name));
+                }
+            } else if !options.archives_only && logfile_names.matches(&fname) {
+                loose.seen += 1;
+                if run.is_rotated_out(&path, &fname) {
+                    run.rotated_out += 1;
+                    continue;
+                }
+
+                // On stdout, `--entry` picks the loose file as well as archive entries
+                if options.stdout && !options.entries.is_empty() {
+                    match options.entries.iter().find(|e| **e == *fname) {
+                        Some(entry) => run.found_entries.insert(entry.as_str()),
+                        None => continue,
+                    };
+                }
+                loose.matched += 1;
+
+                let out_path = if options.dry_run || options.stdout { PathBuf::new() } else { dest.join(unique_name(&mut run.written, &fname, &fname)) };
+
+                if options.dry_run {
+                    if content_matches(fs::File::open(&path)?, options)? {
+                        run.planned.push((fname.to_string(), fs::metadata(&path)?.len()));
+                    }
+                } else if options.stdout {
+                    write_if_content_matches(fs::File::open(&path)?, options).with_context(|| format!("reading {}", fname))?;
+                } else if is_extracted(&out_path, fs::metadata(&path)?.len(), options)
+                    && (!options.verify || same_content(&out_path, fs::File::open(&path)?)?)
+                {
+                    run.skip_unchanged(&fname);
+                } else {
+                    match options.link {
+                        Some(mode) => link_if_content_matches(&path, &out_path, mode, options)?,
+                        None => {
+                            copy_if_content_matches(fs::File::open(&path)?, &out_path, options)?;
+                        }
+                    }
+                }
+            } else if !options.loose_only && let Some(format) = layout::archive_file_format(&path) {
+                run.archive(&path, &Archive::download(&fname), format)?;
+            } else if !options.archives_only {
+                loose.seen += 1;
+            }
+        }
+    }
+
+    let Run { mut report, planned, found_entries, unchanged, rotated_out, quiet, .. } = run;
+    if loose.seen > 0 {
+        report.sources.insert(0, loose);
+    }
+
+    if options.stdout {
+        // The planning pass of extract_to_stdout only collects the matching files
+    } else if options.dry_run {
+        println!("Would extract {} files, {} in total:", planned.len(), HumanBytes(planned.iter().map(|(_, size)| size).sum()));
+        for (name, size) in &planned {
+            println!("  {} ({})", name, HumanBytes(*size));
+        }
+    } else {
+        output::success(format!("Extraction complete to {}", dest.display()));
+    }
+
+    if quiet {
+        report.planned = planned;
+        return Ok(report);
+    }
+
+    if unchanged > 0 {
+        output::info(format!("Skipped {} files already extracted, pass --force to extract them again", unchanged));
+    }
+    report.unchanged = unchanged;
+
+    if rotated_out > 0 {
+        let kept = match config.rotated_logs.as_ref().map(|r| r.keep) {
+            Some(Keep::Last) => "last",
+            _ => "first",
+        };
+        output::info(format!("Skipped {} rotated logs, keeping the {} of each group", rotated_out, kept));
+    }
+
+    // Keep stdout for the file content
+    if !options.stdout {
+        for count in &report.sources {
+            let source = count.archive.as_deref().unwrap_or("Loose files");
+            output::info(format!("{}: {} seen, {} matched, {} skipped", source, count.seen, count.matched, count.skipped()));
+        }
+    }
+
+    // With --entry the patterns do not select, missing entries are reported below
+    let archive_pattern = if config.archive_regex.is_some() { "archive_regex" } else { "logfile_regex" };
+    for count in report.sources.iter().filter(|c| c.seen > 0 && c.matched == 0 && options.entries.is_empty()) {
+        match &count.archive {
+            None => output::warning(format!("None of the {} loose files match logfile_regex, check the pattern", count.seen)),
+            Some(archive) => output::warning(format!(
+                "None of the {} entries of {} match {}, check the pattern", count.seen, archive, archive_pattern
+            )),
+        }
+    }
+
+    for name in options.entries.iter().filter(|n| !found_entries.contains(n.as_str())) {
+        output::warning(format!("No archive contains {}", name));
+    }
+
+    if !report.failed_archives.is_empty() {
+        output::warning(format!("{} archives could not be opened:", report.failed_archives.len()));
+        for failure in &report.failed_archives {
+            output::warning(format!("  {}", failure));
+        }
+    }
+
+    if !report.failed_documents.is_empty() {
+        output::warning(format!("{} PDFs have no text that could be extracted:", report.failed_documents.len()));
+        for failure in &report.failed_documents {
+            output::warning(format!("  {}", failure));
+        }
+    }
+
+    if !report.failed_entries.is_empty() {
+        output::warning(format!("{} archive entries could not be extracted:", report.failed_entries.len()));
+        for failure in &report.failed_entries {
+            output::warning(format!("  {}", failure));
+        }
+    }
+
+    report.planned = planned;
+
+    Ok(report)
+}
+
+/// `name`, or `name` with a number appended if this run already wrote a file of that name to the
+/// destination, recorded in `written`. `source` names the file or entry in the warning.
+fn unique_name(written: &mut HashSet<String>, name: &str, source: &str) -> String {
+    let mut unique = name.to_string();
+    let mut number = 1;
+    while !written.insert(unique.clone()) {
+        number += 1;
+        unique = filename::with_suffix(name, &number.to_string());
+    }
+
+    if unique != name {
+        output::warning(format!("{} is extracted as {}, another file is already named {}", source, unique, name));
+    }
+
+    unique
+}
+
+/// Names matching `include` but not `exclude`, the `exclude_regex` of the config.
+struct NameFilter {
+    include: Regex,
+    exclude: Option<Regex>,
+}
+
+impl NameFilter {
+    fn matches(&self, name: &str) -> bool {
+        self.include.is_match(name) && !self.excludes(name)
+    }
+
+    fn excludes(&self, name: &str) -> bool {
+        self.exclude.as_ref().is_some_and(|exclude| exclude.is_match(name))
+    }
+}
+
+/// The file kept of each group of rotated logs, see [`RotatedLogs`].
+struct Rotation {
+    pattern: Regex,
+    /// Per group, the order, source and name of the kept file.
+    kept: HashMap<String, (Order, PathBuf, String)>,
+}
+
+/// Sort key of a rotation from the `order` capture.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Order {
+    Missing,
+    Number(u128),
+    Text(String),
+}
+
+impl Rotation {
+    /// Picks the file to keep of each group among the loose files and archive entries in `paths`
+    /// that would be extracted.
+    fn plan(rotated: &RotatedLogs, paths: &[PathBuf], logfile_names: &NameFilter, entry_names: &NameFilter, options: &ExtractOptions) -> anyhow::Result<Self> {
+        let pattern = Regex::new(&rotated.pattern).context("Invalid rotated_logs pattern")?;
+        if !pattern.capture_names().any(|name| name == Some("group")) {
+            anyhow::bail!("The rotated_logs pattern needs a `group` capture, like (?P<group>app)");
+        }
+        let mut rotation = Rotation { pattern, kept: HashMap::new() };
+
+        for path in paths.iter().filter(|path| path.is_file()) {
+            let fname = path.file_name().unwrap().to_string_lossy();
+            if !options.archives_only && logfile_names.matches(&fname) {
+                rotation.offer(path, &fname, rotated.keep);
+            } else if !options.loose_only && layout::archive_file_format(path) == Some(ArchiveFormat::Zip) && let Ok(zip) = open_zip(path) {
+                for name in zip.file_names().filter(|name| !name.ends_with('/') && entry_names.matches(name)) {
+                    rotation.offer(path, name, rotated.keep);
+                }
+            }
+        }
+
+        Ok(rotation)
+    }
+
+    /// Group and order of the file or entry `name`, `None` if it is not a rotated log.
+    fn group_and_order(&self, name: &str) -> Option<(String, Order)> {
+        let file_name = Path::new(name).file_name()?.to_string_lossy();
+        let captures = self.pattern.captures(&file_name)?;
+        let order = match captures.name("order").map(|m| m.as_str()) {
+            None => Order::Missing,
+            Some(order) => order.parse().map(Order::Number).unwrap_or_else(|_| Order::Text(order.to_string())),
+        };
+
+        Some((captures.name("group")?.as_str().to_string(), order))
+    }
+
+    fn offer(&mut self, source: &Path, name: &str, keep: Keep) {
+        let Some((group, order)) = self.group_and_order(name) else {
+            return;
+        };
+
+        let better = match self.kept.get(&group) {
+            None => true,
+            Some((kept, _, _)) => match keep {
+                Keep::First => order < *kept,
+                Keep::Last => order > *kept,
+            },
+        };
+        if better {
+            self.kept.insert(group, (order, source.to_path_buf(), name.to_string()));
+        }
+    }
+
+    /// Whether `name` from `source` is a rotated log other than the one kept of its group.
+    fn skips(&self, source: &Path, name: &str) -> bool {
+        let Some((group, _)) = self.group_and_order(name) else {
+            return false;
+        };
+
+        self.kept.get(&group).is_some_and(|(_, kept_source, kept_name)| kept_source != source || kept_name != name)
+    }
+}
+
+/// Whether `out_path` already holds a file of `size` bytes, so extracting it again can be skipped
+/// unless `options.force` is set. With `options.verify` callers compare the content too.
+fn is_extracted(out_path: &Path, size: u64, options: &ExtractOptions) -> bool {
+    !options.force && fs::metadata(out_path).is_ok_and(|m| m.is_file() && m.len() == size)
+}
+
+/// Whether the file at `path` has the SHA-256 of the content of `reader`.
+fn same_content(path: &Path, reader: impl Read) -> io::Result<bool> {
+    Ok(manifest::hash_reader(reader)? == manifest::hash_file(path)?)
+}
+
+/// How an archive entry is looked up, so that it can be opened again after checking it.
+#[derive(Clone, Copy)]
+enum EntryRef<'a> {
+    Index(usize),
+    Name(&'a str),
+}
+
+impl EntryRef<'_> {
+    fn open<'z>(self, zip: &'z mut ZipArchive<fs::File>) -> zip::result::ZipResult<ZipFile<'z>> {
+        match self {
+            EntryRef::Index(i) => zip.by_index(i),
+            EntryRef::Name(name) => zip.by_name(name),
+        }
+    }
+}
+
+fn open_zip(path: &Path) -> anyhow::Result<ZipArchive<fs::File>> {
+    Ok(ZipArchive::new(fs::File::open(path)?)?)
+}
+
+/// Archives inside archives are unpacked down to this depth, downloads being the first level.
+const MAX_ARCHIVE_DEPTH: usize = 4;
+
+/// Bytes read at most out of an archive nested in another one, against zip bombs.
+const MAX_NESTED_SIZE: u64 = 8 << 30;
+
+/// An archive being extracted: a download, or one found inside another archive.
+struct Archive {
+    /// Name in messages, like `bundle.zip/device.tar.gz` for nested archives.
+    label: String,
+    /// 1 for downloads, one more per archive it is nested in.
+    depth: usize,
+    /// Bytes that may still be read out of the archive, see [`MAX_NESTED_SIZE`].
+    budget: Cell<u64>,
+}
+
+impl Archive {
+    fn download(name: &str) -> Self {
+        Archive { label: name.to_string(), depth: 1, budget: Cell::new(u64::MAX) }
+    }
+
+    fn nested(&self, name: &str) -> Self {
+        Archive { label: format!("{}/{}", self.label, name), depth: self.depth + 1, budget: Cell::new(MAX_NESTED_SIZE) }
+    }
+
+    /// `reader` counted against the budget of the archive.
+    fn limit<R: Read>(&self, reader: R) -> SizeLimit<'_, R> {
+        SizeLimit { inner: reader, left: &self.budget }
+    }
+}
+
+/// What became of an archive entry.
+enum Entry {
+    Ignored,
+    /// Extracted, or would have been with `dry_run`, or already in the destination.
+    Extracted(String, u64),
+    /// An archive whose entries were extracted in turn.
+    Unpacked,
+}
+
+/// State shared by the loose files and archives of one extraction.
+struct Run<'a> {
+    dest: &'a Path,
+    options: &'a ExtractOptions,
+    entry_names: &'a NameFilter,
+    rotation: Option<Rotation>,
+    /// Whether problems are reported by the caller instead.
+    quiet: bool,
+    report: ExtractReport,
+    found_entries: HashSet<&'a str>,
+    planned: Vec<(String, u64)>,
+    /// Names written to `dest`, so files of the same name do not overwrite each other.
+    written: HashSet<String>,
+    unchanged: usize,
+    rotated_out: usize,
+}
+
+impl<'a> Run<'a> {
+    fn is_rotated_out(&self, source: &Path, name: &str) -> bool {
+        self.rotation.as_ref().is_some_and(|r| r.skips(source, name))
+    }
+
+    /// Counts `name` as already in the destination, listed one by one only with `-v`.
+    fn skip_unchanged(&mut self, name: &str) {
+        output::detail(format!("Skipping {}, already extracted", name));
+        self.unchanged += 1;
+    }
+
+    /// The format of the entry `name` if it is an archive to unpack rather than extract.
+    fn nested_format(&self, name: &str) -> Option<ArchiveFormat> {
+        layout::archive_format(name).filter(|_| !self.entry_names.excludes(name))
+    }
+
+    /// Extracts the matching entries of the archive at `path` and unpacks the archives among
+    /// them. Archives that cannot be read are reported, only interrupts are returned.
+    fn archive(&mut self, path: &Path, archive: &Archive, format: ArchiveFormat) -> anyhow::Result<()> {
+        let mut count = SourceCount { archive: Some(archive.label.clone()), seen: 0, matched: 0 };
+        let result = match format {
+            ArchiveFormat::Zip => self.zip(path, archive, &mut count),
+            ArchiveFormat::Tar | ArchiveFormat::TarGz => fs::File::open(path).map_err(anyhow::Error::from).and_then(|file| {
+                let reader: Box<dyn Read> = if format == ArchiveFormat::TarGz { Box::new(GzDecoder::new(file)) } else { Box::new(file) };
+                self.tar(reader, archive, &mut count)
+            }),
+            ArchiveFormat::Image => self.image(path, archive, &mut count),
+        };
+
+        match result {
+            Err(err) if !err.is::<Interrupted>() => {
+                if !self.quiet {
+                    output::failure(format!("Skipping {}, cannot read archive: {:#}", archive.label, err));
+                }
+                self.report.failed_archives.push(format!("{}: {:#}", archive.label, err));
+
+                // A tarball may break after some of its entries were extracted
+                if count.seen > 0 {
+                    self.report.sources.push(count);
+                }
+            }
+            result => {
+                result?;
+                self.report.sources.push(count);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn zip(&mut self, path: &Path, archive: &Archive, count: &mut SourceCount) -> anyhow::Result<()> {
+        let mut zip = open_zip(path)?;
+        count.seen = zip.file_names().filter(|name| !name.ends_with('/')).count();
+        let options = self.options;
+
+        if options.entries.is_empty() {
+            let len = zip.len();
+            for i in 0..len {
+                interrupt::check()?;
+
+                // Rotated logs are picked among the downloaded archives
+                let rotated = archive.depth == 1 && zip.by_index_raw(i).is_ok_and(|f| self.is_rotated_out(path, f.name()));
+                if rotated {
+                    self.rotated_out += 1;
+                    continue;
+                }
+
+                let result = self.zip_entry(&mut zip, EntryRef::Index(i), archive, true)
+                    .with_context(|| format!("{}, entry {} of {}", archive.label, i + 1, len));
+                self.record(count, archive, result)?;
+            }
+        } else {
+            for name in &options.entries {
+                interrupt::check()?;
+
+                let result = match self.zip_entry(&mut zip, EntryRef::Name(name), archive, false) {
+                    Err(err) if matches!(err.downcast_ref(), Some(ZipError::FileNotFound)) => continue,
+                    result => result,
+                };
+                self.found_entries.insert(name.as_str());
+                self.record(count, archive, result.with_context(|| format!("{}, entry {}", archive.label, name)))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Extracts `entry` into the destination. With `select`, only if its name matches the
+    /// patterns, and archives are unpacked instead. Entries whose path leads out of the archive,
+    /// like `../../etc/profile`, are refused.
+    fn zip_entry(&mut self, zip: &mut ZipArchive<fs::File>, entry: EntryRef<'_>, archive: &Archive, select: bool) -> anyhow::Result<Entry> {
+        let f = entry.open(zip)?;
+        let name = f.name().to_string();
+        let size = f.size();
+
+        if f.is_dir() {
+            return Ok(Entry::Ignored);
+        }
+        if select && let Some(format) = self.nested_format(&name) {
+            self.nested(archive.limit(f), archive, &name, format)?;
+            return Ok(Entry::Unpacked);
+        }
+        if select && !self.entry_names.matches(&name) {
+            return Ok(Entry::Ignored);
+        }
+
+        let Some(file_name) = f.enclosed_name().and_then(Path::file_name).map(|n| n.to_string_lossy().into_owned()) else {
+            bail!("refusing {}, its path leaves the archive", name);
+        };
+
+        if self.options.dry_run {
+            let matched = content_matches(archive.limit(f), self.options).with_context(|| format!("reading {}", name))?;
+            return Ok(if matched { Entry::Extracted(name, size) } else { Entry::Ignored });
+        }
+
+        if self.options.stdout {
+            write_if_content_matches(archive.limit(f), self.options).with_context(|| format!("extracting {}", name))?;
+            return Ok(Entry::Extracted(name, size));
+        }
+
+        drop(f);
+        let out_path = self.dest.join(unique_name(&mut self.written, &file_name, &name));
+        if is_extracted(&out_path, size, self.options) && (!self.options.verify || same_content(&out_path, archive.limit(entry.open(zip)?))?) {
+            self.skip_unchanged(&format!("{}: {}", archive.label, name));
+            return Ok(Entry::Extracted(name, size));
+        }
+
+        copy_if_content_matches(archive.limit(entry.open(zip)?), &out_path, self.options).with_context(|| format!("extracting {}", name))?;
+
+        Ok(Entry::Extracted(name, size))
+    }
+
+    /// Extracts the regular files of the tarball `reader` like the entries of a zip. A broken
+    /// stream ends the tarball with an error.
+    fn tar(&mut self, reader: Box<dyn Read>, archive: &Archive, count: &mut SourceCount) -> anyhow::Result<()> {
+        let mut tar = tar::Archive::new(archive.limit(reader));
+
+        for entry in tar.entries()? {
+            interrupt::check()?;
+
+            let entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            count.seen += 1;
+
+            let result = self.tar_entry(entry, archive).with_context(|| format!("{}, entry {}", archive.label, count.seen));
+            self.record(count, archive, result)?;
+        }
+
+        Ok(())
+    }
+
+    /// Extracts a file of a tarball like [`Run::zip_entry`]. Tarballs are read once from start to
+    /// end, so with `verify` entries already in the destination are extracted again instead of
+    /// being compared.
+    fn tar_entry(&mut self, entry: tar::Entry<'_, impl Read>, archive: &Archive) -> anyhow::Result<Entry> {
+        let path = entry.path()?.into_owned();
+        let name = path.to_string_lossy().trim_start_matches("./").to_string();
+        let size = entry.size();
+        let options = self.options;
+
+        if options.entries.is_empty() {
+            if let Some(format) = self.nested_format(&name) {
+                self.nested(entry, archive, &name, format)?;
+                return Ok(Entry::Unpacked);
+            }
+            if !self.entry_names.matches(&name) {
+                return Ok(Entry::Ignored);
+            }
+        } else {
+            match options.entries.iter().find(|e| **e == name) {
+                Some(listed) => self.found_entries.insert(listed.as_str()),
+                None => return Ok(Entry::Ignored),
+            };
+        }
+
+        let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+        let Some(file_name) = file_name.filter(|_| path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir))) else {
+            bail!("refusing {}, its path leaves the archive", name);
+        };
+
+        if options.dry_run {
+            let matched = content_matches(entry, options).with_context(|| format!("reading {}", name))?;
+            return Ok(if matched { Entry::Extracted(name, size) } else { Entry::Ignored });
+        }
+
+        if options.stdout {
+            write_if_content_matches(entry, options).with_context(|| format!("extracting {}", name))?;
+            return Ok(Entry::Extracted(name, size));
+        }
+
+        let out_path = self.dest.join(unique_name(&mut self.written, &file_name, &name));
+        if !options.verify && is_extracted(&out_path, size, options) {
+            self.skip_unchanged(&format!("{}: {}", archive.label, name));
+            return Ok(Entry::Extracted(name, size));
+        }
+
+        copy_if_content_matches(entry, &out_path, options).with_context(|| format!("extracting {}", name))?;
+
+        Ok(Entry::Extracted(name, size))
+    }
+
+    /// Extracts the regular files of the file system image at `path` like the entries of a zip.
+    /// Images of file systems other than ext fail with the name of the file system.
+    fn image(&mut self, path: &Path, archive: &Archive, count: &mut SourceCount) -> anyhow::Result<()> {
+        let mut image = fsimage::Ext::open(fs::File::open(path)?)?;
+        let files = image.files()?;
+        count.seen = files.len();
+
+        for file in &files {
+            interrupt::check()?;
+
+            let result = self.image_entry(&mut image, file, archive).with_context(|| format!("{}, entry {}", archive.label, file.path));
+            self.record(count, archive, result)?;
+        }
+
+        Ok(())
+    }
+
+    /// Extracts a file of an image like [`Run::zip_entry`], picked by `--entry` like those of a
+    /// tarball.
+    fn image_entry(&mut self, image: &mut fsimage::Ext, file: &ImageFile, archive: &Archive) -> anyhow::Result<Entry> {
+        let name = file.path.clone();
+        let size = file.size;
+        let options = self.options;
+
+        if options.entries.is_empty() {
+            if let Some(format) = self.nested_format(&name) {
+                self.nested(archive.limit(image.open_file(file)?), archive, &name, format)?;
+                return Ok(Entry::Unpacked);
+            }
+            if !self.entry_names.matches(&name) {
+                return Ok(Entry::Ignored);
+            }
+        } else {
+            match options.entries.iter().find(|e| **e == name) {
+                Some(listed) => self.found_entries.insert(listed.as_str()),
+                None => return Ok(Entry::Ignored),
+            };
+        }
+
+        if options.dry_run {
+            let matched = content_matches(archive.limit(image.open_file(file)?), options).with_context(|| format!("reading {}", name))?;
+            return Ok(if matched { Entry::Extracted(name, size) } else { Entry::Ignored });
+        }
+
+        if options.stdout {
+            write_if_content_matches(archive.limit(image.open_file(file)?), options).with_context(|| format!("extracting {}", name))?;
+            return Ok(Entry::Extracted(name, size));
+        }
+
+        // Paths come from the directory tree, they cannot lead out of the image
+        let file_name = name.rsplit('/').next().unwrap_or(&name);
+        let out_path = self.dest.join(unique_name(&mut self.written, file_name, &name));
+        if is_extracted(&out_path, size, options) && (!options.verify || same_content(&out_path, archive.limit(image.open_file(file)?))?) {
+            self.skip_unchanged(&format!("{}: {}", archive.label, name));
+            return Ok(Entry::Extracted(name, size));
+        }
+
+        copy_if_content_matches(archive.limit(image.open_file(file)?), &out_path, options).with_context(|| format!("extracting {}", name))?;
+
+        Ok(Entry::Extracted(name, size))
+    }
+
+    /// Unpacks the archive entry `name` of `archive`, read from `reader`. It is copied to a
+    /// temporary file first, as zips cannot be read from a stream.
+    fn nested(&mut self, mut reader: impl Read, archive: &Archive, name: &str, format: ArchiveFormat) -> anyhow::Result<()> {
+        if archive.depth >= MAX_ARCHIVE_DEPTH {
+            bail!("not unpacking {}, archives are only unpacked {} levels deep", name, MAX_ARCHIVE_DEPTH);
+        }
+
+        let staged = Staged::new();
+        io::copy(&mut reader, &mut fs::File::create(&staged.0)?).with_context(|| format!("unpacking {}", name))?;
+        drop(reader);
+
+        self.archive(&staged.0, &archive.nested(name), format)
+    }
+
+    /// Counts the outcome of an entry of `archive`. Entries that failed are counted as matched,
+    /// they are listed separately. Interrupts are passed on.
+    fn record(&mut self, count: &mut SourceCount, archive: &Archive, result: anyhow::Result<Entry>) -> anyhow::Result<()> {
+        match result {
+            Ok(Entry::Ignored) => {}
+            Ok(Entry::Unpacked) => count.matched += 1,
+            Ok(Entry::Extracted(name, size)) => {
+                count.matched += 1;
+                if self.options.dry_run {
+                    self.planned.push((format!("{}: {}", archive.label, name), size));
+                }
+            }
+            Err(err) if err.is::<Interrupted>() => return Err(err),
+            Err(err) => {
+                count.matched += 1;
+                if !self.quiet {
+                    output::failure(format!("Skipping {:#}", err));
+                }
+                self.report.failed_entries.push(format!("{:#}", err));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Fails once more bytes were read than the budget of an archive allows.
+struct SizeLimit<'b, R> {
+    inner: R,
+    left: &'b Cell<u64>,
+}
+
+impl<R: Read> Read for SizeLimit<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let left = self.left.get().checked_sub(read as u64).ok_or_else(|| {
+            io::Error::other(format!("more than {} unpacked, refusing a possible zip bomb", HumanBytes(MAX_NESTED_SIZE)))
+        })?;
+        self.left.set(left);
+
+        Ok(read)
+    }
+}
+
+/// A temporary copy of a nested archive, removed when dropped.
+struct Staged(PathBuf);
+
+impl Staged {
+    fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let number = NEXT.fetch_add(1, Ordering::Relaxed);
+
+        Staged(std::env::temp_dir().join(format!("getlogs-{}-{}.nested", std::process::id(), number)))
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Writes the file matching `options` to stdout, or all of them with `options.concat`. Which
+/// files match is worked out first, so nothing is written if the selection is ambiguous.
+fn extract_to_stdout(src: &Path, config: &Config, options: &ExtractOptions) -> anyhow::Result<ExtractReport> {
+    let plan = extract_files(src, Path::new(""), config, &ExtractOptions { dry_run: true, ..options.clone() })?;
+    let names: Vec<&str> = plan.planned.iter().map(|(name, _)| name.as_str()).collect();
+
+    if names.is_empty() {
+        anyhow::bail!("No file in {} matches", src.display());
+    }
+
+    if names.len() > 1 && !options.concat {
+        anyhow::bail!(
+            "{} files match, select one with --entry or pass --concat to write all of them: {}",
+            names.len(), names.join(", ")
+        );
+    }
+
+    let mut report = extract_files(src, Path::new(""), config, options)?;
+    io::stdout().flush()?;
+    report.planned = plan.planned;
+
+    Ok(report)
+}
+
+/// Streams `reader` to stdout if it passes the content filter of `options`, returning whether it
+/// did. The scanned lines are held back until the match, so a file without one writes nothing;
+/// see [`HeldLines`].
+fn write_if_content_matches(reader: impl Read, options: &ExtractOptions) -> io::Result<bool> {
+    let mut reader = BufReader::with_capacity(options.buffer_size, reader);
+    let mut out = io::stdout().lock();
+
+    if let Some(regex) = &options.content_regex {
+        let mut scanned = HeldLines::new(2 * options.buffer_size);
+        if !copy_scanning(&mut reader, &mut scanned, regex, options)? {
+            return Ok(false);
+        }
+        scanned.write_to(&mut out)?;
+    }
+
+    io::copy(&mut reader, &mut out)?;
+
+    Ok(true)
+}
+
+/// Lines written to stdout only once the content filter matched. Up to `limit` bytes are held in
+/// memory, more go to a temporary file that is removed on drop, so a match far into a large file
+/// does not hold the file in memory.
+struct HeldLines {
+    memory: Vec<u8>,
+    limit: usize,
+    file: Option<(PathBuf, fs::File)>,
+}
+
+impl HeldLines {
+    fn new(limit: usize) -> Self {
+        HeldLines { memory: Vec::new(), limit, file: None }
+    }
+
+    fn write_to(mut self, out: &mut impl Write) -> io::Result<()> {
+        match &mut self.file {
+            Some((_, file)) => {
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(file, out)?;
+            }
+            None => out.write_all(&self.memory)?,
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for HeldLines {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() && self.memory.len() + buf.len() > self.limit {
+            let path = std::env::temp_dir().join(format!("getlogs-held-{}-{}", std::process::id(), fastrand::u64(..)));
+            let mut file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+            file.write_all(&self.memory)?;
+            self.memory = Vec::new();
+            self.file = Some((path, file));
+        }
+
+        match &mut self.file {
+            Some((_, file)) => file.write(buf),
+            None => {
+                self.memory.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for HeldLines {
+    fn drop(&mut self) {
+        // Closed first, Windows does not remove open files
+        if let Some((path, file)) = self.file.take() {
+            drop(file);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Streams `reader` to `out_path`, unless `options` has a content filter and none of the scanned
+/// lines match it, in which case the output is removed again. Returns whether it was kept. At most
+/// two buffers of `options.buffer_size` bytes are held, however large the file: lines longer than
+/// that are matched in pieces.
+fn copy_if_content_matches(reader: impl Read, out_path: &Path, options: &ExtractOptions) -> anyhow::Result<bool> {
+    let mut reader = BufReader::with_capacity(options.buffer_size, reader);
+
+    // A link left by `--link` would have the original truncated through it
+    remove_link(out_path)?;
+    let mut out = fs::File::create(out_path).with_context(|| format!("creating {}", out_path.display()))?;
+
+    let result = match &options.content_regex {
+        Some(regex) => copy_scanning(&mut reader, &mut out, regex, options)
+            .and_then(|matched| if matched { io::copy(&mut reader, &mut out).map(|_| true) } else { Ok(false) }),
+        None => io::copy(&mut reader, &mut out).map(|_| true),
+    };
+
+    if !matches!(result, Ok(true)) {
+        drop(out);
+        let _ = fs::remove_file(out_path);
+    }
+
+    Ok(result?)
+}
+
+/// Links `src` to `out_path` if it passes the content filter of `options`. Falls back to copying
+/// where the link cannot be created, e.g. for a hard link across file systems.
+fn link_if_content_matches(src: &Path, out_path: &Path, mode: LinkMode, options: &ExtractOptions) -> anyhow::Result<()> {
+    if !content_matches(fs::File::open(src)?, options)? {
+        return Ok(());
+    }
+
+    remove_link(out_path)?;
+    let linked = match mode {
+        LinkMode::Hard => fs::hard_link(src, out_path),
+        LinkMode::Symbolic => fs::canonicalize(src).and_then(|target| symlink(&target, out_path)),
+    };
+
+    if let Err(err) = linked {
+        output::warning(format!("Copying {} instead of linking it: {}", src.display(), err));
+        let copy_options = ExtractOptions { content_regex: None, entries: Vec::new(), ..*options };
+        copy_if_content_matches(fs::File::open(src)?, out_path, &copy_options)?;
+    }
+
+    Ok(())
+}
+
+/// Minimum length of a file's content to be taken for base64, in characters without whitespace.
+const MIN_BASE64_LENGTH: u64 = 16;
+
+/// The decoded size of `file` if it is entirely base64: only characters of the
+/// standard alphabet, padding only at the end, a length divisible by four, and all lines but the
+/// last of the same length. Anything else is left alone, so text logs are never mistaken for it.
+fn base64_size(file: impl Read, buffer_size: usize) -> io::Result<Option<u64>> {
+    let mut reader = BufReader::with_capacity(buffer_size, file);
+    let mut length = 0u64;
+    let mut padding = 0u64;
+    let mut line_length = 0u64;
+    let mut wrap: Option<u64> = None;
+    let mut last_line_shorter = false;
+
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+
+        for &byte in buffer {
+            match byte {
+                b'\n' => {
+                    if line_length > 0 {
+                        // A line shorter than the wrap width has to be the last one
+                        if last_line_shorter || wrap.is_some_and(|w| line_length > w) {
+                            return Ok(None);
+                        }
+                        last_line_shorter = wrap.is_some_and(|w| line_length < w);
+                        wrap.get_or_insert(line_length);
+                    }
+                    line_length = 0;
+                }
+                b'\r' => {}
+                b'=' => {
+                    padding += 1;
+                    length += 1;
+                    line_length += 1;
+                }
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'+' | b'/' if padding == 0 && !last_line_shorter => {
+                    length += 1;
+                    line_length += 1;
+                }
+                _ => return Ok(None),
+            }
+        }
+
+        let consumed = buffer.len();
+        reader.consume(consumed);
+    }
+
+    if last_line_shorter && line_length > 0 || wrap.is_some_and(|w| line_length > w) {
+        return Ok(None);
+    }
+
+    if length < MIN_BASE64_LENGTH || !length.is_multiple_of(4) || padding > 2 {
+        return Ok(None);
+    }
+
+    Ok(Some(length / 4 * 3 - padding))
+}
+
+/// The decoded content of a base64 file.
+fn base64_reader(path: &Path) -> io::Result<impl Read> {
+    let file = SkipWhitespace(fs::File::open(path)?);
+
+    Ok(base64::read::DecoderReader::new(file, &base64::engine::general_purpose::STANDARD))
+}
+
+/// `trace.dlt.b64` becomes `trace.dlt`, names without an inner extension get `.bin`.
+fn decoded_name(name: &str) -> String {
+    let path = Path::new(name);
+    match path.file_stem() {
+        Some(stem) if Path::new(stem).extension().is_some() => stem.to_string_lossy().into_owned(),
+        _ => path.with_extension("bin").to_string_lossy().into_owned(),
+    }
+}
+
+/// Drops the line breaks between base64 lines.
+struct SkipWhitespace<R>(R);
+
+impl<R: Read> Read for SkipWhitespace<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.0.read(buf)?;
+            if read == 0 {
+                return Ok(0);
+            }
+
+            let mut kept = 0;
+            for i in 0..read {
+                if !buf[i].is_ascii_whitespace() {
+                    buf[kept] = buf[i];
+                    kept += 1;
+                }
+            }
+
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+/// Whether `reader` passes the content filter of `options`; always without one.
+fn content_matches(reader: impl Read, options: &ExtractOptions) -> io::Result<bool> {
+    match &options.content_regex {
+        Some(regex) => copy_scanning(&mut BufReader::with_capacity(options.buffer_size, reader), &mut io::sink(), regex, options),
+        None => Ok(true),
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Removes `path` if it exists, so that writing it does not go through an earlier link.
+fn remove_link(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Writes lines to `out` while looking for one matching `regex`, stopping after the match.
+/// Returns whether a match was found before `options.content_lines` lines were scanned.
+fn copy_scanning(reader: &mut impl BufRead, out: &mut impl Write, regex: &regex::bytes::Regex, options: &ExtractOptions) -> io::Result<bool> {
+    let mut line = Vec::with_capacity(options.buffer_size);
+    let mut scanned = 0;
+
+    while options.content_lines.is_none_or(|max| scanned < max) {
+        line.clear();
+        if reader.by_ref().take(options.buffer_size as u64).read_until(b'\n', &mut line)? == 0 {
+            return Ok(false);
+        }
+
+        out.write_all(&line)?;
+
+        if regex.is_match(&line) {
+            return Ok(true);
+        }
+
+        if line.ends_with(b"\n") {
+            scanned += 1;
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sniff(content: &str) -> Option<u64> {
+        // A small buffer so lines span several reads
+        base64_size(content.as_bytes(), 5).unwrap()
+    }
+
+    #[test]
+    fn detects_wrapped_base64_and_its_decoded_size() {
+        assert_eq!(sniff("aGVsbG8gd29ybGQsIGxvZ3MhCg=="), Some(19));
+        assert_eq!(sniff("aGVsbG8gd29y\nbGQsIGxvZ3Mh\nCg==\n"), Some(19));
+        assert_eq!(sniff("aGVsbG8gd29y\r\nbGQsIGxvZ3Mh\r\n"), Some(18));
+    }
+
+    #[test]
+    fn leaves_text_that_only_looks_like_base64() {
+        // Too short, a word of plain text
+        assert_eq!(sniff("aGVsbG8="), None);
+        assert_eq!(sniff("Connectionrefused"), None);
+        // Spaces, padding in the middle, uneven lines, a length not divisible by four
+        assert_eq!(sniff("aGVsbG8g d29ybGQsIGxvZ3MhCg=="), None);
+        assert_eq!(sniff("aGVsbG8=\nd29ybGQsIGxvZ3MhCg=="), None);
+        assert_eq!(sniff("aGVsbG8g\nd29ybGQsIGxvZ3Mh\nCg=="), None);
+        assert_eq!(sniff("aGVs\nbG8g\nd2\nCg=="), None);
+        assert_eq!(sniff("aGVsbG8gd29ybGQsIGxvZ3MhCg"), None);
+        assert_eq!(sniff("aGVsbG8gd29ybGQsIGxvZ3MhCg==="), None);
+        assert_eq!(sniff("aGVsbG8gd29ybGQsIGxvZ3Mhä=="), None);
+        assert_eq!(sniff(""), None);
+    }
+
+    #[test]
+    fn holds_lines_beyond_the_limit_in_a_file() {
+        let mut held = HeldLines::new(8);
+        held.write_all(b"line 1\n").unwrap();
+        assert!(held.file.is_none());
+        held.write_all(b"line 2\nline 3\n").unwrap();
+        let path = held.file.as_ref().map(|(path, _)| path.clone()).unwrap();
+        assert!(held.memory.is_empty() && path.exists());
+
+        let mut out = Vec::new();
+        held.write_to(&mut out).unwrap();
+        assert_eq!(out, b"line 1\nline 2\nline 3\n");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn names_decoded_files_after_the_inner_extension() {
+        assert_eq!(decoded_name("trace.dlt.b64"), "trace.dlt");
+        assert_eq!(decoded_name("dump.txt"), "dump.bin");
+        assert_eq!(decoded_name("dump"), "dump.bin");
+    }
+}
@@ -0,0 +1,1460 @@
+use std::{borrow::Cow, collections::{BTreeSet, HashMap, HashSet, VecDeque}, fs, io::{self, Cursor, Seek, SeekFrom, Write}, net::SocketAddr, path::{Path, PathBuf}, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use std::fs::OpenOptions;
+use anyhow::Context;
+use bytes::Bytes;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use hyper::client::connect::dns::Name;
+use indicatif::HumanBytes;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use reqwest::{Client, Proxy, RequestBuilder, StatusCode, tls, dns::{Addrs, Resolve, Resolving}, header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE}};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339, macros::format_description};
+use crate::{attachment::{Attachment, AttachmentPage, IssueResponse}, budget::{Budget, BudgetExceeded}, concurrency::{self, Concurrency}, disk::{self, DiskError}, config::{Config, Http2, IpFamily, TlsVersion}, filename, filter::Filter, http_log::HttpLog, interrupt::{self, Interrupted}, layout::WORKLOGS_DIR, limit, manifest::{self, IssueDetails, Manifest, Status}, netrc, output, retry::{self, Backoff}, sftp, source::{self, Source}};
+
+/// Seconds idle connections are kept open by default, longer than the longest retry delay.
+const DEFAULT_POOL_IDLE_TIMEOUT: u64 = 90;
+
+/// Maximum number of times a single download is resumed after the connection dropped.
+const MAX_RESUME_ATTEMPTS: u32 = 5;
+
+/// How long an attachment that is still being uploaded is waited for, and the first and longest
+/// pause between attempts.
+const UPLOAD_WAIT: Duration = Duration::from_secs(120);
+const UPLOAD_POLL_START: Duration = Duration::from_secs(1);
+const UPLOAD_POLL_MAX: Duration = Duration::from_secs(15);
+
+/// First and longest pause before resuming a download whose connection dropped.
+const RESUME_DELAY_START: Duration = Duration::from_secs(1);
+const RESUME_DELAY_MAX: Duration = Duration::from_secs(30);
+
+/// Jira cuts the inline attachment list of an issue at this length; at or above it, the
+/// complete list is paged through the attachments endpoint.
+const INLINE_ATTACHMENT_LIMIT: usize = 1000;
+
+/// Attachments requested per page of the attachments endpoint.
+const ATTACHMENT_PAGE_SIZE: usize = 100;
+
+/// Worklog entries requested per page.
+const WORKLOG_PAGE_SIZE: usize = 100;
+
+/// Issues requested per page of a JQL search.
+const SEARCH_PAGE_SIZE: usize = 100;
+
+/// Downloads below this size are fetched in one piece without a progress bar.
+const SMALL_DOWNLOAD_SIZE: u64 = 1 << 20;
+
+/// Controls which attachments `fetch_attachments` downloads.
+#[derive(Default)]
+pub struct FetchOptions {
+    pub retry_failed: bool,
+    pub created_after: Option<OffsetDateTime>,
+    pub smallest_first: bool,
+    /// Only download the attachments referenced in this comment.
+    pub comment_id: Option<String>,
+    /// Only download the attachments matching this expression.
+    pub filter: Option<Filter>,
+    /// Stop at the first failed download instead of trying the remaining attachments.
+    pub fail_fast: bool,
+    /// Download the thumbnail of image attachments instead of the original.
+    pub thumbnails: bool,
+    /// Also download the attachments referenced in the worklog and changelog of Jira issues but
+    /// missing from their attachment field, into `worklogs/`.
+    pub worklogs: bool,
+    /// Download attachments again even if the issue folder has them unchanged.
+    pub force: bool,
+    /// Only download this many bytes from the start of each attachment, into `<name>.sample`
+    /// next to where the attachment would go. The manifest records no downloads.
+    pub sample: Option<u64>,
+    /// File downloaded as the only attachment with `--url`.
+    pub url: Option<String>,
+}
+
+impl FetchOptions {
+    /// Whether every attachment of the issue is downloaded in full, so a clean fetch may become
+    /// the point `--since-last-run` continues from.
+    pub fn is_complete(&self) -> bool {
+        self.comment_id.is_none() && self.filter.is_none() && !self.thumbnails && self.sample.is_none() && self.url.is_none()
+    }
+}
+
+/// Files and bytes `fetch_attachments` downloaded for an issue and how long it took, for the
+/// summary line and `--report json`.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Transfer {
+    pub files: usize,
+    pub bytes: u64,
+    pub seconds: f64,
+    pub bytes_per_second: f64,
+}
+
+impl Transfer {
+    fn since(files: usize, bytes: u64, started: Instant) -> Self {
+        let seconds = started.elapsed().as_secs_f64();
+        let bytes_per_second = if seconds > 0.0 { bytes as f64 / seconds } else { 0.0 };
+        Transfer { files, bytes, seconds, bytes_per_second }
+    }
+}
+
+impl std::fmt::Display for Transfer {
+    /// `3.40 GiB in 2m11s (26.57 MiB/s)`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elapsed = match self.seconds {
+            seconds if seconds < 60.0 => format!("{:.1}s", seconds),
+            seconds => format!("{}m{:02}s", seconds as u64 / 60, seconds as u64 % 60),
+        };
+        write!(f, "{} in {} ({}/s)", HumanBytes(self.bytes), elapsed, HumanBytes(self.bytes_per_second as u64))
+    }
+}
+
+/// What `fetch_attachments` did for an issue, including the downloads that failed.
+#[derive(Debug, Default)]
+pub struct FetchReport {
+    pub transfer: Transfer,
+    /// Error counting the failed downloads or samples, each reported when it failed.
+    pub failure: Option<anyhow::Error>,
+}
+
+/// What a failed issue or download does to the rest of the run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnError {
+    /// Stop at the first failure.
+    FailFast,
+    /// Go on with the remaining issues and attachments, failing the run at the end.
+    Continue,
+}
+
+/// HTTP client and credentials shared by all requests of a run.
+pub struct Session<'a> {
+    pub(crate) config: &'a Config,
+    source: Source,
+    client: Client,
+    request_id: String,
+    bearer_token: Mutex<Option<String>>,
+    /// Email and API token for basic authentication, from the config or `.netrc`.
+    basic_auth: Option<(String, String)>,
+    http_log: Option<HttpLog>,
+    /// `content_url_rewrite` of the config, compiled.
+    content_rewrite: Option<(Regex, String)>,
+    /// Fraction of each retry delay that is randomized.
+    retry_jitter: f64,
+    /// Time after the start of a download at which it is no longer retried.
+    retry_deadline: Option<Duration>,
+    budget: Arc<Budget>,
+    progress_interval: Duration,
+    /// Downloads run at the same time, shared by the issues of the session.
+    concurrency: Mutex<Concurrency>,
+}
+
+impl<'a> Session<'a> {
+    pub async fn new(config: &'a Config) -> anyhow::Result<Self> {
+        Self::with_source(config, Source::Jira).await
+    }
+
+    pub async fn with_source(config: &'a Config, source: Source) -> anyhow::Result<Self> {
+        let bearer_token = match (&config.token_command, source) {
+            (Some(command), Source::Jira) => Some(run_token_command(command).await?),
+            // The credentials of Jira are not sent to the host of a link, see `with_url_credentials`
+            (_, Source::Url) => None,
+            _ => config.bearer_token.clone(),
+        };
+
+        let basic_auth = match (&config.user_email, &config.api_token) {
+            _ if source == Source::Url => None,
+            (Some(email), Some(api_token)) => Some((email.clone(), api_token.clone())),
+            _ if source == Source::Jira && bearer_token.is_none() => reqwest::Url::parse(&config.jira_url)
+                .ok()
+                .and_then(|url| url.host_str().and_then(netrc::credentials)),
+            _ => None,
+        };
+
+        let content_rewrite = match &config.content_url_rewrite {
+            Some(rewrite) => {
+                let pattern = Regex::new(&rewrite.pattern)
+                    .map_err(|e| anyhow::anyhow!("Invalid pattern `{}` in content_url_rewrite: {}", rewrite.pattern, e))?;
+                Some((pattern, rewrite.replacement.clone()))
+            }
+            None => None,
+        };
+
+        let retry_jitter = config.retry_jitter.unwrap_or(retry::DEFAULT_JITTER);
+        if !(0.0..=1.0).contains(&retry_jitter) {
+            anyhow::bail!("retry_jitter must be between 0 and 1, got {}", retry_jitter);
+        }
+
+        let request_id = uuid::Uuid::new_v4().to_string();
+
+        Ok(Session {
+            config,
+            source,
+            client: create_http_client(config, &request_id)?,
+            request_id,
+            bearer_token: Mutex::new(bearer_token),
+            basic_auth,
+            http_log: None,
+            content_rewrite,
+            retry_jitter,
+            retry_deadline: None,
+            budget: Arc::new(Budget::default()),
+            progress_interval: output::DEFAULT_PROGRESS_INTERVAL,
+            concurrency: Mutex::new(Concurrency::fixed(config.max_concurrent_downloads.unwrap_or(concurrency::DEFAULT_CONCURRENCY))),
+        })
+    }
+
+    pub fn budget(&self) -> &Budget {
+        &self.budget
+    }
+
+    /// Stops downloading once `budget` is used up, see [`Budget`]. Sessions given the same budget
+    /// share it.
+    pub fn with_budget(mut self, budget: Arc<Budget>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Redraws progress bars at most once per `interval`.
+    pub fn with_progress_interval(mut self, interval: Duration) -> Self {
+        self.progress_interval = interval;
+        self
+    }
+
+    /// Adapts the number of downloads running at the same time to the throughput, up to
+    /// `max_concurrent_downloads`, see [`Concurrency`].
+    pub fn with_adaptive_concurrency(mut self) -> Self {
+        let max = self.config.max_concurrent_downloads.unwrap_or(concurrency::DEFAULT_MAX_CONCURRENCY);
+        self.concurrency = Mutex::new(Concurrency::adaptive(max));
+        self
+    }
+
+    /// Stops retrying a download once `deadline` has passed since it started.
+    pub fn with_retry_deadline(mut self, deadline: Duration) -> Self {
+        self.retry_deadline = Some(deadline);
+        self
+    }
+
+    /// Records every request of the session in the file at `path`, see [`HttpLog`].
+    pub fn with_http_log(mut self, path: &Path) -> anyhow::Result<Self> {
+        let mut log = HttpLog::open(path, &user_agent(self.config), &self.request_id)?;
+        if let Some(name) = &self.config.auth_header_name {
+            log.add_secret_header(name);
+        }
+        self.http_log = Some(log);
+
+        Ok(self)
+    }
+
+    /// Signs in to the host of the `--url` link with `token` as bearer token, or else with its
+    /// `.netrc` entry. Credentials in the URL itself are sent by the client.
+    pub fn with_url_credentials(mut self, url: &str, token: Option<String>) -> Self {
+        if token.is_none() {
+            self.basic_auth = reqwest::Url::parse(url).ok().and_then(|url| url.host_str().and_then(netrc::credentials));
+        }
+        self.bearer_token = Mutex::new(token);
+        self
+    }
+
+    /// Sent as `X-Request-Id` with every request of the run, for server-side tracing.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Re-acquires the bearer token via `token_command`. Returns false if no refresh is configured.
+    async fn refresh_token(&self) -> anyhow::Result<bool> {
+        let (Some(command), Source::Jira) = (&self.config.token_command, self.source) else {
+            return Ok(false);
+        };
+
+        let token = run_token_command(command).await?;
+        *self.bearer_token.lock().unwrap() = Some(token);
+
+        Ok(true)
+    }
+}
+
+async fn run_token_command(command: &str) -> anyhow::Result<String> {
+    let output = if cfg!(windows) {
+        tokio::process::Command::new("cmd").args(["/C", command]).output().await?
+    } else {
+        tokio::process::Command::new("sh").args(["-c", command]).output().await?
+    };
+
+    if !output.status.success() {
+        anyhow::bail!("token_command failed with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let token = String::from_utf8(output.stdout)?.trim().to_string();
+    if token.is_empty() {
+        anyhow::bail!("token_command did not print a token");
+    }
+
+    Ok(token)
+}
+
+fn build_request(session: &Session, url: &str, offset: u64) -> anyhow::Result<RequestBuilder> {
+    let mut request = session.client.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+
+    match session.source {
+        Source::Jira => {}
+        Source::Gitlab => {
+            let token = session.config.gitlab.as_ref().and_then(|g| g.token.as_ref());
+            return Ok(match token {
+                Some(token) => request.header("PRIVATE-TOKEN", token),
+                None => request,
+            });
+        }
+        Source::Github => {
+            return Ok(match session.config.github.as_ref().and_then(|g| g.token.as_ref()) {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            });
+        }
+        // Files are fetched with `sftp`, not over HTTP
+        Source::Sftp => return Ok(request),
+        Source::Url => {
+            let token = session.bearer_token.lock().unwrap().clone();
+            return Ok(match (token, &session.basic_auth) {
+                (Some(token), _) => request.bearer_auth(token),
+                (None, Some((user, password))) => request.basic_auth(user, Some(password)),
+                (None, None) => request,
+            });
+        }
+    }
+
+    if let Some(token) = session.bearer_token.lock().unwrap().as_ref() {
+        let config = session.config;
+        let auth_val = match (&config.auth_header_template, &config.auth_header_name) {
+            (Some(template), _) => template.replace("{token}", token),
+            (None, Some(_)) => token.clone(),
+            (None, None) => format!("Bearer {}", token),
+        };
+        let name = match &config.auth_header_name {
+            Some(name) => HeaderName::from_bytes(name.as_bytes()).with_context(|| format!("Invalid auth_header_name {:?}", name))?,
+            None => AUTHORIZATION,
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(&auth_val)?);
+
+        Ok(request.headers(headers))
+    } else if let Some((email, api_token)) = &session.basic_auth {
+        Ok(request.basic_auth(email, Some(api_token)))
+    } else {
+        anyhow::bail!("No authentication configured: set either bearer_token, token_command or user_email+api_token in config, or add the Jira host to ~/.netrc");
+    }
+}
+
+pub(crate) async fn auth_request(session: &Session<'_>, url: &str) -> anyhow::Result<reqwest::Response> {
+    auth_request_from(session, url, 0, &HeaderMap::new()).await
+}
+
+/// Sends an authenticated GET for the bytes starting at `offset`, with the additional `headers`.
+/// If the server rejects the token, it is refreshed once and the request is repeated.
+async fn auth_request_from(session: &Session<'_>, url: &str, offset: u64, headers: &HeaderMap) -> anyhow::Result<reqwest::Response> {
+    let response = send(session, build_request(session, url, offset)?.headers(headers.clone())).await?;
+
+    if response.status() == StatusCode::UNAUTHORIZED && session.refresh_token().await? {
+        return send(session, build_request(session, url, offset)?.headers(headers.clone())).await;
+    }
+
+    Ok(response)
+}
+
+async fn send(session: &Session<'_>, request: RequestBuilder) -> anyhow::Result<reqwest::Response> {
+    let Some(log) = &session.http_log else {
+        return request.send().await.map_err(|err| tls_hint(session, err));
+    };
+
+    let request = request.build()?;
+    let logged = request.try_clone().context("Request cannot be logged")?;
+    let start = Instant::now();
+    let response = session.client.execute(request).await;
+    log.record(&logged, &response, start.elapsed());
+
+    response.map_err(|err| tls_hint(session, err))
+}
+
+/// A handshake failing for the protocol version is only an OpenSSL alert deep in the error, so
+/// with `min_tls_version` set it is pointed out.
+fn tls_hint(session: &Session<'_>, err: reqwest::Error) -> anyhow::Error {
+    let text = format!("{:?}", err).to_lowercase();
+    let protocol = ["protocol version", "unsupported protocol", "no protocols available"].iter().any(|alert| text.contains(alert));
+    match session.config.min_tls_version {
+        Some(version) if err.is_connect() && protocol => anyhow::Error::new(err)
+            .context(format!("Could not connect with TLS {} or newer (min_tls_version), the server may only offer older versions", version)),
+        _ => err.into(),
+    }
+}
+
+fn user_agent(config: &Config) -> String {
+    config.user_agent.clone().unwrap_or_else(|| format!("getlogs/{}", env!("CARGO_PKG_VERSION")))
+}
+
+fn create_http_client(config: &Config, request_id: &str) -> anyhow::Result<Client> {
+    let user_agent = user_agent(config);
+
+    let mut headers = HeaderMap::new();
+    headers.insert("X-Request-Id", HeaderValue::from_str(request_id).expect("UUIDs are valid header values"));
+
+    let mut builder = Client::builder().user_agent(user_agent).default_headers(headers);
+    if let Some(proxy_url) = &config.proxy {
+        builder = builder.proxy(Proxy::all(proxy_url).expect("Could not resolve proxy URL"));
+    }
+    if let Some(family) = config.ip_family {
+        builder = builder.dns_resolver(Arc::new(FamilyResolver(family)));
+    }
+
+    // Parallel downloads from one host share its connections, or streams of one connection
+    builder = match config.http2.unwrap_or_default() {
+        Http2::Auto => builder.http2_adaptive_window(true),
+        Http2::Off => builder.http1_only(),
+        Http2::PriorKnowledge => builder.http2_prior_knowledge().http2_adaptive_window(true),
+    };
+    if let Some(max) = config.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    builder = builder.pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout.unwrap_or(DEFAULT_POOL_IDLE_TIMEOUT)));
+
+    // reqwest cannot require 1.3 with native-tls, `TlsVersion` refuses it
+    if let Some(version) = config.min_tls_version {
+        builder = builder.min_tls_version(match version {
+            TlsVersion::Tls1_0 => tls::Version::TLS_1_0,
+            TlsVersion::Tls1_1 => tls::Version::TLS_1_1,
+            TlsVersion::Tls1_2 => tls::Version::TLS_1_2,
+        });
+    }
+
+    Ok(builder.build().expect("Could not create HTTP client"))
+}
+
+/// Resolves host names with the system resolver and keeps only the addresses of one family,
+/// unless the host has none of that family. For dual-stack servers with one unhealthy family.
+struct FamilyResolver(IpFamily);
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let family = self.0;
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            let preferred: Vec<SocketAddr> = addrs.iter().copied().filter(|addr| match family {
+                IpFamily::Ipv4 => addr.is_ipv4(),
+                IpFamily::Ipv6 => addr.is_ipv6(),
+            }).collect();
+
+            let addrs = if preferred.is_empty() { addrs } else { preferred };
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+pub async fn fetch_attachments(session: &Session<'_>, issue: &str, dest: &Path, options: &FetchOptions) -> anyhow::Result<FetchReport> {
+    let fetched = OffsetDateTime::now_utc().format(&Rfc3339)?;
+    // Issues are fetched one after another, the bytes of the session are this issue's
+    let (started, received) = (Instant::now(), session.budget.used());
+    let (atts, details) = match session.source {
+        Source::Jira => {
+            let listed = list_attachments(session, issue).await?;
+            match &listed.attachments {
+                Ok(atts) if atts.is_empty() => output::info(format!("{} has no attachments", issue)),
+                Ok(atts) if listed.truncated => output::warning(format!(
+                    "{} lists {} attachments, which may not be all of them; the server offers no paginated attachment list", issue, atts.len()
+                )),
+                Ok(_) => {}
+                Err(reason) => output::warning(format!("The attachment field of {} is not visible: {}", issue, reason)),
+            }
+            (listed.attachments.ok(), listed.details)
+        }
+        Source::Gitlab => (Some(source::gitlab_attachments(session, issue).await?), IssueDetails::default()),
+        Source::Github => (Some(source::github_attachments(session, issue).await?), IssueDetails::default()),
+        Source::Sftp => (Some(sftp::attachments(session.config.sftp.as_ref(), issue).await?), IssueDetails::default()),
+        Source::Url => (Some(vec![source::url_attachment(options.url.as_deref().context("No --url given")?)?]), IssueDetails::default()),
+    };
+
+    // Logs attached to worklog entries are not in the attachment field
+    let mut worklog_ids = HashSet::new();
+    let atts = match atts {
+        Some(mut atts) if options.worklogs => {
+            if session.source != Source::Jira {
+                anyhow::bail!("--worklogs is only supported for Jira issues");
+            }
+
+            let referenced = worklog_attachments(session, issue, &atts).await?;
+            worklog_ids.extend(referenced.iter().map(|att| att.id.clone()));
+            atts.extend(referenced);
+            Some(atts)
+        }
+        atts => atts,
+    };
+
+    let manifest_fields = details.fields.clone();
+    let mut manifest = Manifest::load(dest)?;
+    manifest.details = Some(IssueDetails { key: issue.to_string(), fetched: Some(fetched), ..details });
+    manifest.save(dest)?;
+    let mut attempted = 0;
+    let mut skipped = 0;
+    let mut unchanged = 0;
+    let mut filtered = 0;
+    let mut excluded = 0;
+    let mut failed = 0;
+    let exclude_regex = session.config.exclude_regex.as_deref()
+        .map(|regex| RegexBuilder::new(regex).case_insensitive(session.config.case_insensitive.unwrap_or(false)).build())
+        .transpose()
+        .context("Invalid exclude_regex")?;
+
+    if let Some(mut atts) = atts {
+        let sanitizer = session.config.filename_sanitizer.unwrap_or_default();
+        let layout = session.config.download_layout.unwrap_or_default();
+        let local_names: Vec<(&str, Cow<str>)> = atts.iter().map(|att| {
+            let name = match &session.config.download_name_template {
+                Some(template) => Cow::Owned(filename::from_template(template, &att.filename, issue, &manifest_fields)),
+                None => Cow::Borrowed(att.filename.as_str()),
+            };
+            (att.id.as_str(), name)
+        }).collect();
+        let file_names = filename::file_names(sanitizer, local_names.iter().map(|(id, name)| (*id, name.as_ref())));
+
+        if let Some(comment_id) = &options.comment_id {
+            if session.source != Source::Jira {
+                anyhow::bail!("--comment-id is only supported for Jira issues");
+            }
+
+            let (names, ids) = comment_references(session, issue, comment_id).await?;
+            atts.retain(|att| names.contains(&att.filename) || ids.contains(&att.id));
+
+            if atts.is_empty() {
+                output::info(format!("Comment {} does not reference any attachment of {}", comment_id, issue));
+            }
+        }
+
+        if options.smallest_first {
+            atts.sort_by_key(|att| att.size.unwrap_or(u64::MAX));
+        }
+
+        // Pick the attachments to fetch
+        let mut queue = VecDeque::new();
+        for att in &atts {
+            let id = att.id.as_str();
+            let fname = file_names[id].as_str();
+
+            if options.retry_failed && !manifest.is_failed(id) {
+                continue;
+            }
+
+            if let (Some(cutoff), Some(created)) = (options.created_after, att.created_time()) && created <= cutoff {
+                output::detail(format!("Skipping {}, created before the last run", fname));
+                skipped += 1;
+                continue;
+            }
+
+            if let Some(filter) = &options.filter && !filter.matches(att) {
+                output::detail(format!("Skipping {}, not matching the filter", fname));
+                filtered += 1;
+                continue;
+            }
+
+            if exclude_regex.as_ref().is_some_and(|regex| regex.is_match(&att.filename)) {
+                output::detail(format!("Skipping {}, matching exclude_regex", fname));
+                excluded += 1;
+                continue;
+            }
+
+            // Only a file still in the issue folder under the same name is kept
+            let thumbnail = options.thumbnails && att.image_thumbnail().is_some();
+            let previous = manifest.attachments.get(id)
+                .filter(|entry| !options.force && options.sample.is_none() && entry.status == Status::Downloaded && entry.filename == fname && entry.thumbnail == thumbnail)
+                .filter(|entry| entry.path(dest, layout).exists());
+            let validators = previous.map(Validators::of).unwrap_or_default();
+
+            // Without validators, a file of the listed size is taken to be unchanged
+            if let Some(entry) = previous && validators.is_empty() && att.size.is_some() && entry.size == att.size
+                && fs::metadata(entry.path(dest, layout)).is_ok_and(|metadata| Some(metadata.len()) == att.size)
+            {
+                output::detail(format!("Skipping {}, already downloaded with the same size", fname));
+                unchanged += 1;
+                continue;
+            }
+
+            queue.push_back((att, validators));
+        }
+
+        if let Some(bytes) = options.sample {
+            if session.source == Source::Sftp {
+                anyhow::bail!("--sample is not supported for --source sftp");
+            }
+            let atts: Vec<&Attachment> = queue.into_iter().map(|(att, _)| att).collect();
+            let failure = sample_attachments(session, issue, dest, &atts, &file_names, &worklog_ids, bytes).await?;
+            return Ok(FetchReport { transfer: Transfer::since(atts.len(), session.budget.used() - received, started), failure });
+        }
+
+        // Attachments listed without a size are not counted
+        disk::check_space(dest, queue.iter().filter_map(|(att, _)| att.size).sum())?;
+
+        // Fetch them, as many at the same time as the session's concurrency allows
+        let mut running = FuturesUnordered::new();
+        let mut sample = tokio::time::interval(concurrency::SAMPLE_INTERVAL);
+        loop {
+            let limit = session.concurrency.lock().unwrap().limit();
+            while running.len() < limit && let Some((att, validators)) = queue.pop_front() {
+                let fname = file_names[att.id.as_str()].as_str();
+                let out_dir = if worklog_ids.contains(&att.id) { dest.join(WORKLOGS_DIR) } else { layout.download_dir(dest, fname) };
+
+                // Jira does not list the size of thumbnails
+                let thumbnail = options.thumbnails.then(|| att.image_thumbnail()).flatten();
+                let download = match thumbnail {
+                    Some(url) => Cow::Owned(Attachment { content: url.to_string(), size: None, ..att.clone() }),
+                    None => Cow::Borrowed(att),
+                };
+
+                session.budget.check()?;
+                session.budget.reserve(download.size.unwrap_or(0))?;
+
+                fs::create_dir_all(&out_dir)?;
+                attempted += 1;
+                let out_path = out_dir.join(fname);
+                running.push(async move {
+                    let permit = limit::acquire_async().await;
+                    let result = download_attachment(session, &download, &out_path, &validators).await;
+                    drop(permit);
+                    (att, download, out_path, thumbnail.is_some(), result)
+                });
+            }
+
+            let (att, download, out_path, thumbnail, result) = tokio::select! {
+                done = running.next() => match done {
+                    Some(done) => done,
+                    None => break,
+                },
+                _ = sample.tick() => {
+                    session.concurrency.lock().unwrap().sample(session.budget.used(), running.len());
+                    continue;
+                }
+            };
+
+            let id = att.id.as_str();
+            let original_name = att.filename.as_str();
+            let fname = file_names[id].as_str();
+            if let Err(err) = &result && (err.is::<Interrupted>() || err.is::<BudgetExceeded>() || err.is::<DiskError>()) {
+                return result.map(|_| FetchReport::default());
+            }
+            if let Err(err) = &result && is_congestion(err) {
+                session.concurrency.lock().unwrap().back_off();
+            }
+            let (result, validators) = match result {
+                Ok(Fetched::NotModified) => {
+                    output::detail(format!("Skipping {}, not modified since the last download", fname));
+                    attempted -= 1;
+                    unchanged += 1;
+                    continue;
+                }
+                Ok(Fetched::Downloaded(validators)) => (Ok(()), validators),
+                Err(err) => (Err(err), Validators::default()),
+            };
+            let result = result.and_then(|()| Ok(manifest::hash_file(&out_path)?));
+
+            let entry = manifest::Entry {
+                filename: fname.to_string(),
+                original_name: Some(original_name).filter(|&n| n != fname).map(str::to_string),
+                size: download.size,
+                status: if result.is_ok() { Status::Downloaded } else { Status::Failed },
+                error: result.as_ref().err().map(|e| format!("{:#}", e)),
+                sha256: result.as_ref().ok().cloned(),
+                thumbnail,
+                worklog: worklog_ids.contains(id),
+                etag: validators.etag,
+                last_modified: validators.last_modified,
+            };
+            manifest.attachments.insert(id.to_string(), entry);
+            manifest.save(dest)?;
+
+            match result {
+                Ok(_) if thumbnail => output::success(format!("Downloaded the thumbnail of {}", fname)),
+                Ok(_) if fname != original_name => output::success(format!("Downloaded {} as {}", original_name, fname)),
+                Ok(_) => output::success(format!("Downloaded {}", fname)),
+                Err(err) if options.fail_fast => {
+                    return Err(err.context(format!("Failed to download {} of {} (request ID {})", fname, issue, session.request_id)));
+                }
+                Err(err) => {
+                    failed += 1;
+                    output::failure(format!("Failed to download {}: {:#}", fname, err));
+                }
+            }
+        }
+    }
+
+    if skipped > 0 {
+        output::info(format!("Skipped {} attachments created before the last run", skipped));
+    }
+
+    if filtered > 0 {
+        output::info(format!("Skipped {} attachments not matching the filter", filtered));
+    }
+
+    if excluded > 0 {
+        output::info(format!("Skipped {} attachments matching exclude_regex", excluded));
+    }
+
+    if options.retry_failed && attempted == 0 {
+        output::info(format!("No failed downloads recorded for {}", issue));
+    }
+
+    let mut summary = format!("{}: {} downloaded", issue, attempted - failed);
+    if unchanged > 0 {
+        summary += &format!(", {} unchanged", unchanged);
+    }
+    if skipped + filtered + excluded > 0 {
+        summary += &format!(", {} skipped", skipped + filtered + excluded);
+    }
+    if failed > 0 {
+        summary += &format!(", {} failed", failed);
+    }
+    let transfer = Transfer::since(attempted - failed, session.budget.used() - received, started);
+    if transfer.bytes > 0 {
+        summary += &format!(", {}", transfer);
+    }
+    output::summary(summary, failed == 0);
+
+    let failure = (failed > 0).then(|| anyhow::anyhow!(
+        "{} of {} downloads failed for {} (request ID {}), rerun with --retry-failed to retry them",
+        failed, attempted, issue, session.request_id
+    ));
+
+    Ok(FetchReport { transfer, failure })
+}
+
+/// Downloads the first `bytes` of each of `atts` into `<name>.sample`, returning an error
+/// counting those that could not be read.
+async fn sample_attachments(session: &Session<'_>, issue: &str, dest: &Path, atts: &[&Attachment], file_names: &HashMap<String, String>, worklog_ids: &HashSet<String>, bytes: u64) -> anyhow::Result<Option<anyhow::Error>> {
+    let layout = session.config.download_layout.unwrap_or_default();
+    let mut failed = 0;
+    for att in atts {
+        let fname = file_names[att.id.as_str()].as_str();
+        let out_dir = if worklog_ids.contains(&att.id) { dest.join(WORKLOGS_DIR) } else { layout.download_dir(dest, fname) };
+        fs::create_dir_all(&out_dir)?;
+
+        let out_path = out_dir.join(format!("{}{}", fname, manifest::SAMPLE_SUFFIX));
+        match download_sample(session, &att.content, &out_path, bytes).await.map_err(|err| disk::classify(err, &out_dir)) {
+            Ok(written) => output::success(format!("Sampled {} of {} bytes of {}", written, att.size.map_or("?".to_string(), |size| size.to_string()), fname)),
+            Err(err) if err.is::<Interrupted>() || err.is::<BudgetExceeded>() || err.is::<DiskError>() => return Err(err),
+            Err(err) => {
+                failed += 1;
+                output::failure(format!("Failed to sample {}: {:#}", fname, err));
+            }
+        }
+    }
+
+    let mut summary = format!("{}: {} sampled", issue, atts.len() - failed);
+    if failed > 0 {
+        summary += &format!(", {} failed", failed);
+    }
+    output::summary(summary, failed == 0);
+
+    Ok((failed > 0).then(|| anyhow::anyhow!("{} of {} samples failed for {} (request ID {})", failed, atts.len(), issue, session.request_id)))
+}
+
+/// Writes the first `bytes` of `url` to `out_path` with a range request, returning how many were
+/// written. Servers that ignore the range send the whole file, which is cut off after `bytes`.
+async fn download_sample(session: &Session<'_>, url: &str, out_path: &Path, bytes: u64) -> anyhow::Result<u64> {
+    let mut headers = HeaderMap::new();
+    headers.insert(RANGE, HeaderValue::from_str(&format!("bytes=0-{}", bytes.saturating_sub(1)))?);
+    let response = auth_request_from(session, url, 0, &headers).await?.error_for_status()?;
+
+    let mut file = fs::File::create(out_path)?;
+    let mut written = 0;
+    let mut stream = response.bytes_stream();
+    while written < bytes {
+        let chunk = tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = interrupt::wait() => {
+                drop(file);
+                fs::remove_file(out_path)?;
+                return Err(Interrupted.into());
+            }
+        };
+        let Some(chunk) = chunk.transpose()? else {
+            break;
+        };
+
+        let chunk = &chunk[..chunk.len().min((bytes - written) as usize)];
+        file.write_all(chunk)?;
+        written += chunk.len() as u64;
+        session.budget.add(chunk.len() as u64);
+    }
+
+    Ok(written)
+}
+
+/// Downloads all attachments of `issue` into memory, returning file names and contents. Prints
+/// nothing; fails if the attachment field of the issue is not visible.
+pub async fn fetch_attachments_to_memory(config: &Config, issue: &str) -> anyhow::Result<Vec<(String, Bytes)>> {
+    let session = Session::new(config).await?;
+    let atts = list_attachments(&session, issue).await?.attachments
+        .map_err(|reason| anyhow::anyhow!("The attachment field of {} is not visible: {}", issue, reason))?;
+    let mut files = Vec::new();
+
+    for att in atts {
+        let response = auth_request(&session, &att.content).await?.error_for_status()?;
+        files.push((att.filename, response.bytes().await?));
+    }
+
+    Ok(files)
+}
+
+/// The attachment field of a Jira issue read by [`list_attachments`].
+struct AttachmentList {
+    /// The attachments, or why the field is not visible.
+    attachments: Result<Vec<Attachment>, String>,
+    details: IssueDetails,
+    /// The list may be cut off at the inline limit, the server offers no paginated list.
+    truncated: bool,
+}
+
+/// Fetches the attachment field of `issue` and the fields describing the issue, leaving it to
+/// the caller to tell the user about missing or truncated lists.
+/// The fields named by `download_name_template` are requested as well.
+async fn list_attachments(session: &Session<'_>, issue: &str) -> anyhow::Result<AttachmentList> {
+    let name_fields = session.config.download_name_template.as_deref().map(filename::template_fields).unwrap_or_default();
+    let mut url = format!("{}/rest/api/2/issue/{}?fields=attachment,summary,status,assignee", session.config.jira_url, issue);
+    for field in &name_fields {
+        url += &format!(",{}", utf8_percent_encode(field, NON_ALPHANUMERIC));
+    }
+
+    // Fetch the attachment field
+    let response = auth_request(session, &url).await?;
+    let status = response.status();
+    let json: serde_json::Value = response.json().await.unwrap_or_default();
+
+    if !status.is_success() {
+        anyhow::bail!("Could not fetch {} ({}): {}", issue, status, error_messages(&json));
+    }
+
+    let fields = IssueResponse::deserialize(&json).with_context(|| format!("Unexpected attachment list of {}", issue))?.fields;
+    let details = IssueDetails {
+        key: issue.to_string(),
+        summary: fields.summary,
+        status: fields.status.map(|status| status.name),
+        assignee: fields.assignee.map(|user| user.display_name),
+        fetched: None,
+        fields: name_fields.into_iter().filter_map(|field| {
+            let text = field_text(&json["fields"][field.as_str()])?;
+            Some((field, text))
+        }).collect(),
+    };
+
+    if let Some(atts) = fields.attachment {
+        let (atts, truncated) = if atts.len() >= INLINE_ATTACHMENT_LIMIT { all_attachments(session, issue, atts).await? } else { (atts, false) };
+
+        let atts = atts.into_iter().map(|att| with_content_url(session, att)).collect::<anyhow::Result<_>>()?;
+        return Ok(AttachmentList { attachments: Ok(atts), details, truncated });
+    }
+
+    let reason = missing_attachments_reason(session, &json).await;
+
+    Ok(AttachmentList { attachments: Err(reason), details, truncated: false })
+}
+
+/// Text of a Jira field value: strings and numbers as they are, the name of objects like
+/// components or users, and the items of lists joined by commas.
+fn field_text(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(text) => Some(text.clone()),
+        serde_json::Value::Number(number) => Some(number.to_string()),
+        serde_json::Value::Bool(value) => Some(value.to_string()),
+        serde_json::Value::Array(items) => {
+            let items: Vec<String> = items.iter().filter_map(field_text).collect();
+            (!items.is_empty()).then(|| items.join(", "))
+        }
+        serde_json::Value::Object(object) => ["value", "name", "displayName", "key"].iter().find_map(|key| object.get(*key)?.as_str().map(str::to_string)),
+        serde_json::Value::Null => None,
+    }
+}
+
+/// `att` with its `content` and `thumbnail` URLs passed through `content_url_rewrite` and
+/// resolved against `jira_url` if relative, for proxies that hand out internal or relative links.
+fn with_content_url(session: &Session<'_>, mut att: Attachment) -> anyhow::Result<Attachment> {
+    let resolve = |url: &str| -> anyhow::Result<String> {
+        let url = match &session.content_rewrite {
+            Some((pattern, replacement)) => pattern.replace(url, replacement.as_str()).into_owned(),
+            None => url.to_string(),
+        };
+
+        if reqwest::Url::parse(&url).is_ok() {
+            return Ok(url);
+        }
+
+        let base = reqwest::Url::parse(&format!("{}/", session.config.jira_url.trim_end_matches('/')))
+            .with_context(|| format!("Invalid jira_url {}", session.config.jira_url))?;
+        Ok(base.join(&url).with_context(|| format!("Invalid content URL {} of {}", url, att.filename))?.to_string())
+    };
+
+    let content = resolve(&att.content)?;
+    let thumbnail = att.thumbnail.as_deref().map(resolve).transpose()?;
+    att.content = content;
+    att.thumbnail = thumbnail;
+
+    Ok(att)
+}
+
+/// Completes an inline attachment list that may have been truncated from the paginated
+/// attachments endpoint. Keeps the inline list if the server has no such endpoint, returning
+/// `true` along with it.
+async fn all_attachments(session: &Session<'_>, issue: &str, inline: Vec<Attachment>) -> anyhow::Result<(Vec<Attachment>, bool)> {
+    let mut ids: HashSet<String> = inline.iter().map(|a| a.id.clone()).collect();
+    let mut atts = inline;
+
+    for start in (0..).step_by(ATTACHMENT_PAGE_SIZE) {
+        interrupt::check()?;
+
+        let url = format!("{}/rest/api/2/issue/{}/attachments?startAt={}&maxResults={}", session.config.jira_url, issue, start, ATTACHMENT_PAGE_SIZE);
+        let response = auth_request(session, &url).await?;
+        let status = response.status();
+
+        if status == StatusCode::NOT_FOUND && start == 0 {
+            return Ok((atts, true));
+        }
+
+        let page: serde_json::Value = response.json().await.unwrap_or_default();
+        if !status.is_success() {
+            anyhow::bail!("Could not list the attachments of {} ({}): {}", issue, status, error_messages(&page));
+        }
+
+        let (values, is_last) = AttachmentPage::deserialize(&page)
+            .with_context(|| format!("Unexpected attachment list of {}", issue))?
+            .into_parts();
+
+        let count = values.len();
+        atts.extend(values.into_iter().filter(|a| ids.insert(a.id.clone())));
+
+        if count < ATTACHMENT_PAGE_SIZE || is_last {
+            break;
+        }
+    }
+
+    Ok((atts, false))
+}
+
+/// Keys of all issues matching `jql`, paging through the search results.
+pub async fn search_issues(session: &Session<'_>, jql: &str) -> anyhow::Result<Vec<String>> {
+    let encoded = utf8_percent_encode(jql, NON_ALPHANUMERIC);
+    let mut keys = Vec::new();
+
+    for start in (0..).step_by(SEARCH_PAGE_SIZE) {
+        interrupt::check()?;
+
+        let url = format!(
+            "{}/rest/api/2/search?jql={}&startAt={}&maxResults={}&fields=key",
+            session.config.jira_url, encoded, start, SEARCH_PAGE_SIZE
+        );
+        let response = auth_request(session, &url).await?;
+        let status = response.status();
+        let page: serde_json::Value = response.json().await.unwrap_or_default();
+
+        if !status.is_success() {
+            anyhow::bail!("Searching for `{}` failed ({}): {}", jql, status, error_messages(&page));
+        }
+
+        let issues = page["issues"].as_array().cloned().unwrap_or_default();
+        keys.extend(issues.iter().filter_map(|issue| issue["key"].as_str().map(str::to_string)));
+
+        let total = page["total"].as_u64().unwrap_or(0) as usize;
+        if issues.is_empty() || start + issues.len() >= total {
+            break;
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Display name of the user the session authenticates as, to check the credentials.
+pub async fn current_user(session: &Session<'_>) -> anyhow::Result<String> {
+    let url = format!("{}/rest/api/2/myself", session.config.jira_url);
+    let response = auth_request(session, &url).await?;
+    let status = response.status();
+    let json: serde_json::Value = response.json().await.unwrap_or_default();
+
+    if !status.is_success() {
+        let messages = error_messages(&json);
+        let messages = if messages.is_empty() { String::new() } else { format!(": {}", messages) };
+        anyhow::bail!("Signing in to {} failed ({}){}", session.config.jira_url, status, messages);
+    }
+
+    let name = json["displayName"].as_str().or(json["name"].as_str()).or(json["emailAddress"].as_str());
+    Ok(name.unwrap_or("unknown user").to_string())
+}
+
+/// JQL of the saved filter `id`.
+pub async fn filter_jql(session: &Session<'_>, id: &str) -> anyhow::Result<String> {
+    let url = format!("{}/rest/api/2/filter/{}", session.config.jira_url, id);
+    let response = auth_request(session, &url).await?;
+    let status = response.status();
+    let json: serde_json::Value = response.json().await.unwrap_or_default();
+
+    if !status.is_success() {
+        anyhow::bail!("Could not fetch filter {} ({}): {}", id, status, error_messages(&json));
+    }
+
+    match json["jql"].as_str() {
+        Some(jql) => Ok(jql.to_string()),
+        None => anyhow::bail!("Filter {} has no JQL", id),
+    }
+}
+
+/// Explains why Jira omitted the attachment field from an issue response.
+async fn missing_attachments_reason(session: &Session<'_>, json: &serde_json::Value) -> String {
+    let messages = error_messages(json);
+    if !messages.is_empty() {
+        return messages;
+    }
+
+    let url = format!("{}/rest/api/2/attachment/meta", session.config.jira_url);
+    if let Ok(response) = auth_request(session, &url).await {
+        let status = response.status();
+        let meta: serde_json::Value = response.json().await.unwrap_or_default();
+
+        if status.is_success() && meta["enabled"] == false {
+            return "attachments are disabled on this Jira instance".to_string();
+        }
+
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            return "the credentials are not allowed to access attachments".to_string();
+        }
+    }
+
+    "the account probably lacks permission to view attachments. It needs the Browse Projects \
+     permission on the project, and API tokens on Jira Cloud need the read:jira-work scope".to_string()
+}
+
+/// Joins the `errorMessages` and `errors` of a Jira error response.
+fn error_messages(json: &serde_json::Value) -> String {
+    let messages = json["errorMessages"].as_array().into_iter().flatten();
+    let errors = json["errors"].as_object().into_iter().flat_map(|e| e.values());
+
+    messages.chain(errors).filter_map(|m| m.as_str()).collect::<Vec<_>>().join("; ")
+}
+
+/// File names and attachment IDs referenced in a comment, either through wiki markup like
+/// `[^trace.zip]` and `!screenshot.png|thumbnail!` or through attachment URLs.
+async fn comment_references(session: &Session<'_>, issue: &str, comment_id: &str) -> anyhow::Result<(HashSet<String>, HashSet<String>)> {
+    let url = format!("{}/rest/api/2/issue/{}/comment/{}", session.config.jira_url, issue, comment_id);
+    let response = auth_request(session, &url).await?.error_for_status()
+        .with_context(|| format!("Could not fetch comment {} of {}", comment_id, issue))?;
+    let json: serde_json::Value = response.json().await?;
+
+    Ok(attachment_references(json["body"].as_str().unwrap_or_default()))
+}
+
+/// Names of the attachments that the wiki markup `text` refers to by `[^file]` and `!file!`,
+/// and IDs of those it links to.
+fn attachment_references(text: &str) -> (HashSet<String>, HashSet<String>) {
+    let name_regex = Regex::new(r"\[\^([^\]]+)\]|!([^!|\s]+)(?:\|[^!]*)?!").unwrap();
+    let id_regex = Regex::new(r"/(?:secure/attachment|attachment/content|attachment)/(\d+)").unwrap();
+
+    let names = name_regex.captures_iter(text)
+        .filter_map(|c| c.get(1).or(c.get(2)))
+        .map(|m| m.as_str().to_string())
+        .collect();
+    let ids = id_regex.captures_iter(text).map(|c| c[1].to_string()).collect();
+
+    (names, ids)
+}
+
+/// Attachments that are not among `listed` but linked from a worklog comment of `issue`, or
+/// added according to its changelog and not removed again, as some projects attach logs to
+/// worklog entries. Links to attachments that are gone are reported and skipped.
+async fn worklog_attachments(session: &Session<'_>, issue: &str, listed: &[Attachment]) -> anyhow::Result<Vec<Attachment>> {
+    let mut ids = BTreeSet::new();
+
+    let mut start = 0;
+    loop {
+        let url = format!("{}/rest/api/2/issue/{}/worklog?startAt={}&maxResults={}", session.config.jira_url, issue, start, WORKLOG_PAGE_SIZE);
+        let json: serde_json::Value = auth_request(session, &url).await?.error_for_status()
+            .with_context(|| format!("Could not fetch the worklog of {}", issue))?
+            .json().await?;
+
+        let worklogs = json["worklogs"].as_array().map(Vec::as_slice).unwrap_or_default();
+        for worklog in worklogs {
+            ids.extend(attachment_references(worklog["comment"].as_str().unwrap_or_default()).1);
+        }
+
+        start += worklogs.len();
+        if worklogs.is_empty() || start as u64 >= json["total"].as_u64().unwrap_or(0) {
+            break;
+        }
+    }
+
+    let url = format!("{}/rest/api/2/issue/{}?fields=none&expand=changelog", session.config.jira_url, issue);
+    let json: serde_json::Value = auth_request(session, &url).await?.error_for_status()
+        .with_context(|| format!("Could not fetch the changelog of {}", issue))?
+        .json().await?;
+    let histories = json["changelog"]["histories"].as_array().map(Vec::as_slice).unwrap_or_default();
+    for item in histories.iter().flat_map(|history| history["items"].as_array().map(Vec::as_slice).unwrap_or_default()) {
+        if !item["field"].as_str().is_some_and(|field| field.eq_ignore_ascii_case("attachment")) {
+            continue;
+        }
+        if let Some(removed) = item["from"].as_str() {
+            ids.remove(removed);
+        }
+        if let Some(added) = item["to"].as_str() {
+            ids.insert(added.to_string());
+        }
+    }
+
+    let mut atts = Vec::new();
+    for id in ids.into_iter().filter(|id| listed.iter().all(|att| att.id != *id)) {
+        let url = format!("{}/rest/api/2/attachment/{}", session.config.jira_url, id);
+        let response = auth_request(session, &url).await?;
+        if !response.status().is_success() {
+            output::warning(format!("Attachment {} referenced in the worklog of {} is not available ({})", id, issue, response.status()));
+            continue;
+        }
+
+        let att: Attachment = response.json().await.with_context(|| format!("Unexpected attachment {}", id))?;
+        atts.push(with_content_url(session, att)?);
+    }
+
+    if !atts.is_empty() {
+        output::info(format!("Found {} attachments in the worklog and changelog of {}", atts.len(), issue));
+    }
+
+    Ok(atts)
+}
+
+/// Parses timestamps like `2024-01-31T12:34:56.789+0000` as returned by Jira.
+pub(crate) fn parse_jira_time(value: &str) -> Option<OffsetDateTime> {
+    let format = format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond][offset_hour sign:mandatory][offset_minute]");
+
+    OffsetDateTime::parse(value, &format).ok()
+}
+
+/// `ETag` and `Last-Modified` of a downloaded file. Sent back as `If-None-Match` and
+/// `If-Modified-Since` when it is fetched again, so the server can answer 304 if it did not
+/// change.
+#[derive(Debug, Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    fn of(entry: &manifest::Entry) -> Self {
+        Validators { etag: entry.etag.clone(), last_modified: entry.last_modified.clone() }
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| headers.get(name).and_then(|value: &HeaderValue| value.to_str().ok()).map(str::to_string);
+        Validators { etag: header(ETAG), last_modified: header(LAST_MODIFIED) }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = self.etag.as_deref().and_then(|etag| HeaderValue::from_str(etag).ok()) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = self.last_modified.as_deref().and_then(|date| HeaderValue::from_str(date).ok()) {
+            headers.insert(IF_MODIFIED_SINCE, date);
+        }
+
+        headers
+    }
+}
+
+/// What came of fetching an attachment.
+enum Fetched {
+    Downloaded(Validators),
+    /// The server answered a conditional request with 304, the file at hand is current.
+    NotModified,
+}
+
+/// A download that ended before the size Jira announced for the attachment.
+#[derive(Debug)]
+struct IncompleteDownload {
+    received: u64,
+    expected: u64,
+}
+
+impl std::fmt::Display for IncompleteDownload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Received {} bytes, expected {}", self.received, self.expected)
+    }
+}
+
+impl std::error::Error for IncompleteDownload {}
+
+/// Downloads the attachment `att`. While it is still being uploaded, Jira lists it but its
+/// content 404s or is cut short; this is retried with backoff for up to [`UPLOAD_WAIT`], or
+/// until the retry deadline of the session.
+async fn download_attachment(session: &Session<'_>, att: &Attachment, out_path: &Path, validators: &Validators) -> anyhow::Result<Fetched> {
+    if session.source == Source::Sftp {
+        let received = sftp::download(session.config.sftp.as_ref(), &att.content, out_path, att.size).await
+            .map_err(|err| disk::classify(err, out_path.parent().unwrap_or(out_path)))?;
+        session.budget.add(received);
+        return Ok(Fetched::Downloaded(Validators::default()));
+    }
+
+    let deadline = session.retry_deadline.and_then(|d| Instant::now().checked_add(d));
+    let mut backoff = Backoff::new(UPLOAD_POLL_START, UPLOAD_POLL_MAX, UPLOAD_WAIT)
+        .with_jitter(session.retry_jitter)
+        .with_deadline(deadline);
+
+    loop {
+        let result = download_file(session, &att.content, out_path, att.size, deadline, validators).await
+            .map_err(|err| disk::classify(err, out_path.parent().unwrap_or(out_path)));
+        let Err(err) = &result else {
+            return result;
+        };
+
+        if !upload_pending(session, att, err).await {
+            return result;
+        }
+
+        let Some(delay) = backoff.next_delay() else {
+            return result.context("Attachment was still incomplete after waiting for the upload");
+        };
+
+        output::warning(format!("{} is still being uploaded ({:#}), retrying in {}s", att.filename, err, delay.as_secs_f32()));
+        retry::sleep(delay).await?;
+    }
+}
+
+/// Whether `err` suggests that fewer downloads should run at the same time: a timeout, a
+/// connection that could not be made or a server asking to slow down.
+fn is_congestion(err: &anyhow::Error) -> bool {
+    err.chain().filter_map(|cause| cause.downcast_ref::<reqwest::Error>()).any(|err| {
+        err.is_timeout() || err.is_connect() || matches!(err.status(), Some(StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE))
+    })
+}
+
+/// Whether `err` looks like the attachment's upload has not finished yet, as opposed to an
+/// attachment that is gone: the download was short, or 404'd while the attachment still exists.
+async fn upload_pending(session: &Session<'_>, att: &Attachment, err: &anyhow::Error) -> bool {
+    if err.is::<IncompleteDownload>() {
+        return true;
+    }
+
+    let not_found = err.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) == Some(StatusCode::NOT_FOUND);
+    if !not_found || session.source != Source::Jira {
+        return false;
+    }
+
+    let url = format!("{}/rest/api/2/attachment/{}", session.config.jira_url, att.id);
+    match auth_request(session, &url).await {
+        Ok(response) => response.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+/// Streams `url` into `<out_path>.part` and renames it to `out_path` once it is complete, so a
+/// file at its final path is always whole. If the connection drops or the token expires
+/// mid-stream, the download is resumed from the current offset with a range request instead of
+/// starting over, after a backoff that ends at `deadline`. A `.part` left by Ctrl-C, a crash or
+/// an exhausted budget is resumed by the next run. Small files, judged by `size` or the response
+/// length, are written in one go instead. A new download is conditional on `validators` from the
+/// last one, if any.
+async fn download_file(session: &Session<'_>, url: &str, out_path: &Path, size: Option<u64>, deadline: Option<Instant>, validators: &Validators) -> anyhow::Result<Fetched> {
+    let part_path = PathBuf::from(format!("{}{}", out_path.display(), manifest::PART_SUFFIX));
+    let mut offset = 0;
+    let mut attempts = 0;
+    let mut backoff = Backoff::new(RESUME_DELAY_START, RESUME_DELAY_MAX, Duration::MAX)
+        .with_jitter(session.retry_jitter)
+        .with_deadline(deadline);
+
+    // Earlier versions kept interrupted downloads as `.partial`
+    let legacy_path = PathBuf::from(format!("{}.partial", out_path.display()));
+    if legacy_path.exists() && !part_path.exists() {
+        fs::rename(&legacy_path, &part_path)?;
+    }
+
+    let mut file = if part_path.exists() {
+        let mut file = OpenOptions::new().write(true).open(&part_path)?;
+        offset = file.seek(SeekFrom::End(0))?;
+        file
+    } else {
+        fs::File::create(&part_path)?
+    };
+
+    let label = out_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut progress_bar = output::Progress::new(label, session.progress_interval)?;
+
+    // A resumed download continues the file of the last attempt, whatever the server has now
+    let mut conditional = if offset == 0 { validators.conditional_headers() } else { HeaderMap::new() };
+    let mut received;
+
+    'resume: loop {
+        let response = auth_request_from(session, url, offset, &conditional).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            progress_bar.finish_and_clear();
+            drop(file);
+            fs::remove_file(&part_path)?;
+            return Ok(Fetched::NotModified);
+        }
+
+        let response = response.error_for_status()?;
+        received = Validators::from_headers(response.headers());
+        conditional.clear();
+
+        if offset > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            // The server ignored the range request and sends the whole file again
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            offset = 0;
+        }
+
+        let length = response.content_length().or(size);
+        if offset == 0 && length.is_some_and(|l| l < SMALL_DOWNLOAD_SIZE) {
+            let bytes = tokio::select! {
+                bytes = response.bytes() => bytes,
+                _ = interrupt::wait() => {
+                    drop(file);
+                    fs::remove_file(&part_path)?;
+                    return Err(Interrupted.into());
+                }
+            };
+
+            match bytes {
+                Ok(bytes) => {
+                    file.write_all(&bytes)?;
+                    session.budget.add(bytes.len() as u64);
+                    break 'resume;
+                }
+                Err(err) if attempts < MAX_RESUME_ATTEMPTS => {
+                    attempts += 1;
+                    let Some(delay) = backoff.next_delay() else {
+                        return Err(err).context("Gave up retrying at the retry deadline");
+                    };
+                    session.concurrency.lock().unwrap().back_off();
+                    output::warning(format!("Connection lost ({}), retrying in {:.1}s", err, delay.as_secs_f32()));
+                    retry::sleep(delay).await?;
+                    continue 'resume;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        progress_bar.set_length(offset + response.content_length().unwrap_or(0));
+        progress_bar.set_position(offset);
+
+        let mut stream = response.bytes_stream();
+
+        loop {
+            let chunk = tokio::select! {
+                chunk = stream.next() => chunk,
+                _ = interrupt::wait() => {
+                    progress_bar.abandon();
+
+                    output::warning(format!("Kept partial download as {}", part_path.display()));
+                    return Err(Interrupted.into());
+                }
+            };
+
+            let chunk = match chunk {
+                Some(Ok(chunk)) => chunk,
+                Some(Err(err)) if attempts < MAX_RESUME_ATTEMPTS => {
+                    attempts += 1;
+                    let Some(delay) = backoff.next_delay() else {
+                        progress_bar.abandon();
+                        return Err(err).context("Gave up resuming at the retry deadline");
+                    };
+                    session.concurrency.lock().unwrap().back_off();
+                    progress_bar.println(format!("Connection lost ({}), resuming at byte {} in {:.1}s", err, offset, delay.as_secs_f32()));
+                    if let Err(err) = retry::sleep(delay).await {
+                        progress_bar.abandon();
+                        output::warning(format!("Kept partial download as {}", part_path.display()));
+                        return Err(err);
+                    }
+                    continue 'resume;
+                }
+                Some(Err(err)) => return Err(err.into()),
+                None => break 'resume,
+            };
+
+            progress_bar.inc(chunk.len() as u64);
+            offset += chunk.len() as u64;
+            session.budget.add(chunk.len() as u64);
+            io::copy(&mut Cursor::new(chunk), &mut file)?;
+
+            if let Err(err) = session.budget.check() {
+                progress_bar.abandon();
+                output::warning(format!("Kept partial download as {}", part_path.display()));
+                return Err(err);
+            }
+        }
+    }
+
+    progress_bar.finish_and_clear();
+
+    file.flush()?;
+    let written = file.metadata()?.len();
+    drop(file);
+
+    if let Some(expected) = size && written != expected {
+        fs::remove_file(&part_path)?;
+        return Err(IncompleteDownload { received: written, expected }.into());
+    }
+
+    fs::rename(&part_path, out_path)?;
+
+    Ok(Fetched::Downloaded(received))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(rewrite: serde_json::Value) -> Config {
+        serde_json::from_value(serde_json::json!({
+            "default_path": std::env::temp_dir(),
+            "jira_url": "https://jira.example.com/base/",
+            "bearer_token": "unused",
+            "logfile_regex": r".*\.log$",
+            "content_url_rewrite": rewrite,
+        }))
+        .unwrap()
+    }
+
+    fn attachment(content: &str, thumbnail: Option<&str>) -> Attachment {
+        serde_json::from_value(serde_json::json!({ "id": 1, "filename": "main.log", "content": content, "thumbnail": thumbnail })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rewrites_content_and_thumbnail_urls() {
+        let config = config(serde_json::json!({ "pattern": r"^http://jira-internal:8080/(.*)$", "replacement": "https://jira.example.com/base/$1" }));
+        let session = Session::new(&config).await.unwrap();
+
+        let att = with_content_url(&session, attachment("http://jira-internal:8080/secure/attachment/1/main.log", Some("http://jira-internal:8080/thumbnail/1"))).unwrap();
+        assert_eq!(att.content, "https://jira.example.com/base/secure/attachment/1/main.log");
+        assert_eq!(att.thumbnail.as_deref(), Some("https://jira.example.com/base/thumbnail/1"));
+
+        // URLs the pattern does not match are kept
+        let att = with_content_url(&session, attachment("https://cdn.example.com/1/main.log", None)).unwrap();
+        assert_eq!(att.content, "https://cdn.example.com/1/main.log");
+        assert_eq!(att.thumbnail, None);
+    }
+
+    #[tokio::test]
+    async fn resolves_relative_urls_against_jira_url() {
+        let plain = config(serde_json::Value::Null);
+        let session = Session::new(&plain).await.unwrap();
+
+        assert_eq!(with_content_url(&session, attachment("secure/attachment/1/main.log", None)).unwrap().content, "https://jira.example.com/base/secure/attachment/1/main.log");
+        assert_eq!(with_content_url(&session, attachment("/secure/attachment/1/main.log", None)).unwrap().content, "https://jira.example.com/secure/attachment/1/main.log");
+
+        // A rewrite may also produce a relative link
+        let stripping = config(serde_json::json!({ "pattern": r"^http://jira-internal:8080/", "replacement": "" }));
+        let session = Session::new(&stripping).await.unwrap();
+        assert_eq!(with_content_url(&session, attachment("http://jira-internal:8080/secure/attachment/1/main.log", None)).unwrap().content, "https://jira.example.com/base/secure/attachment/1/main.log");
+    }
+
+    #[tokio::test]
+    async fn refuses_an_invalid_rewrite_pattern() {
+        let config = config(serde_json::json!({ "pattern": "(", "replacement": "" }));
+
+        let err = Session::new(&config).await.err().unwrap();
+        assert!(err.to_string().starts_with("Invalid pattern `(` in content_url_rewrite"), "{}", err);
+    }
+}
@@ -0,0 +1,287 @@
+//! FIBEX descriptions of non-verbose DLT messages. A non-verbose payload is only a message ID
+//! followed by raw argument values; the FIBEX frame with that ID lists the static text parts and
+//! argument types needed to render it.
+
+use std::{collections::HashMap, fs, path::Path};
+use anyhow::Context;
+use roxmltree::{Document, Node};
+use crate::dlt::{self, Argument, LogLevel};
+
+pub struct Fibex {
+    frames: HashMap<u32, Frame>,
+}
+
+/// A non-verbose message type.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub app_id: Option<String>,
+    pub context_id: Option<String>,
+    pub level: Option<LogLevel>,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Value(ValueType),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ValueType {
+    Bool,
+    Unsigned(usize),
+    Signed(usize),
+    Float(usize),
+    String,
+}
+
+impl Fibex {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let xml = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+
+        Self::parse(&xml).with_context(|| format!("parsing FIBEX {}", path.display()))
+    }
+
+    pub fn parse(xml: &str) -> anyhow::Result<Self> {
+        let doc = Document::parse(xml)?;
+        let elements = |name: &'static str| doc.descendants().filter(move |n| n.tag_name().name() == name);
+
+        let codings: HashMap<&str, ValueType> = elements("CODING")
+            .filter_map(|c| {
+                let coded = child(c, "CODED-TYPE")?;
+                let base = coded.attributes().find(|a| a.name() == "BASE-DATA-TYPE")?.value();
+                Some((c.attribute("ID")?, value_type(base)?))
+            })
+            .collect();
+
+        // Signals usually reference a coding, the common ones are also recognizable by their ID
+        let signals: HashMap<&str, ValueType> = elements("SIGNAL")
+            .filter_map(|s| {
+                let id = s.attribute("ID")?;
+                let coding = child(s, "CODING-REF").and_then(|r| r.attribute("ID-REF")).and_then(|r| codings.get(r));
+                Some((id, coding.copied().or_else(|| value_type(id))?))
+            })
+            .collect();
+
+        let pdus: HashMap<&str, Part> = elements("PDU")
+            .filter_map(|p| {
+                let id = p.attribute("ID")?;
+                let signal = p.descendants().find(|n| n.tag_name().name() == "SIGNAL-REF").and_then(|r| r.attribute("ID-REF"));
+
+                let part = match signal {
+                    Some(signal) => Part::Value(*signals.get(signal)?),
+                    None => Part::Text(child(p, "DESC").and_then(|d| d.text()).unwrap_or_default().to_string()),
+                };
+                Some((id, part))
+            })
+            .collect();
+
+        let mut frames = HashMap::new();
+        for frame in elements("FRAME") {
+            let Some(id) = frame.attribute("ID").and_then(message_id) else {
+                continue;
+            };
+
+            let mut instances: Vec<(u32, &str)> = frame.descendants()
+                .filter(|n| n.tag_name().name() == "PDU-INSTANCE")
+                .filter_map(|i| {
+                    let pdu = child(i, "PDU-REF")?.attribute("ID-REF")?;
+                    let sequence = child(i, "SEQUENCE-NUMBER").and_then(|s| s.text()).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
+                    Some((sequence, pdu))
+                })
+                .collect();
+            instances.sort_by_key(|&(sequence, _)| sequence);
+
+            let extension = |name| frame.descendants().find(|n| n.tag_name().name() == name).and_then(|n| n.text()).map(|t| t.trim().to_string());
+
+            frames.insert(id, Frame {
+                app_id: extension("APPLICATION_ID"),
+                context_id: extension("CONTEXT_ID"),
+                level: extension("MESSAGE_INFO").as_deref().and_then(log_level),
+                parts: instances.iter().filter_map(|(_, pdu)| pdus.get(pdu).cloned()).collect(),
+            });
+        }
+
+        Ok(Fibex { frames })
+    }
+
+    pub fn frame(&self, message_id: u32) -> Option<&Frame> {
+        self.frames.get(&message_id)
+    }
+}
+
+impl Frame {
+    /// Renders the text parts and the argument values read from `payload`. Data that does not
+    /// fit the description is appended as hex.
+    pub fn decode(&self, payload: &[u8], big_endian: bool) -> String {
+        let mut cursor = dlt::Cursor::new(payload, big_endian);
+        let mut parts = Vec::with_capacity(self.parts.len());
+
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => parts.push(text.clone()),
+                Part::Value(value_type) => match read_value(&mut cursor, *value_type) {
+                    Ok(argument) => parts.push(argument.to_string()),
+                    Err(_) => break,
+                },
+            }
+        }
+
+        let rest = cursor.rest();
+        if !rest.is_empty() {
+            parts.push(dlt::hex(rest));
+        }
+
+        parts.join(" ")
+    }
+}
+
+fn read_value(cursor: &mut dlt::Cursor<'_>, value_type: ValueType) -> std::io::Result<Argument> {
+    Ok(match value_type {
+        ValueType::Bool => Argument::Bool(cursor.uint(1)? != 0),
+        ValueType::Unsigned(len) => Argument::Unsigned(cursor.uint(len)?),
+        ValueType::Signed(len) => {
+            let shift = 64 - 8 * len as u32;
+            Argument::Signed(((cursor.uint(len)? << shift) as i64) >> shift)
+        }
+        ValueType::Float(4) => Argument::Float(f32::from_bits(cursor.uint(4)? as u32) as f64),
+        ValueType::Float(_) => Argument::Float(f64::from_bits(cursor.uint(8)?)),
+        ValueType::String => {
+            let len = cursor.uint(2)? as usize;
+            let bytes = cursor.take(len)?;
+            Argument::String(String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string())
+        }
+    })
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.tag_name().name() == name)
+}
+
+/// Frame IDs look like `ID_1234`.
+fn message_id(id: &str) -> Option<u32> {
+    id.rsplit('_').next()?.parse().ok()
+}
+
+/// Parses type names like `A_UINT32`, `S_SINT8`, `A_FLOAT64` or `S_STRG_ASCII`.
+fn value_type(name: &str) -> Option<ValueType> {
+    let name = name.to_ascii_uppercase();
+    let bits = |prefix: &str| -> Option<usize> {
+        let digits: String = name.split(prefix).nth(1)?.chars().take_while(char::is_ascii_digit).collect();
+        Some(digits.parse::<usize>().ok()? / 8).filter(|len| matches!(len, 1 | 2 | 4 | 8))
+    };
+
+    if name.contains("BOOL") {
+        Some(ValueType::Bool)
+    } else if name.contains("STRG") || name.contains("STRING") {
+        Some(ValueType::String)
+    } else if name.contains("UINT") {
+        bits("UINT").map(ValueType::Unsigned)
+    } else if name.contains("SINT") {
+        bits("SINT").map(ValueType::Signed)
+    } else if name.contains("INT") {
+        bits("INT").map(ValueType::Signed)
+    } else if name.contains("FLOAT") {
+        bits("FLOAT").map(ValueType::Float)
+    } else if name.contains("FLOA") {
+        bits("FLOA").map(ValueType::Float)
+    } else {
+        None
+    }
+}
+
+fn log_level(info: &str) -> Option<LogLevel> {
+    Some(match info {
+        "DLT_LOG_FATAL" => LogLevel::Fatal,
+        "DLT_LOG_ERROR" => LogLevel::Error,
+        "DLT_LOG_WARN" => LogLevel::Warn,
+        "DLT_LOG_INFO" => LogLevel::Info,
+        "DLT_LOG_DEBUG" => LogLevel::Debug,
+        "DLT_LOG_VERBOSE" => LogLevel::Verbose,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIBEX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<fx:FIBEX xmlns:fx="http://www.asam.net/xml/fbx" xmlns:ho="http://www.asam.net/xml" VERSION="3.1.0">
+  <fx:ELEMENTS>
+    <fx:FRAMES>
+      <fx:FRAME ID="ID_1001">
+        <fx:PDU-INSTANCES>
+          <fx:PDU-INSTANCE ID="P_1001_1"><fx:PDU-REF ID-REF="PDU_1001_1"/><fx:SEQUENCE-NUMBER>1</fx:SEQUENCE-NUMBER></fx:PDU-INSTANCE>
+          <fx:PDU-INSTANCE ID="P_1001_0"><fx:PDU-REF ID-REF="PDU_1001_0"/><fx:SEQUENCE-NUMBER>0</fx:SEQUENCE-NUMBER></fx:PDU-INSTANCE>
+          <fx:PDU-INSTANCE ID="P_1001_2"><fx:PDU-REF ID-REF="PDU_1001_2"/><fx:SEQUENCE-NUMBER>2</fx:SEQUENCE-NUMBER></fx:PDU-INSTANCE>
+          <fx:PDU-INSTANCE ID="P_1001_3"><fx:PDU-REF ID-REF="PDU_1001_3"/><fx:SEQUENCE-NUMBER>3</fx:SEQUENCE-NUMBER></fx:PDU-INSTANCE>
+        </fx:PDU-INSTANCES>
+        <fx:MANUFACTURER-EXTENSION>
+          <APPLICATION_ID>APP1</APPLICATION_ID>
+          <CONTEXT_ID>CTX1</CONTEXT_ID>
+          <MESSAGE_INFO>DLT_LOG_WARN</MESSAGE_INFO>
+        </fx:MANUFACTURER-EXTENSION>
+      </fx:FRAME>
+      <fx:FRAME ID="ID_1002">
+        <fx:PDU-INSTANCES>
+          <fx:PDU-INSTANCE ID="P_1002_0"><fx:PDU-REF ID-REF="PDU_1002_0"/><fx:SEQUENCE-NUMBER>0</fx:SEQUENCE-NUMBER></fx:PDU-INSTANCE>
+        </fx:PDU-INSTANCES>
+      </fx:FRAME>
+    </fx:FRAMES>
+    <fx:PDUS>
+      <fx:PDU ID="PDU_1001_0"><ho:DESC>Battery at</ho:DESC></fx:PDU>
+      <fx:PDU ID="PDU_1001_1"><fx:SIGNAL-INSTANCES><fx:SIGNAL-INSTANCE ID="SI_1"><fx:SIGNAL-REF ID-REF="S_UINT8"/></fx:SIGNAL-INSTANCE></fx:SIGNAL-INSTANCES></fx:PDU>
+      <fx:PDU ID="PDU_1001_2"><ho:DESC>% drawing</ho:DESC></fx:PDU>
+      <fx:PDU ID="PDU_1001_3"><fx:SIGNAL-INSTANCES><fx:SIGNAL-INSTANCE ID="SI_2"><fx:SIGNAL-REF ID-REF="SIG_CURRENT"/></fx:SIGNAL-INSTANCE></fx:SIGNAL-INSTANCES></fx:PDU>
+      <fx:PDU ID="PDU_1002_0"><fx:SIGNAL-INSTANCES><fx:SIGNAL-INSTANCE ID="SI_3"><fx:SIGNAL-REF ID-REF="S_STRG_UTF8"/></fx:SIGNAL-INSTANCE></fx:SIGNAL-INSTANCES></fx:PDU>
+    </fx:PDUS>
+    <fx:SIGNALS>
+      <fx:SIGNAL ID="S_UINT8"/>
+      <fx:SIGNAL ID="S_STRG_UTF8"/>
+      <fx:SIGNAL ID="SIG_CURRENT"><fx:CODING-REF ID-REF="COD_CURRENT"/></fx:SIGNAL>
+    </fx:SIGNALS>
+  </fx:ELEMENTS>
+  <fx:PROCESSING-INFORMATION>
+    <fx:CODINGS>
+      <fx:CODING ID="COD_CURRENT"><ho:CODED-TYPE ho:BASE-DATA-TYPE="A_INT16" CATEGORY="STANDARD-LENGTH-TYPE"/></fx:CODING>
+    </fx:CODINGS>
+  </fx:PROCESSING-INFORMATION>
+</fx:FIBEX>"#;
+
+    #[test]
+    fn reads_frames_with_their_parts_in_sequence() {
+        let fibex = Fibex::parse(FIBEX).unwrap();
+
+        let frame = fibex.frame(1001).unwrap();
+        assert_eq!((frame.app_id.as_deref(), frame.context_id.as_deref(), frame.level), (Some("APP1"), Some("CTX1"), Some(LogLevel::Warn)));
+        assert_eq!(frame.decode(&[80, 0xf6, 0xff], false), "Battery at 80 % drawing -10");
+        assert_eq!(frame.decode(&[80, 0xff, 0xf6], true), "Battery at 80 % drawing -10");
+
+        let frame = fibex.frame(1002).unwrap();
+        assert_eq!((frame.app_id.as_deref(), frame.level), (None, None));
+        assert_eq!(frame.decode(b"\x03\x00ok\0", false), "ok");
+
+        assert!(fibex.frame(1003).is_none());
+    }
+
+    #[test]
+    fn appends_data_that_does_not_fit_as_hex() {
+        let fibex = Fibex::parse(FIBEX).unwrap();
+        let frame = fibex.frame(1001).unwrap();
+
+        assert_eq!(frame.decode(&[80, 0xf6, 0xff, 1, 2], false), "Battery at 80 % drawing -10 01 02");
+        assert_eq!(frame.decode(&[80, 5], false), "Battery at 80 % drawing 05");
+    }
+
+    #[test]
+    fn names_value_types() {
+        assert!(matches!(value_type("A_UINT32"), Some(ValueType::Unsigned(4))));
+        assert!(matches!(value_type("S_SINT8"), Some(ValueType::Signed(1))));
+        assert!(matches!(value_type("A_FLOAT64"), Some(ValueType::Float(8))));
+        assert!(matches!(value_type("S_STRG_ASCII"), Some(ValueType::String)));
+        assert!(matches!(value_type("S_BOOL"), Some(ValueType::Bool)));
+        assert!(value_type("A_UINT24").is_none());
+        assert_eq!(message_id("ID_1234"), Some(1234));
+    }
+}
@@ -0,0 +1,259 @@
+//! File names on disk for attachment names, which may contain characters some file systems
+//! reject: colons and backslashes on Windows and many network shares, trailing dots and spaces,
+//! reserved device names like `CON`, and now and then a slash.
+
+use std::{collections::{BTreeMap, HashMap}, path::Path};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Which file systems the stored names must be valid on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sanitizer {
+    /// Only what the current platform rejects.
+    #[default]
+    Platform,
+    /// Names valid on Windows as well, e.g. for data directories on network shares.
+    Portable,
+}
+
+const WINDOWS_RESERVED: &[&str] = &["CON", "PRN", "AUX", "NUL"];
+
+impl Sanitizer {
+    /// Replaces the characters of `name` that are not allowed in a file name by `_`.
+    pub fn sanitize(self, name: &str) -> String {
+        let windows = self.windows_rules();
+        let mut safe: String = name
+            .chars()
+            .map(|c| match c {
+                '/' | '\0' => '_',
+                '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' if windows => '_',
+                c if windows && c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+
+        if windows {
+            safe.truncate(safe.trim_end_matches(['.', ' ']).len());
+
+            let stem = safe.split('.').next().unwrap_or_default().to_ascii_uppercase();
+            if WINDOWS_RESERVED.contains(&stem.as_str()) || is_device_with_number(&stem) {
+                safe.insert(0, '_');
+            }
+        }
+
+        if safe.is_empty() || safe == "." || safe == ".." {
+            safe = "_".to_string();
+        }
+
+        safe
+    }
+
+    fn windows_rules(self) -> bool {
+        self == Sanitizer::Portable || cfg!(windows)
+    }
+
+    /// Whether two names are the same file, as on case-insensitive file systems.
+    fn key(self, name: &str) -> String {
+        if self.windows_rules() || cfg!(target_os = "macos") {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+/// `COM1` to `COM9` and `LPT1` to `LPT9`.
+fn is_device_with_number(stem: &str) -> bool {
+    (stem.starts_with("COM") || stem.starts_with("LPT"))
+        && stem.len() == 4
+        && stem.as_bytes()[3].is_ascii_digit()
+        && stem.as_bytes()[3] != b'0'
+}
+
+/// Assigns a file name to every attachment, given as `(id, name)` pairs. Attachments whose
+/// sanitized names collide get their ID appended, except the oldest (lowest ID), so names stay
+/// the same whatever order attachments are downloaded in.
+pub fn file_names<'a>(sanitizer: Sanitizer, attachments: impl IntoIterator<Item = (&'a str, &'a str)>) -> HashMap<String, String> {
+    let mut by_key: HashMap<String, Vec<(&str, String)>> = HashMap::new();
+    for (id, name) in attachments {
+        let safe = sanitizer.sanitize(name);
+        by_key.entry(sanitizer.key(&safe)).or_default().push((id, safe));
+    }
+
+    let mut names = HashMap::new();
+    for mut group in by_key.into_values() {
+        group.sort_by(|(a, _), (b, _)| (a.len(), a).cmp(&(b.len(), b)));
+
+        for (index, (id, safe)) in group.into_iter().enumerate() {
+            let name = if index == 0 { safe } else { with_suffix(&safe, &sanitizer.sanitize(id)) };
+            names.insert(id.to_string(), name);
+        }
+    }
+
+    names
+}
+
+/// `log.txt` with suffix `12` becomes `log-12.txt`.
+pub fn with_suffix(name: &str, suffix: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{}-{}.{}", stem, suffix, extension),
+        _ => format!("{}-{}", name, suffix),
+    }
+}
+
+/// Longest slug made of a field value, cut at a dash.
+const MAX_SLUG_LEN: usize = 48;
+
+/// Name of the attachment `original` of issue `key` from the `download_name_template`
+/// `template`: `{original}` is the attachment name, `{key}` the issue key, and other
+/// placeholders are the value of that Jira field in `fields`, like `{summary}` or
+/// `{customfield_10042}`, empty if the issue has none. With `-slug`, as in `{summary-slug}`, the
+/// value is shortened to lowercase words joined by dashes. Dashes, underscores and spaces at
+/// either end are dropped, the extension of `original` is appended if the template drops it,
+/// and `original` is kept if nothing is left. The result still needs to be sanitized.
+pub fn from_template(template: &str, original: &str, key: &str, fields: &BTreeMap<String, String>) -> String {
+    let name = placeholder_regex().replace_all(template, |caps: &regex::Captures| {
+        let value = match &caps[1] {
+            "original" => original,
+            "key" => key,
+            field => fields.get(field).map(String::as_str).unwrap_or_default(),
+        };
+
+        if caps.get(2).is_some() { slug(value) } else { value.to_string() }
+    });
+    // Separators next to empty fields, as in `{key}-{summary-slug}` without summary
+    let mut name = name.trim_matches(['-', '_', ' ']).to_string();
+    if name.is_empty() {
+        return original.to_string();
+    }
+
+    let lower = original.to_lowercase();
+    let extension = if lower.ends_with(".tar.gz") {
+        Some(&original[original.len() - ".tar.gz".len() + 1..])
+    } else {
+        Path::new(original).extension().and_then(|e| e.to_str())
+    };
+    if let Some(extension) = extension && !name.to_lowercase().ends_with(&format!(".{}", extension.to_lowercase())) {
+        name = format!("{}.{}", name, extension);
+    }
+
+    name
+}
+
+/// The Jira fields the placeholders of `template` refer to.
+pub fn template_fields(template: &str) -> Vec<String> {
+    let mut fields: Vec<String> = placeholder_regex().captures_iter(template)
+        .map(|caps| caps[1].to_string())
+        .filter(|field| field != "original" && field != "key")
+        .collect();
+    fields.sort();
+    fields.dedup();
+
+    fields
+}
+
+/// Placeholders of `download_name_template`, a name optionally followed by `-slug`.
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\{([A-Za-z0-9_]+)(-slug)?\}").unwrap()
+}
+
+/// `Crash on boot (EU build)` becomes `crash-on-boot-eu-build`.
+fn slug(value: &str) -> String {
+    let mut slug = String::new();
+    for word in value.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+        let word = word.to_lowercase();
+        if !slug.is_empty() && slug.len() + 1 + word.len() > MAX_SLUG_LEN {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word);
+    }
+
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_characters_windows_rejects() {
+        assert_eq!(Sanitizer::Portable.sanitize(r#"a:b\c?"d|e*<f>.log"#), "a_b_c__d_e__f_.log");
+        assert_eq!(Sanitizer::Portable.sanitize("tab\there.log"), "tab_here.log");
+        assert_eq!(Sanitizer::Portable.sanitize("report. . "), "report");
+        assert_eq!(Sanitizer::Portable.sanitize("..."), "_");
+        assert_eq!(Sanitizer::Platform.sanitize("logs/main\0.log"), "logs_main_.log");
+        assert_eq!(Sanitizer::Platform.sanitize(".."), "_");
+        assert_eq!(Sanitizer::Platform.sanitize(""), "_");
+    }
+
+    #[test]
+    fn prefixes_reserved_device_names() {
+        assert_eq!(Sanitizer::Portable.sanitize("CON"), "_CON");
+        assert_eq!(Sanitizer::Portable.sanitize("nul.txt"), "_nul.txt");
+        assert_eq!(Sanitizer::Portable.sanitize("com1.log"), "_com1.log");
+        assert_eq!(Sanitizer::Portable.sanitize("LPT9"), "_LPT9");
+        assert_eq!(Sanitizer::Portable.sanitize("COM0.log"), "COM0.log");
+        assert_eq!(Sanitizer::Portable.sanitize("LPT10"), "LPT10");
+        assert_eq!(Sanitizer::Portable.sanitize("console.log"), "console.log");
+    }
+
+    #[test]
+    fn suffixes_colliding_names_with_their_id() {
+        let names = file_names(Sanitizer::Portable, [("10", "Log.txt"), ("9", "log.txt"), ("100", "log.txt"), ("11", "a:b"), ("12", "a_b"), ("13", "other")]);
+
+        assert_eq!(names["9"], "log.txt");
+        assert_eq!(names["10"], "Log-10.txt");
+        assert_eq!(names["100"], "log-100.txt");
+        assert_eq!(names["11"], "a_b");
+        assert_eq!(names["12"], "a_b-12");
+        assert_eq!(names["13"], "other");
+    }
+
+    #[test]
+    fn appends_suffixes_before_the_extension() {
+        assert_eq!(with_suffix("log.txt", "12"), "log-12.txt");
+        assert_eq!(with_suffix("archive", "12"), "archive-12");
+        assert_eq!(with_suffix(".bashrc", "12"), ".bashrc-12");
+    }
+
+    #[test]
+    fn fills_in_name_templates() {
+        let fields = BTreeMap::from([
+            ("summary".to_string(), "Crash on boot (EU build)".to_string()),
+            ("customfield_10042".to_string(), "R 1.2".to_string()),
+        ]);
+
+        assert_eq!(from_template("{key}-{summary-slug}", "trace.dlt", "KEY-1", &fields), "KEY-1-crash-on-boot-eu-build.dlt");
+        assert_eq!(from_template("{customfield_10042}_{original}", "trace.dlt", "KEY-1", &fields), "R 1.2_trace.dlt");
+        assert_eq!(from_template("{key}", "logs.TAR.GZ", "KEY-1", &fields), "KEY-1.TAR.GZ");
+        assert_eq!(from_template("{key}.DLT", "trace.dlt", "KEY-1", &fields), "KEY-1.DLT");
+    }
+
+    #[test]
+    fn drops_separators_of_empty_fields() {
+        let fields = BTreeMap::new();
+
+        assert_eq!(from_template("{key}-{summary-slug}", "trace.dlt", "KEY-1", &fields), "KEY-1.dlt");
+        assert_eq!(from_template("{summary-slug}_{key}", "trace.dlt", "KEY-1", &fields), "KEY-1.dlt");
+        assert_eq!(from_template("{summary} - {labels}", "trace.dlt", "KEY-1", &fields), "trace.dlt");
+    }
+
+    #[test]
+    fn lists_the_fields_of_a_template() {
+        assert_eq!(template_fields("{key}-{summary-slug}-{customfield_10042}-{summary}_{original}"), ["customfield_10042", "summary"]);
+    }
+
+    #[test]
+    fn cuts_slugs_at_a_dash() {
+        assert_eq!(slug("Crash on boot (EU build)"), "crash-on-boot-eu-build");
+        assert_eq!(slug("  ÄÖ: Überlauf!  "), "äö-überlauf");
+
+        let long = slug("the quick brown fox jumps over the lazy dog and keeps on running far away");
+        assert_eq!(long, "the-quick-brown-fox-jumps-over-the-lazy-dog-and");
+        assert!(long.len() <= MAX_SLUG_LEN);
+    }
+}
@@ -0,0 +1,323 @@
+//! Filter expressions selecting the attachments to download, e.g.
+//! `name~"\.zip$" and size>10M and (created>-1d or mime:text/*)`.
+//!
+//! Conditions are `name~REGEX`, `size` or `created` compared with `<`, `<=`, `>` or `>=`, and
+//! `mime:TYPE` where `TYPE` may end in `*`. They combine with `not`, `and` and `or`, binding in
+//! that order, and can be grouped with parentheses.
+
+use anyhow::{bail, Context};
+use regex::Regex;
+use time::{Duration, OffsetDateTime, PrimitiveDateTime, macros::format_description};
+use crate::attachment::Attachment;
+
+#[derive(Debug, Clone)]
+pub enum Filter {
+    Name(Regex),
+    Size(Comparison, u64),
+    Created(Comparison, OffsetDateTime),
+    Mime(String),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds<T: PartialOrd>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+impl Filter {
+    pub fn parse(expr: &str) -> anyhow::Result<Self> {
+        let tokens = tokenize(expr)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+
+        let filter = parser.or()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected `{}` in filter expression", token);
+        }
+
+        Ok(filter)
+    }
+
+    /// Whether the attachment `att` matches. Conditions on fields the record lacks do not match.
+    pub fn matches(&self, att: &Attachment) -> bool {
+        match self {
+            Filter::Name(regex) => regex.is_match(&att.filename),
+            Filter::Size(cmp, size) => att.size.map(|s| cmp.holds(s, *size)).unwrap_or(false),
+            Filter::Created(cmp, time) => att.created_time().map(|c| cmp.holds(c, *time)).unwrap_or(false),
+            Filter::Mime(pattern) => att.mime_type.as_deref().map(|m| mime_matches(pattern, m)).unwrap_or(false),
+            Filter::Not(inner) => !inner.matches(att),
+            Filter::And(left, right) => left.matches(att) && right.matches(att),
+            Filter::Or(left, right) => left.matches(att) || right.matches(att),
+        }
+    }
+}
+
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => mime.to_ascii_lowercase().starts_with(&prefix.to_ascii_lowercase()),
+        None => mime.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// Splits at whitespace and parentheses. Double quotes protect both and are removed.
+fn tokenize(expr: &str) -> anyhow::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in expr.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if quoted => current.push(c),
+            '(' | ')' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if quoted {
+        bail!("Unterminated quote in filter expression");
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&str> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1).map(String::as_str)
+    }
+
+    fn or(&mut self) -> anyhow::Result<Filter> {
+        let mut filter = self.and()?;
+        while self.peek().is_some_and(|t| t.eq_ignore_ascii_case("or")) {
+            self.pos += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Filter> {
+        let mut filter = self.not()?;
+        while self.peek().is_some_and(|t| t.eq_ignore_ascii_case("and")) {
+            self.pos += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+
+        Ok(filter)
+    }
+
+    fn not(&mut self) -> anyhow::Result<Filter> {
+        if self.peek().is_some_and(|t| t.eq_ignore_ascii_case("not")) {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+
+        match self.next() {
+            Some("(") => {
+                let filter = self.or()?;
+                if self.next() != Some(")") {
+                    bail!("Missing `)` in filter expression");
+                }
+                Ok(filter)
+            }
+            Some(token) => condition(token),
+            None => bail!("Filter expression ends unexpectedly"),
+        }
+    }
+}
+
+fn condition(token: &str) -> anyhow::Result<Filter> {
+    if let Some(pattern) = token.strip_prefix("name~") {
+        return Ok(Filter::Name(Regex::new(pattern).with_context(|| format!("Invalid regex in `{}`", token))?));
+    }
+
+    if let Some(pattern) = token.strip_prefix("mime:") {
+        return Ok(Filter::Mime(pattern.to_string()));
+    }
+
+    if let Some(rest) = token.strip_prefix("size") && let Some((cmp, value)) = comparison(rest) {
+        return size_condition(cmp, value).with_context(|| format!("Invalid condition `{}`", token));
+    }
+
+    if let Some(rest) = token.strip_prefix("created") && let Some((cmp, value)) = comparison(rest) {
+        return created_condition(cmp, value).with_context(|| format!("Invalid condition `{}`", token));
+    }
+
+    bail!("Unknown filter condition `{}`", token)
+}
+
+fn comparison(rest: &str) -> Option<(Comparison, &str)> {
+    if let Some(value) = rest.strip_prefix("<=") {
+        Some((Comparison::LessOrEqual, value))
+    } else if let Some(value) = rest.strip_prefix(">=") {
+        Some((Comparison::GreaterOrEqual, value))
+    } else if let Some(value) = rest.strip_prefix('<') {
+        Some((Comparison::Less, value))
+    } else {
+        rest.strip_prefix('>').map(|value| (Comparison::Greater, value))
+    }
+}
+
+fn size_condition(cmp: Comparison, value: &str) -> anyhow::Result<Filter> {
+    Ok(Filter::Size(cmp, parse_size(value)?))
+}
+
+/// Sizes are bytes, optionally with a `K`, `M` or `G` suffix (powers of 1024, `B` optional).
+pub fn parse_size(value: &str) -> anyhow::Result<u64> {
+    let upper = value.to_ascii_uppercase();
+    let digits = upper.trim_end_matches('B');
+    let (digits, factor) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 1u64 << 10),
+        Some('M') => (&digits[..digits.len() - 1], 1 << 20),
+        Some('G') => (&digits[..digits.len() - 1], 1 << 30),
+        _ => (digits, 1),
+    };
+
+    digits.parse::<u64>()?.checked_mul(factor).with_context(|| format!("{} is too large", value))
+}
+
+/// Dates are `YYYY-MM-DD`, `YYYY-MM-DDTHH:MM:SS` (UTC), or relative to now like `-1d` or `-6h`.
+fn created_condition(cmp: Comparison, value: &str) -> anyhow::Result<Filter> {
+    if let Some(ago) = value.strip_prefix('-') {
+        let Some((split, unit)) = ago.char_indices().last() else {
+            bail!("Missing amount and unit after `-`, like -1d");
+        };
+        let amount: i64 = ago[..split].parse()?;
+        let seconds = match unit {
+            'd' => 86_400,
+            'h' => 3_600,
+            'm' => 60,
+            _ => bail!("Unknown unit `{}`, expected d, h or m", unit),
+        };
+
+        let time = amount.checked_mul(seconds)
+            .and_then(|seconds| OffsetDateTime::now_utc().checked_sub(Duration::seconds(seconds)))
+            .with_context(|| format!("-{} is too far back", ago))?;
+        return Ok(Filter::Created(cmp, time));
+    }
+
+    let time = match value.len() {
+        10 => time::Date::parse(value, format_description!("[year]-[month]-[day]"))?.midnight(),
+        _ => PrimitiveDateTime::parse(value, format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]"))?,
+    };
+
+    Ok(Filter::Created(cmp, time.assume_utc()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(filename: &str, size: Option<u64>, mime_type: Option<&str>, created: Option<&str>) -> Attachment {
+        Attachment {
+            id: "1".to_string(),
+            filename: filename.to_string(),
+            content: String::new(),
+            size,
+            mime_type: mime_type.map(str::to_string),
+            created: created.map(str::to_string),
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn tokenizes_at_whitespace_and_parentheses_outside_quotes() {
+        assert_eq!(tokenize(r#"(name~"a b" or  size>1)and not(x)"#).unwrap(), ["(", "name~a b", "or", "size>1", ")", "and", "not", "(", "x", ")"]);
+        assert_eq!(tokenize("name~ä.log").unwrap(), ["name~ä.log"]);
+        assert!(tokenize(r#"name~"open"#).is_err());
+    }
+
+    #[test]
+    fn parses_sizes_with_units() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10k").unwrap(), 10 << 10);
+        assert_eq!(parse_size("10KB").unwrap(), 10 << 10);
+        assert_eq!(parse_size("3M").unwrap(), 3 << 20);
+        assert_eq!(parse_size("2gb").unwrap(), 2 << 30);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("1T").is_err());
+        assert!(parse_size("10ä").is_err());
+        assert!(parse_size("99999999999999G").is_err());
+    }
+
+    #[test]
+    fn parses_relative_and_absolute_dates() {
+        let Filter::Created(_, time) = Filter::parse("created>-2h").unwrap() else { panic!("not a date condition") };
+        let expected = OffsetDateTime::now_utc() - Duration::hours(2);
+        assert!((time - expected).abs() < Duration::minutes(1));
+
+        let Filter::Created(_, time) = Filter::parse("created<2024-05-01").unwrap() else { panic!("not a date condition") };
+        assert_eq!(time, time::macros::datetime!(2024-05-01 0:00 UTC));
+
+        let Filter::Created(_, time) = Filter::parse("created>=2024-05-01T10:20:30").unwrap() else { panic!("not a date condition") };
+        assert_eq!(time, time::macros::datetime!(2024-05-01 10:20:30 UTC));
+    }
+
+    #[test]
+    fn rejects_bad_dates_without_panicking() {
+        for expr in ["created>-1ä", "created>-ä", "created>-", "created>-d", "created>-1w", "created>-9223372036854775807d", "created>2024-13-01"] {
+            assert!(Filter::parse(expr).is_err(), "{} should not parse", expr);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for expr in ["", "(name~a", "name~a)", "name~a and", "not", "size=1", "color:red", "name~(", "size>1 size>2"] {
+            assert!(Filter::parse(expr).is_err(), "{} should not parse", expr);
+        }
+    }
+
+    #[test]
+    fn combines_conditions_with_precedence() {
+        let filter = Filter::parse(r#"name~"\.zip$" and size>1K or mime:text/*"#).unwrap();
+        assert!(filter.matches(&attachment("logs.zip", Some(4096), None, None)));
+        assert!(!filter.matches(&attachment("logs.zip", Some(10), None, None)));
+        assert!(filter.matches(&attachment("notes", None, Some("TEXT/plain"), None)));
+        assert!(!filter.matches(&attachment("notes", None, Some("image/png"), None)));
+
+        let filter = Filter::parse("not (size<1K or created<2024-01-01)").unwrap();
+        assert!(filter.matches(&attachment("a", Some(2048), None, Some("2024-05-01T10:00:00.000+0000"))));
+        assert!(!filter.matches(&attachment("a", Some(2048), None, Some("2023-05-01T10:00:00.000+0000"))));
+        // A missing field does not match, so `not` of it does
+        assert!(filter.matches(&attachment("a", None, None, None)));
+    }
+}
@@ -0,0 +1,457 @@
+//! File system images attached instead of archives. ext2, ext3 and ext4 images are read
+//! directly, without mounting them; other file systems are only recognized, so they can be
+//! reported by name.
+
+use std::{collections::HashSet, fs, io::{self, Read, Seek, SeekFrom}};
+use anyhow::bail;
+
+/// Offset of the ext superblock, and of the magic within it.
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT_MAGIC: u16 = 0xEF53;
+
+/// Inode of the root directory.
+const ROOT_INODE: u32 = 2;
+
+/// Features that change where data is, and are not supported.
+const INCOMPAT_COMPRESSION: u32 = 0x1;
+const INCOMPAT_META_BG: u32 = 0x10;
+const INCOMPAT_64BIT: u32 = 0x80;
+const INCOMPAT_ENCRYPT: u32 = 0x10000;
+
+/// Inode flags.
+const EXTENTS_FL: u32 = 0x80000;
+const INLINE_DATA_FL: u32 = 0x1000_0000;
+const ENCRYPT_FL: u32 = 0x800;
+
+const EXTENT_MAGIC: u16 = 0xF30A;
+
+/// Directories deeper than this are not read, against loops in broken images.
+const MAX_DIR_DEPTH: usize = 64;
+
+/// Largest directory read into memory.
+const MAX_DIR_SIZE: u64 = 64 << 20;
+
+/// The file system of the image `file` by its magic numbers, `None` if it is none getlogs knows.
+pub fn identify(file: &mut fs::File) -> io::Result<Option<&'static str>> {
+    let mut head = vec![0u8; 0x10048];
+    file.seek(SeekFrom::Start(0))?;
+    let len = read_up_to(file, &mut head)?;
+    head.truncate(len);
+
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+    let kind = if at(1080, &EXT_MAGIC.to_le_bytes()) {
+        "ext"
+    } else if at(0, &0xED26_FF3Au32.to_le_bytes()) {
+        "Android sparse image"
+    } else if at(0, b"hsqs") {
+        "squashfs"
+    } else if at(1024, &0xE0F5_E1E2u32.to_le_bytes()) {
+        "EROFS"
+    } else if at(1024, &0xF2F5_2010u32.to_le_bytes()) {
+        "F2FS"
+    } else if at(0, b"XFSB") {
+        "XFS"
+    } else if at(0x10040, b"_BHRfS_M") {
+        "Btrfs"
+    } else if at(32769, b"CD001") {
+        "ISO 9660"
+    } else if at(3, b"NTFS    ") {
+        "NTFS"
+    } else if at(510, &[0x55, 0xAA]) && (at(54, b"FAT") || at(82, b"FAT32")) {
+        "FAT"
+    } else if at(512, b"EFI PART") {
+        "partitioned disk (GPT)"
+    } else if at(510, &[0x55, 0xAA]) {
+        "partitioned disk (MBR)"
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(kind))
+}
+
+/// A regular file of an image.
+#[derive(Debug, Clone)]
+pub struct ImageFile {
+    /// Path from the root of the image, like `var/log/messages`.
+    pub path: String,
+    pub size: u64,
+    inode: u32,
+}
+
+/// An ext2, ext3 or ext4 file system read from an image.
+pub struct Ext {
+    file: fs::File,
+    block_size: u64,
+    inodes_per_group: u32,
+    inode_size: u64,
+    /// First block of the inode table of each block group.
+    inode_tables: Vec<u64>,
+}
+
+impl Ext {
+    /// Opens the image `file`, failing with the name of the file system if it is no ext image.
+    pub fn open(mut file: fs::File) -> anyhow::Result<Self> {
+        match identify(&mut file)? {
+            Some("ext") => {}
+            Some(kind) => bail!("unsupported file system image ({}), only ext2, ext3 and ext4 images are read", kind),
+            None => bail!("not a file system image getlogs knows"),
+        }
+
+        let mut sb = [0u8; 1024];
+        file.seek(SeekFrom::Start(SUPERBLOCK_OFFSET))?;
+        file.read_exact(&mut sb)?;
+
+        let inodes_count = u32_at(&sb, 0);
+        let first_data_block = u32_at(&sb, 20) as u64;
+        let log_block_size = u32_at(&sb, 24);
+        let inodes_per_group = u32_at(&sb, 40);
+        let rev_level = u32_at(&sb, 76);
+        let incompat = u32_at(&sb, 96);
+        if log_block_size > 6 || inodes_per_group == 0 {
+            bail!("broken ext superblock");
+        }
+        for (flag, feature) in [(INCOMPAT_COMPRESSION, "compression"), (INCOMPAT_META_BG, "meta_bg"), (INCOMPAT_ENCRYPT, "encryption")] {
+            if incompat & flag != 0 {
+                bail!("ext images with {} are not supported", feature);
+            }
+        }
+
+        let block_size = 1024u64 << log_block_size;
+        let inode_size = if rev_level == 0 { 128 } else { u16_at(&sb, 88) as u64 };
+        let desc_size = if incompat & INCOMPAT_64BIT != 0 { (u16_at(&sb, 254) as u64).max(32) } else { 32 };
+
+        // The group descriptors follow the block of the superblock
+        let groups = inodes_count.div_ceil(inodes_per_group) as u64;
+        let mut descs = vec![0u8; (groups * desc_size) as usize];
+        file.seek(SeekFrom::Start((first_data_block + 1) * block_size))?;
+        file.read_exact(&mut descs)?;
+        let inode_tables = descs.chunks(desc_size as usize).map(|desc| {
+            let high = if desc_size >= 64 { u32_at(desc, 0x28) as u64 } else { 0 };
+            high << 32 | u32_at(desc, 8) as u64
+        }).collect();
+
+        Ok(Ext { file, block_size, inodes_per_group, inode_size, inode_tables })
+    }
+
+    /// The regular files of the image, in directory order. Symbolic links, devices and the
+    /// like are left out.
+    pub fn files(&mut self) -> anyhow::Result<Vec<ImageFile>> {
+        let mut files = Vec::new();
+        let mut pending = vec![(ROOT_INODE, String::new(), 0)];
+        let mut visited = HashSet::from([ROOT_INODE]);
+
+        while let Some((dir, prefix, depth)) = pending.pop() {
+            let inode = self.inode(dir)?;
+            if inode.size > MAX_DIR_SIZE {
+                bail!("directory /{} is too large", prefix);
+            }
+
+            let mut data = Vec::new();
+            self.reader(&inode)?.read_to_end(&mut data)?;
+            // Inline directories start with the inode of the parent instead of `.` and `..`
+            let entries = if inode.inline.is_some() { data.get(4..).unwrap_or_default() } else { &data };
+            let mut subdirs = Vec::new();
+            for (number, name) in dir_entries(entries) {
+                if name == "." || name == ".." || !visited.insert(number) {
+                    continue;
+                }
+
+                let path = format!("{}{}", prefix, name);
+                let entry = self.inode(number)?;
+                match entry.mode & 0xF000 {
+                    0x4000 if depth < MAX_DIR_DEPTH => subdirs.push((number, format!("{}/", path), depth + 1)),
+                    0x8000 => files.push(ImageFile { path, size: entry.size, inode: number }),
+                    _ => {}
+                }
+            }
+
+            // Depth first, in the order the directory lists its entries
+            pending.extend(subdirs.into_iter().rev());
+        }
+
+        Ok(files)
+    }
+
+    /// The content of `file`.
+    pub fn open_file(&mut self, file: &ImageFile) -> anyhow::Result<impl Read + '_> {
+        let inode = self.inode(file.inode)?;
+        if inode.flags & ENCRYPT_FL != 0 {
+            bail!("{} is encrypted", file.path);
+        }
+
+        self.reader(&inode)
+    }
+
+    fn inode(&mut self, number: u32) -> anyhow::Result<Inode> {
+        let index = (number - 1) / self.inodes_per_group;
+        let Some(&table) = self.inode_tables.get(index as usize) else {
+            bail!("inode {} is outside the image", number);
+        };
+        let offset = table * self.block_size + ((number - 1) % self.inodes_per_group) as u64 * self.inode_size;
+
+        let mut raw = vec![0u8; self.inode_size.max(128) as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut raw)?;
+
+        let mut block = [0u8; 60];
+        block.copy_from_slice(&raw[40..100]);
+        let mode = u16_at(&raw, 0);
+        let flags = u32_at(&raw, 32);
+        // The upper half of the size was the ACL of directories in early ext2
+        let high = if mode & 0xF000 == 0x4000 { 0 } else { u32_at(&raw, 108) as u64 };
+        Ok(Inode {
+            mode,
+            size: high << 32 | u32_at(&raw, 4) as u64,
+            flags,
+            block,
+            inline: (flags & INLINE_DATA_FL != 0).then(|| inline_data(&raw)),
+        })
+    }
+
+    fn reader(&mut self, inode: &Inode) -> anyhow::Result<FileReader<'_>> {
+        let mut runs = Vec::new();
+        match &inode.inline {
+            Some(inline) if inode.size > inline.len() as u64 => bail!("broken inline data"),
+            Some(_) => {}
+            None if inode.flags & EXTENTS_FL != 0 => self.extents(&inode.block, &mut runs, 0)?,
+            None => runs = self.block_map(&inode.block, inode.size)?,
+        }
+
+        Ok(FileReader { file: &mut self.file, block_size: self.block_size, runs, inline: inode.inline.clone(), pos: 0, size: inode.size })
+    }
+
+    /// Collects the leaves of the extent tree `node` into `runs`.
+    fn extents(&mut self, node: &[u8], runs: &mut Vec<Extent>, level: usize) -> anyhow::Result<()> {
+        if node.len() < 12 || u16_at(node, 0) != EXTENT_MAGIC || level > 5 {
+            bail!("broken extent tree");
+        }
+
+        let entries = u16_at(node, 2) as usize;
+        let depth = u16_at(node, 6);
+        for entry in node[12..].chunks_exact(12).take(entries) {
+            if depth == 0 {
+                let len = u16_at(entry, 4) as u64;
+                runs.push(Extent {
+                    logical: u32_at(entry, 0) as u64,
+                    physical: (u16_at(entry, 6) as u64) << 32 | u32_at(entry, 8) as u64,
+                    // Longer extents are preallocated, reading as zeros
+                    len: if len > 32768 { len - 32768 } else { len },
+                    initialized: len <= 32768,
+                });
+            } else {
+                let child = (u16_at(entry, 8) as u64) << 32 | u32_at(entry, 4) as u64;
+                let block = self.block(child)?;
+                self.extents(&block, runs, level + 1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The runs of the classic block map of ext2 and ext3: twelve direct blocks, then single,
+    /// double and triple indirect ones.
+    fn block_map(&mut self, block: &[u8; 60], size: u64) -> anyhow::Result<Vec<Extent>> {
+        let blocks = size.div_ceil(self.block_size);
+        let mut physical = Vec::new();
+        for i in 0..12 {
+            physical.push(u32_at(block, i * 4) as u64);
+        }
+        for (i, level) in [(12, 1), (13, 2), (14, 3)] {
+            if physical.len() as u64 >= blocks {
+                break;
+            }
+            self.indirect(u32_at(block, i * 4) as u64, level, blocks, &mut physical)?;
+        }
+        physical.truncate(blocks as usize);
+
+        // Merge neighbouring blocks into runs, holes are block 0
+        let mut runs: Vec<Extent> = Vec::new();
+        for (logical, physical) in physical.into_iter().enumerate().filter(|(_, physical)| *physical != 0) {
+            let logical = logical as u64;
+            match runs.last_mut() {
+                Some(run) if run.logical + run.len == logical && run.physical + run.len == physical => run.len += 1,
+                _ => runs.push(Extent { logical, physical, len: 1, initialized: true }),
+            }
+        }
+
+        Ok(runs)
+    }
+
+    fn indirect(&mut self, number: u64, level: u32, blocks: u64, physical: &mut Vec<u64>) -> anyhow::Result<()> {
+        let per_block = self.block_size / 4;
+        if number == 0 {
+            let skipped = per_block.pow(level).min(blocks.saturating_sub(physical.len() as u64));
+            physical.extend(std::iter::repeat_n(0, skipped as usize));
+            return Ok(());
+        }
+
+        let block = self.block(number)?;
+        for i in 0..per_block as usize {
+            if physical.len() as u64 >= blocks {
+                break;
+            }
+            let pointer = u32_at(&block, i * 4) as u64;
+            if level == 1 {
+                physical.push(pointer);
+            } else {
+                self.indirect(pointer, level - 1, blocks, physical)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn block(&mut self, number: u64) -> io::Result<Vec<u8>> {
+        let mut block = vec![0u8; self.block_size as usize];
+        self.file.seek(SeekFrom::Start(number * self.block_size))?;
+        self.file.read_exact(&mut block)?;
+
+        Ok(block)
+    }
+}
+
+struct Inode {
+    mode: u16,
+    size: u64,
+    flags: u32,
+    /// `i_block`: block map or extent tree root.
+    block: [u8; 60],
+    /// Content of small files and directories stored in the inode itself.
+    inline: Option<Vec<u8>>,
+}
+
+/// Blocks `logical` to `logical + len` of a file, stored from block `physical` on.
+#[derive(Debug)]
+struct Extent {
+    logical: u64,
+    physical: u64,
+    len: u64,
+    initialized: bool,
+}
+
+/// Reads a file of an image from its extents, holes and preallocated blocks as zeros.
+struct FileReader<'a> {
+    file: &'a mut fs::File,
+    block_size: u64,
+    runs: Vec<Extent>,
+    inline: Option<Vec<u8>>,
+    pos: u64,
+    size: u64,
+}
+
+impl Read for FileReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.size.saturating_sub(self.pos);
+        if left == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let want = (buf.len() as u64).min(left);
+
+        if let Some(inline) = &self.inline {
+            let start = self.pos as usize;
+            let len = want as usize;
+            buf[..len].copy_from_slice(&inline[start..start + len]);
+            self.pos += want;
+            return Ok(len);
+        }
+
+        let block = self.pos / self.block_size;
+        let offset = self.pos % self.block_size;
+        let run = self.runs.iter().find(|run| block >= run.logical && block < run.logical + run.len);
+        let len = match run {
+            Some(run) => {
+                let len = want.min((run.logical + run.len - block) * self.block_size - offset);
+                if run.initialized {
+                    self.file.seek(SeekFrom::Start((run.physical + block - run.logical) * self.block_size + offset))?;
+                    self.file.read_exact(&mut buf[..len as usize])?;
+                } else {
+                    buf[..len as usize].fill(0);
+                }
+                len
+            }
+            None => {
+                // A hole up to the next run
+                let next = self.runs.iter().map(|run| run.logical).filter(|&logical| logical > block).min();
+                let len = next.map_or(want, |next| want.min((next - block) * self.block_size - offset));
+                buf[..len as usize].fill(0);
+                len
+            }
+        };
+
+        self.pos += len;
+        Ok(len as usize)
+    }
+}
+
+/// The inline data of the inode `raw`: `i_block`, continued in the `system.data` extended
+/// attribute after the inode's fixed fields.
+fn inline_data(raw: &[u8]) -> Vec<u8> {
+    let mut data = raw[40..100].to_vec();
+    let Some(start) = raw.get(128..130).map(|extra| 128 + u16::from_le_bytes([extra[0], extra[1]]) as usize) else {
+        return data;
+    };
+    if raw.len() < start + 4 || u32_at(raw, start) != 0xEA02_0000 {
+        return data;
+    }
+
+    // Value offsets count from the first entry
+    let first = start + 4;
+    let mut offset = first;
+    while offset + 16 <= raw.len() && u32_at(raw, offset) != 0 {
+        let name_len = raw[offset] as usize;
+        let name = raw.get(offset + 16..offset + 16 + name_len);
+        if raw[offset + 1] == 7 && name == Some(b"data") {
+            let value = first + u16_at(raw, offset + 2) as usize;
+            if let Some(value) = raw.get(value..value + u32_at(raw, offset + 8) as usize) {
+                data.extend_from_slice(value);
+            }
+            break;
+        }
+        offset += (16 + name_len + 3) & !3;
+    }
+
+    data
+}
+
+/// Inode numbers and names of the entries of a directory, from its blocks.
+fn dir_entries(data: &[u8]) -> Vec<(u32, String)> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let inode = u32_at(data, offset);
+        let rec_len = u16_at(data, offset + 4) as usize;
+        let name_len = data[offset + 6] as usize;
+        if rec_len < 8 || offset + rec_len > data.len() {
+            break;
+        }
+
+        // Unused entries, the tails of checksummed blocks and htree nodes have inode 0
+        if inode != 0 && 8 + name_len <= rec_len {
+            entries.push((inode, String::from_utf8_lossy(&data[offset + 8..offset + 8 + name_len]).into_owned()));
+        }
+        offset += rec_len;
+    }
+
+    entries
+}
+
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            read => len += read,
+        }
+    }
+
+    Ok(len)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,85 @@
+//! Shell commands run after each issue and after the whole run, to chain getlogs into other
+//! tools like an indexer without a wrapper script.
+
+use std::{path::Path, process::Stdio};
+use crate::output;
+
+/// Runs `post_issue_command` for the issue `key` downloaded to `path`. The key and path are
+/// passed as `$1` and `$2` and as `GETLOGS_ISSUE` and `GETLOGS_PATH`.
+pub async fn post_issue(command: &str, key: &str, path: &Path) -> anyhow::Result<()> {
+    let path = path.to_string_lossy();
+    run("post_issue_command", command, &[key, &path], &[("GETLOGS_ISSUE", key), ("GETLOGS_PATH", &path)]).await
+}
+
+/// Runs `post_run_command` once the issues `keys` are done, passed as arguments and as the
+/// space separated `GETLOGS_ISSUES`, with `GETLOGS_PATH` set to `default_path`.
+pub async fn post_run(command: &str, keys: &[String], path: &Path) -> anyhow::Result<()> {
+    let path = path.to_string_lossy();
+    let args: Vec<&str> = keys.iter().map(String::as_str).collect();
+    run("post_run_command", command, &args, &[("GETLOGS_ISSUES", &keys.join(" ")), ("GETLOGS_PATH", &path)]).await
+}
+
+/// Runs `command` in the shell, its output going where getlogs' own messages go. On Windows the
+/// arguments are appended to the command line, `sh` gets them as positional parameters.
+async fn run(name: &str, command: &str, args: &[&str], env: &[(&str, &str)]) -> anyhow::Result<()> {
+    let mut process = if cfg!(windows) {
+        let mut process = tokio::process::Command::new("cmd");
+        process.args(["/C", command]).args(args);
+        process
+    } else {
+        let mut process = tokio::process::Command::new("sh");
+        process.args(["-c", command, "getlogs"]).args(args);
+        process
+    };
+    process.envs(env.iter().copied());
+
+    // Keep stdout for the data of `--stdout`
+    if output::is_stderr_only() {
+        process.stdout(Stdio::from(std::io::stderr()));
+    }
+
+    let status = process.status().await?;
+    if !status.success() {
+        anyhow::bail!("{} failed with {}", name, status);
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn passes_the_issue_as_arguments_and_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let command = format!("echo \"$1|$2|$GETLOGS_ISSUE|$GETLOGS_PATH\" > '{}'", out.display());
+
+        post_issue(&command, "ABC-1", Path::new("/logs/ABC 1")).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "ABC-1|/logs/ABC 1|ABC-1|/logs/ABC 1\n");
+    }
+
+    #[tokio::test]
+    async fn passes_all_issues_of_the_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let command = format!("echo \"$#|$*|$GETLOGS_ISSUES|$GETLOGS_PATH\" > '{}'", out.display());
+        let keys = ["ABC-1".to_string(), "ABC-2".to_string()];
+
+        post_run(&command, &keys, Path::new("/logs")).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "2|ABC-1 ABC-2|ABC-1 ABC-2|/logs\n");
+    }
+
+    #[tokio::test]
+    async fn fails_with_the_exit_status_of_the_command() {
+        let err = post_issue("exit 3", "ABC-1", Path::new("/logs")).await.unwrap_err();
+        assert!(err.to_string().starts_with("post_issue_command failed with"), "{}", err);
+        assert!(err.to_string().contains('3'), "{}", err);
+
+        let err = post_run("false", &[], Path::new("/logs")).await.unwrap_err();
+        assert!(err.to_string().starts_with("post_run_command failed with"), "{}", err);
+    }
+}
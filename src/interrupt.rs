@@ -0,0 +1,79 @@
+//! Ctrl-C handling. The first interrupt asks running work to stop at the next safe point, a
+//! second one exits immediately. Stages that ran out of time are stopped the same way, through
+//! the token of the thread running them.
+
+use std::{cell::RefCell, sync::{Arc, atomic::{AtomicBool, Ordering}}};
+use tokio::sync::Notify;
+
+/// Exit code after an interrupted run, as used by shells for SIGINT.
+pub const EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static NOTIFY: Notify = Notify::const_new();
+
+thread_local! {
+    static STAGE_TOKEN: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+pub fn install() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+
+        eprintln!("Interrupted, stopping (press Ctrl-C again to exit immediately)");
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        NOTIFY.notify_waiters();
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(EXIT_CODE);
+        }
+    });
+}
+
+/// Fails with [`Interrupted`] once Ctrl-C was pressed, or the stage running on this thread was
+/// cancelled.
+pub fn check() -> anyhow::Result<()> {
+    let cancelled = STAGE_TOKEN.with_borrow(|token| token.as_ref().is_some_and(|t| t.load(Ordering::SeqCst)));
+    if cancelled || INTERRUPTED.load(Ordering::SeqCst) {
+        return Err(Interrupted.into());
+    }
+
+    Ok(())
+}
+
+/// Cancellation token of the stage running on this thread, for threads the stage starts.
+pub fn stage_token() -> Option<Arc<AtomicBool>> {
+    STAGE_TOKEN.with_borrow(|token| token.clone())
+}
+
+/// Runs `f` with `token` as the cancellation token of this thread; once it is set, [`check`]
+/// fails.
+pub fn with_stage_token<T>(token: Option<Arc<AtomicBool>>, f: impl FnOnce() -> T) -> T {
+    let previous = STAGE_TOKEN.replace(token);
+    let result = f();
+    STAGE_TOKEN.set(previous);
+
+    result
+}
+
+/// Completes once Ctrl-C was pressed.
+pub async fn wait() {
+    let notified = NOTIFY.notified();
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        return;
+    }
+
+    notified.await;
+}
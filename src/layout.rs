@@ -0,0 +1,94 @@
+//! Where downloaded attachments are stored inside an issue folder.
+
+use std::{fs, io::Read, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+
+/// Subfolders of the `split` layout.
+pub const ARCHIVES_DIR: &str = "archives";
+pub const FILES_DIR: &str = "files";
+
+/// Subfolder for the attachments only referenced in the worklog or changelog, in both layouts.
+pub const WORKLOGS_DIR: &str = "worklogs";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// All attachments directly in the issue folder.
+    #[default]
+    Flat,
+    /// Archives in `archives/`, all other attachments in `files/`.
+    Split,
+}
+
+impl Layout {
+    /// Folder the attachment `filename` is downloaded into.
+    pub fn download_dir(self, base: &Path, filename: &str) -> PathBuf {
+        match self {
+            Layout::Flat => base.to_path_buf(),
+            Layout::Split if is_archive(filename) => base.join(ARCHIVES_DIR),
+            Layout::Split => base.join(FILES_DIR),
+        }
+    }
+
+    /// Folders holding the downloads of an issue. The split layout includes the issue folder
+    /// itself, for attachments downloaded before switching layouts.
+    pub fn download_dirs(self, base: &Path) -> Vec<PathBuf> {
+        match self {
+            Layout::Flat => vec![base.to_path_buf(), base.join(WORKLOGS_DIR)],
+            Layout::Split => vec![base.join(ARCHIVES_DIR), base.join(FILES_DIR), base.to_path_buf(), base.join(WORKLOGS_DIR)],
+        }
+    }
+}
+
+/// Formats of the archives `extract_logs` unpacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    /// A file system image, see [`crate::fsimage`].
+    Image,
+}
+
+/// Extensions of the zip based archives, including Android packages.
+const ZIP_EXTENSIONS: &[&str] = &["zip", "apk", "aab"];
+
+/// Extensions of file system images.
+const IMAGE_EXTENSIONS: &[&str] = &["img", "ext2", "ext3", "ext4"];
+
+/// Local file header at the start of every non-empty zip.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Whether `filename` is an archive `extract_logs` unpacks.
+pub fn is_archive(filename: &str) -> bool {
+    archive_format(filename).is_some()
+}
+
+/// The format of the archive `filename` by its extension, `None` if it is no archive.
+pub fn archive_format(filename: &str) -> Option<ArchiveFormat> {
+    let lower = filename.to_ascii_lowercase();
+    if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        return Some(ArchiveFormat::TarGz);
+    }
+    if lower.ends_with(".tar") {
+        return Some(ArchiveFormat::Tar);
+    }
+
+    let extension = Path::new(&lower).extension()?.to_string_lossy().into_owned();
+    if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+        return Some(ArchiveFormat::Image);
+    }
+    ZIP_EXTENSIONS.contains(&extension.as_str()).then_some(ArchiveFormat::Zip)
+}
+
+/// The format of the archive at `path` by its name or, for zips with other extensions, by its
+/// first bytes.
+pub fn archive_file_format(path: &Path) -> Option<ArchiveFormat> {
+    if let Some(format) = path.file_name().and_then(|name| archive_format(&name.to_string_lossy())) {
+        return Some(format);
+    }
+
+    let mut head = [0u8; 4];
+    let is_zip = fs::File::open(path).and_then(|mut file| file.read_exact(&mut head)).map(|_| head == ZIP_MAGIC).unwrap_or(false);
+    is_zip.then_some(ArchiveFormat::Zip)
+}
@@ -0,0 +1,40 @@
+//! Fetches attachments from Jira issues, extracts the log files inside them and converts binary
+//! traces into text.
+
+pub mod attachment;
+pub mod budget;
+pub mod bundle;
+pub mod concurrency;
+pub mod config;
+pub mod convert;
+pub mod dedupe;
+pub mod diff;
+pub mod disk;
+pub mod dlt;
+pub mod extract;
+pub mod fetch;
+pub mod fibex;
+pub mod fsimage;
+pub mod filename;
+pub mod filter;
+pub mod hook;
+pub mod http_log;
+pub mod interrupt;
+pub mod layout;
+pub mod limit;
+pub mod manifest;
+pub mod netrc;
+pub mod output;
+pub mod pcap;
+pub mod pdf;
+pub mod perfetto;
+pub mod redact;
+pub mod report;
+pub mod retry;
+pub mod setup;
+pub mod sftp;
+pub mod source;
+pub mod stage;
+pub mod state;
+pub mod stats;
+pub mod verify;
@@ -0,0 +1,67 @@
+//! A cap on the file and socket operations running at the same time across all stages, for
+//! machines with tight limits on open files. The concurrency of a stage, like `--convert-jobs`,
+//! only applies below it.
+
+use std::sync::{Condvar, Mutex, OnceLock};
+
+static LIMITER: OnceLock<Limiter> = OnceLock::new();
+
+/// Counting semaphore usable from threads and, through [`acquire_async`], from async code.
+struct Limiter {
+    max: usize,
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Holds one of the slots of the limit until dropped.
+pub struct Permit {
+    limited: bool,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let (true, Some(limiter)) = (self.limited, LIMITER.get()) {
+            *limiter.available.lock().unwrap() += 1;
+            limiter.released.notify_one();
+        }
+    }
+}
+
+/// Allows at most `max` operations at the same time for the rest of the process. Without a
+/// call, operations are not limited.
+pub fn set_max_parallelism(max: usize) {
+    let max = max.max(1);
+    let limiter = Limiter { max, available: Mutex::new(max), released: Condvar::new() };
+    if LIMITER.set(limiter).is_err() {
+        panic!("The maximum parallelism can only be set once");
+    }
+}
+
+/// The maximum set by [`set_max_parallelism`], if any.
+pub fn max_parallelism() -> Option<usize> {
+    LIMITER.get().map(|limiter| limiter.max)
+}
+
+/// Waits for a free slot, blocking the thread.
+pub fn acquire() -> Permit {
+    let Some(limiter) = LIMITER.get() else {
+        return Permit { limited: false };
+    };
+
+    let mut available = limiter.available.lock().unwrap();
+    while *available == 0 {
+        available = limiter.released.wait(available).unwrap();
+    }
+    *available -= 1;
+
+    Permit { limited: true }
+}
+
+/// Waits for a free slot without blocking the async runtime.
+pub async fn acquire_async() -> Permit {
+    if LIMITER.get().is_none() {
+        return Permit { limited: false };
+    }
+
+    tokio::task::spawn_blocking(acquire).await.expect("Waiting for a free slot failed")
+}
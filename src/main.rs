@@ -1,20 +1,332 @@
-use std::{fs, path::PathBuf, io::{self, Cursor}};
+use std::{collections::{BTreeMap, HashMap}, fs, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant}};
+use anyhow::Context;
 use clap::{Parser, Subcommand};
-use indicatif::{ProgressBar, ProgressStyle};
-use regex::Regex;
-use reqwest::{Client, Proxy, header::{HeaderMap, HeaderValue, AUTHORIZATION}};
-use serde::{Deserialize, Serialize};
-use zip::ZipArchive;
-use futures_util::stream::StreamExt;
+use getlogs::{
+    budget::{Budget, BudgetExceeded, parse_duration},
+    bundle::{self, bundle_logs},
+    config::{Config, DefaultCommand, IpFamily},
+    convert::{self, ConvertOptions, ConvertOrder, Format, OutputEncoding, SplitBy, convert_logs},
+    dedupe::{self, DedupeReport},
+    diff,
+    disk::{self, DiskError},
+    extract::{self, ExtractOptions, LinkMode, extract_logs},
+    fetch::{self, FetchOptions, OnError, Session, fetch_attachments},
+    fibex::Fibex,
+    filter::{Filter, parse_size},
+    hook,
+    interrupt::{self, Interrupted},
+    layout::Layout,
+    limit,
+    manifest::{self, Manifest},
+    output,
+    redact::{self, Redactor},
+    report::{IssueReport, Report, ReportFormat},
+    setup,
+    source::{self, Source},
+    stage::{self, StageTimedOut, StageTiming},
+    state::{BatchState, IssueState},
+    stats::{self, LogStats},
+    verify,
+};
+use time::OffsetDateTime;
 
 #[derive(Parser)]
-#[clap(name = "getlogs", version = "1.0.0", author = "")]
+#[clap(name = "getlogs", version = "1.0.0", author = "", arg_required_else_help = true)]
 struct Cli {
+    /// Defaults to `default_command` of the config when only issues are given
     #[clap(subcommand)]
-    command: Command,
+    command: Option<Command>,
 
+    /// Issue keys, or ranges of them like `PROJ-10..PROJ-20`
     #[clap(global = true)]
     jira_ids: Vec<String>,
+
+    /// Issue tracker to fetch from
+    #[clap(long, global = true, value_enum, default_value_t = Source::Jira)]
+    source: Source,
+
+    /// GitLab project ID or path, or GitHub owner/repo, of the issues
+    #[clap(long, global = true)]
+    project: Option<String>,
+
+    /// Issue number within --project, can be repeated
+    #[clap(long = "issue", global = true, value_name = "IID")]
+    issues: Vec<String>,
+
+    /// Also process the Jira issues matching this JQL query
+    #[clap(long, global = true, value_name = "QUERY")]
+    jql: Option<String>,
+
+    /// Also process the Jira issues matching this saved filter
+    #[clap(long, global = true, value_name = "ID")]
+    filter_id: Option<String>,
+
+    /// Download and process the file at this link instead of issues, e.g. a log bundle on a CDN
+    #[clap(long, global = true, conflicts_with_all = ["jira_ids", "issues", "jql", "filter_id", "source", "project"])]
+    url: Option<String>,
+
+    /// Folder of the --url download, instead of its file name without the extension
+    #[clap(long, global = true, requires = "url")]
+    name: Option<String>,
+
+    /// Bearer token sent with the --url download, instead of a ~/.netrc entry for its host or user:password in the URL
+    #[clap(long, global = true, value_name = "TOKEN", requires = "url")]
+    url_token: Option<String>,
+
+    /// Skip files and attachments whose name matches this regex even if the include patterns match, overrides `exclude_regex` in the config
+    #[clap(long, global = true, value_name = "REGEX")]
+    exclude: Option<String>,
+
+    /// Name downloaded attachments after this template of Jira fields like `{summary-slug}-{original}`, overrides `download_name_template` in the config
+    #[clap(long, global = true, value_name = "TEMPLATE")]
+    name_from_field: Option<String>,
+
+    /// Only extract from archives, ignoring loose downloaded files
+    #[clap(long, global = true, conflicts_with = "loose_only")]
+    archives_only: bool,
+
+    /// Only copy loose downloaded files, ignoring archives
+    #[clap(long, global = true)]
+    loose_only: bool,
+
+    /// Only extract files with a line matching the pattern, e.g. a device serial
+    #[clap(long, global = true, value_name = "REGEX")]
+    content_grep: Option<String>,
+
+    /// Number of lines per file searched for --content-grep, 0 searches the whole file
+    #[clap(long, global = true, value_name = "N", default_value_t = 10000)]
+    content_lines: usize,
+
+    /// Only extract this exact path from archives, and with --stdout this loose file, can be repeated
+    #[clap(long = "entry", global = true, value_name = "PATH")]
+    entries: Vec<String>,
+
+    /// Skip the issues already completed by the previous, unfinished run
+    #[clap(long, global = true, conflicts_with = "restart")]
+    resume: bool,
+
+    /// Forget the progress of the previous run and process all issues
+    #[clap(long, global = true)]
+    restart: bool,
+
+    /// Only list the files extract would write and their sizes, without writing anything
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// Write the content of the single matching file to stdout instead of logs-extracted
+    #[clap(long, global = true, conflicts_with = "dry_run")]
+    stdout: bool,
+
+    /// With --stdout, write all matching files one after the other
+    #[clap(long, global = true, requires = "stdout")]
+    concat: bool,
+
+    /// Download attachments and extract files again even if they are unchanged since the last run,
+    /// and let `setup` replace an existing config
+    #[clap(long, global = true)]
+    force: bool,
+
+    /// Before skipping a file logs-extracted already has, also compare its SHA-256
+    #[clap(long, global = true, conflicts_with = "force")]
+    verify: bool,
+
+    /// After extracting, remove identical files and list line ranges repeated across files
+    #[clap(long, global = true, conflicts_with_all = ["dry_run", "stdout"])]
+    dedupe: bool,
+
+    /// Link matching loose files into logs-extracted instead of copying them, hard links by default
+    #[clap(long, global = true, value_name = "MODE", value_enum, num_args = 0..=1, default_missing_value = "hard")]
+    link: Option<LinkMode>,
+
+    /// Read buffer size per extracted file in bytes, bounding the memory used for huge entries
+    #[clap(long, global = true, value_name = "BYTES", default_value_t = extract::DEFAULT_BUFFER_SIZE)]
+    buffer_size: usize,
+
+    /// Stop the run before downloading more than this many bytes in total, e.g. 20G
+    #[clap(long, global = true, value_name = "BYTES", value_parser = parse_size)]
+    max_total_bytes: Option<u64>,
+
+    /// Stop the run once it took longer than this, e.g. 90m or 2h
+    #[clap(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    max_duration: Option<Duration>,
+
+    /// Give up the downloads of an issue after this long and continue with the next, e.g. 30m
+    #[clap(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    fetch_timeout: Option<Duration>,
+
+    /// Give up extracting the logs of an issue after this long and continue with the next
+    #[clap(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    extract_timeout: Option<Duration>,
+
+    /// Give up converting the logs of an issue after this long and continue with the next
+    #[clap(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    convert_timeout: Option<Duration>,
+
+    /// Stop retrying a download this long after it started, e.g. 10m
+    #[clap(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    retry_deadline: Option<Duration>,
+
+    /// Adapt the number of parallel downloads to the throughput, up to max_concurrent_downloads
+    #[clap(long, global = true)]
+    concurrency_auto: bool,
+
+    /// Milliseconds between progress bar redraws, raise it on slow remote terminals
+    #[clap(long, global = true, value_name = "MS", default_value_t = 100)]
+    progress_interval: u64,
+
+    /// Connect over IPv4 to servers that also have IPv6 addresses, overrides `ip_family` in the config
+    #[clap(long, global = true, conflicts_with = "prefer_ipv6")]
+    prefer_ipv4: bool,
+
+    /// Connect over IPv6 to servers that also have IPv4 addresses
+    #[clap(long, global = true)]
+    prefer_ipv6: bool,
+
+    /// Append method, URL, status, headers and timing of every HTTP request to this file
+    #[clap(long, global = true, value_name = "PATH")]
+    http_log: Option<PathBuf>,
+
+    /// Whether a failed issue, download or conversion stops the run or is reported at the end.
+    /// Without it, the remaining downloads and conversions of an issue are tried but the run
+    /// stops after the issue
+    #[clap(long, global = true, value_enum, value_name = "POLICY")]
+    on_error: Option<OnError>,
+
+    /// Only re-attempt the downloads recorded as failed in the manifest
+    #[clap(long, global = true)]
+    retry_failed: bool,
+
+    /// Only download attachments created since the last successful fetch of the issue
+    #[clap(long, global = true)]
+    since_last_run: bool,
+
+    /// Download the smallest attachments first
+    #[clap(long, global = true)]
+    smallest_first: bool,
+
+    /// Download Jira's thumbnails of image attachments instead of the originals
+    #[clap(long, global = true)]
+    thumbnails: bool,
+
+    /// Also download attachments linked from the worklog or changelog into worklogs/
+    #[clap(long, global = true, conflicts_with = "comment_id")]
+    worklogs: bool,
+
+    /// Only download the first bytes of each attachment into <name>.sample, e.g. 1M
+    #[clap(long, global = true, value_name = "BYTES", value_parser = parse_size, conflicts_with = "thumbnails")]
+    sample: Option<u64>,
+
+    /// Only download the attachments referenced in the given comment
+    #[clap(long, global = true, value_name = "ID")]
+    comment_id: Option<String>,
+
+    /// Format DLT traces are converted into
+    #[clap(long, global = true, value_enum, default_value_t = Format::Logcat)]
+    format: Format,
+
+    /// Cap on the downloads, extractions and conversions running at the same time across all stages
+    #[clap(long, global = true, value_name = "N")]
+    max_parallelism: Option<usize>,
+
+    /// Number of files converted at the same time, at most --max-parallelism
+    #[clap(long, global = true, value_name = "N", default_value_t = 1)]
+    convert_jobs: usize,
+
+    /// Order in which files are converted
+    #[clap(long, global = true, value_enum, default_value_t = ConvertOrder::Name)]
+    convert_order: ConvertOrder,
+
+    /// Replace the spans matching the redaction_rules in the converted logs
+    #[clap(long, global = true)]
+    redact: bool,
+
+    /// Encoding of converted text logs, UTF-16 with a byte order mark for legacy Windows viewers
+    #[clap(long, global = true, value_enum, value_name = "ENCODING", default_value_t = OutputEncoding::Utf8)]
+    output_encoding: OutputEncoding,
+
+    /// Write one logcat file per DLT application or context ID
+    #[clap(long, global = true, value_enum, value_name = "ID")]
+    split_by: Option<SplitBy>,
+
+    /// Only convert the DLT messages of this ECU ID, can be repeated
+    #[clap(long = "ecu", global = true, value_name = "ID")]
+    ecus: Vec<String>,
+
+    /// Name of converted files, e.g. `converted/{issue}-{basename}.{ext}`, overrides `output_name_template` in the config
+    #[clap(long, global = true, value_name = "TEMPLATE")]
+    template: Option<String>,
+
+    /// Append newly converted logs to <ISSUE>.combined.log in the issue folder
+    #[clap(long, global = true)]
+    combined: bool,
+
+    /// Start the combined log with the issue key, summary, status, assignee, fetch time and sources
+    #[clap(long, global = true, requires = "combined")]
+    header: bool,
+
+    /// Print the lines appended to the combined log to stdout, all other output goes to stderr
+    #[clap(long, global = true, requires = "combined", conflicts_with = "stdout")]
+    follow: bool,
+
+    /// Write the combined log from scratch instead of appending
+    #[clap(long, global = true, requires = "combined")]
+    rebuild: bool,
+
+    /// Only write the first N lines of each converted log
+    #[clap(long, global = true, value_name = "N", conflicts_with = "tail")]
+    head: Option<usize>,
+
+    /// Only write the last N lines of each converted log
+    #[clap(long, global = true, value_name = "N")]
+    tail: Option<usize>,
+
+    /// Only download attachments matching the expression, e.g. 'name~"\.zip$" and size>10M'
+    #[clap(long, global = true, value_name = "EXPR")]
+    filter_expr: Option<String>,
+
+    /// Write a report of the attachments of each issue to --report-file as the issues complete
+    #[clap(long, global = true, value_enum, value_name = "FORMAT", requires = "report_file")]
+    report: Option<ReportFormat>,
+
+    /// File the --report is written to
+    #[clap(long, global = true, value_name = "PATH", requires = "report")]
+    report_file: Option<PathBuf>,
+
+    /// Print how long each step took per issue when done, also added to --report json
+    #[clap(long, global = true)]
+    profile_timing: bool,
+
+    /// Print stats as JSON
+    #[clap(long, global = true)]
+    json: bool,
+
+    /// Write the hashes of the downloads to SHA256SUMS in the issue folder, for `sha256sum -c`
+    #[clap(long, global = true)]
+    checksum_manifest: bool,
+
+    /// List each skipped file instead of only counting them per issue
+    #[clap(short, long, global = true)]
+    verbose: bool,
+
+    /// FIBEX file describing non-verbose DLT messages, overrides `fibex_path` in the config
+    #[clap(long, global = true, value_name = "PATH")]
+    fibex: Option<PathBuf>,
+
+    /// Open the extracted logs of the issue when done
+    #[clap(long, global = true)]
+    open: bool,
+
+    /// Pack the extracted logs into <ISSUE>-logs.tar.gz in the issue folder
+    #[clap(long, global = true)]
+    bundle: bool,
+
+    /// Remove the loose extracted files after bundling
+    #[clap(long, global = true, requires = "bundle")]
+    remove_loose: bool,
+
+    /// Shell command run after each issue, overrides `post_issue_command` in the config
+    #[clap(long, global = true, value_name = "COMMAND")]
+    post_hook: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -23,198 +335,717 @@ enum Command {
     Extract,
     Convert,
     All,
+    /// Run all steps for two issues and compare their attachments and logs
+    Diff,
+    /// Summarize the converted logs per level, tag and hour
+    Stats,
+    /// Check the downloaded files against the hashes in the manifest, offline
+    Verify,
+    /// Apply the redaction_rules to the extracted and converted text files in place
+    Redact,
+    /// Print the effective config, with the file each value came from
+    PrintConfig,
+    /// Ask for the Jira URL, credentials, proxy and download folder and write the config
+    #[clap(alias = "init")]
+    Setup,
 }
 
-/// Configuration stored in ~/.getlogs/config.json
-#[derive(Serialize, Deserialize, Debug)]
-struct Config {
-    default_path: PathBuf,
-    jira_url: String,
-    proxy: Option<String>,
-    bearer_token: Option<String>,
-    user_email: Option<String>,
-    api_token: Option<String>,
-    logfile_regex: String,
-    archive_regex: Option<String>
-}
-
-impl Config {
-    fn load_or_create() -> io::Result<Self> {
-        let home = dirs::home_dir().expect("Could not find home directory");
-
-        let config_dir = home.join(".getlogs");
-        if !config_dir.exists() {
-            fs::create_dir_all(&config_dir)?;
-        }
-
-        let config_file = config_dir.join("config.json");
-        if !config_file.exists() {
-            let default = Config {
-                default_path: home.join("logs"),
-                jira_url: "https://your-jira-server.com".to_string(),
-                proxy: None,
-                bearer_token: None,
-                user_email: None,
-                api_token: None,
-                logfile_regex: r".*\.(logcat|dlt|txt)$".to_string(),
-                archive_regex: None
-            };
-
-            let contents = serde_json::to_string_pretty(&default)?;
-            fs::write(&config_file, contents)?;
-
-            eprintln!("Created default config at {}. Please update it with either `bearer_token` or `user_email` + `api_token`, then rerun.", config_file.display());
-            std::process::exit(1);
+impl From<DefaultCommand> for Command {
+    fn from(command: DefaultCommand) -> Self {
+        match command {
+            DefaultCommand::Fetch => Command::Fetch,
+            DefaultCommand::Extract => Command::Extract,
+            DefaultCommand::Convert => Command::Convert,
+            DefaultCommand::All => Command::All,
         }
-
-        let data = fs::read_to_string(&config_file)?;
-        let config: Config = serde_json::from_str(&data)?;
-
-        Ok(config)
     }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let config = Config::load_or_create()?;
+    // Ctrl-C at a prompt of setup quits right away
+    if !matches!(cli.command, Some(Command::Setup)) {
+        interrupt::install();
+    }
+
+    match run(cli).await {
+        Err(err) if err.is::<Interrupted>() => std::process::exit(interrupt::EXIT_CODE),
+        Err(err) if err.is::<BudgetExceeded>() => {
+            output::failure(&err);
+            eprintln!("Finished downloads are kept, run again with --resume to continue with the remaining issues");
+            std::process::exit(1);
+        }
+        Err(err) if err.is::<DiskError>() => {
+            output::failure(&err);
+            eprintln!("Free up space or pick another default_path, then run again with --resume; unfinished downloads continue where they stopped");
+            std::process::exit(1);
+        }
+        result => result,
+    }
+}
+
+async fn run(mut cli: Cli) -> anyhow::Result<()> {
+    match cli.command {
+        Some(Command::PrintConfig) => return print_config(&cli),
+        Some(Command::Setup) => return setup::run(cli.force).await,
+        _ => {}
+    }
+
+    if cli.stdout || cli.follow {
+        output::use_stderr_only();
+    }
+    if cli.verbose {
+        output::use_verbose();
+    }
+
+    let mut config = Config::load_or_create()?;
+    // Issue keys have a dash and a number, so they are never taken for a command
+    let command = match (cli.command.take(), config.default_command) {
+        (Some(command), _) => command,
+        (None, Some(default)) => default.into(),
+        (None, None) => anyhow::bail!("No command given, run e.g. `getlogs all {}`, or set default_command in the config", cli.jira_ids.join(" ")),
+    };
+    apply_overrides(&cli, &mut config);
+    let config = Arc::new(config);
+    if let Some(max) = cli.max_parallelism {
+        limit::set_max_parallelism(max);
+    }
+    if cli.url.is_some() {
+        cli.source = Source::Url;
+    }
+    let mut issues: Vec<String> = match (cli.source, &cli.project) {
+        (Source::Url, _) => match &cli.name {
+            Some(name) => vec![name.clone()],
+            None => vec![source::url_folder_name(cli.url.as_deref().unwrap_or_default())?],
+        },
+        (Source::Jira | Source::Sftp, _) => source::expand_ranges(cli.jira_ids.iter().chain(&cli.issues))?,
+        (_, Some(project)) => source::expand_ranges(cli.jira_ids.iter().chain(&cli.issues))?
+            .iter()
+            .map(|iid| source::issue_key(project, iid))
+            .collect(),
+        (_, None) => anyhow::bail!("--project is required for --source gitlab and github"),
+    };
+
+    // Only needs local files, no session and no credentials
+    if matches!(command, Command::Verify) {
+        return verify_issues(&config, &issues, cli.on_error);
+    }
+
+    let redactor = if cli.redact || matches!(command, Command::Redact) {
+        Some(Redactor::new(config.redaction_rules.as_deref().unwrap_or_default())?)
+    } else {
+        None
+    };
+    if let (Command::Redact, Some(redactor)) = (&command, &redactor) {
+        return redact_issues(&config, &issues, redactor);
+    }
+
+    let budget = Arc::new(Budget::new(cli.max_total_bytes, cli.max_duration));
+    let session = open_session(&config, &cli, &budget).await?;
+
+    let mut queries = Vec::new();
+    if let Some(id) = &cli.filter_id {
+        queries.push(fetch::filter_jql(&session, id).await?);
+    }
+    queries.extend(cli.jql.clone());
+    if !queries.is_empty() && cli.source != Source::Jira {
+        anyhow::bail!("--jql and --filter-id need --source jira");
+    }
+    for jql in &queries {
+        let found = fetch::search_issues(&session, jql).await?;
+        output::info(format!("{} issues match `{}`", found.len(), jql));
+        for key in found {
+            if !issues.contains(&key) {
+                issues.push(key);
+            }
+        }
+    }
+
+    // Issues of the projects in projects_map are fetched from their own server
+    let project_configs = if cli.source == Source::Jira { config.project_configs()? } else { BTreeMap::new() };
+    let mut project_sessions = HashMap::new();
+    if matches!(command, Command::Fetch | Command::All | Command::Diff) {
+        for (project, project_config) in &project_configs {
+            if issues.iter().any(|issue| source::jira_project(issue) == Some(project)) {
+                project_sessions.insert(project.as_str(), open_session(project_config, &cli, &budget).await?);
+            }
+        }
+    }
+
+    let extract_options = Arc::new(ExtractOptions {
+        archives_only: cli.archives_only,
+        loose_only: cli.loose_only,
+        content_regex: cli.content_grep.as_deref().map(regex::bytes::Regex::new).transpose()?,
+        content_lines: Some(cli.content_lines).filter(|&n| n > 0),
+        entries: cli.entries.clone(),
+        buffer_size: cli.buffer_size.max(1),
+        link: cli.link,
+        dry_run: cli.dry_run,
+        stdout: cli.stdout,
+        concat: cli.concat,
+        force: cli.force,
+        verify: cli.verify,
+    });
+    let filter = cli.filter_expr.as_deref().map(Filter::parse).transpose()?;
+    let convert_options = Arc::new(ConvertOptions {
+        head: cli.head,
+        tail: cli.tail,
+        fibex: config.fibex_path.as_ref().map(|p| Fibex::load(p)).transpose()?,
+        rules: convert::Rule::parse_all(config.convert_rules.as_deref().unwrap_or_default())?,
+        format: cli.format,
+        jobs: cli.convert_jobs,
+        order: cli.convert_order,
+        redactor,
+        split_by: cli.split_by,
+        ecus: cli.ecus.clone(),
+        encoding: cli.output_encoding,
+        output_template: config.output_name_template.clone(),
+        fail_fast: cli.on_error == Some(OnError::FailFast),
+    });
+
+    if cli.dry_run && !matches!(command, Command::Extract) {
+        anyhow::bail!("--dry-run is only supported by extract");
+    }
+
+    if cli.stdout && !matches!(command, Command::Extract) {
+        anyhow::bail!("--stdout is only supported by extract");
+    }
+
+    if cli.stdout && issues.len() > 1 && !cli.concat {
+        anyhow::bail!("--stdout takes a single issue, pass --concat to write the files of all {} issues", issues.len());
+    }
+
+    if cli.combined && cli.format != Format::Logcat {
+        anyhow::bail!("--combined needs --format logcat");
+    }
+
+    if cli.combined && cli.output_encoding != OutputEncoding::Utf8 {
+        anyhow::bail!("--combined needs --output-encoding utf8");
+    }
+
+    if cli.split_by.is_some() && (cli.combined || cli.format != Format::Logcat) {
+        anyhow::bail!("--split-by needs --format logcat and cannot be combined with --combined");
+    }
+
+    if matches!(command, Command::Diff) && issues.len() != 2 {
+        anyhow::bail!("diff needs exactly two issues, got {}", issues.len());
+    }
+
+    let data_path = PathBuf::from(&config.default_path);
+    let track_batch = matches!(command, Command::Fetch | Command::Extract | Command::Convert | Command::All) && !cli.dry_run && !cli.stdout;
+    if cli.restart {
+        BatchState::clear(&data_path)?;
+    }
+    let mut batch = if cli.resume { BatchState::load(&data_path)? } else { BatchState::default() };
+
+    let mut report = match (cli.report, &cli.report_file) {
+        (Some(format), Some(path)) => Some(Report::create(path, format).with_context(|| format!("Creating {}", path.display()))?),
+        _ => None,
+    };
+
+    let mut all_stats = Vec::new();
+    let mut timed_out = 0;
+    let mut failed = Vec::new();
+    let mut profile = Vec::new();
 
-    for jira_id in &cli.jira_ids {
-        let base_path = PathBuf::from(&config.default_path).join(jira_id);
-        fs::create_dir_all(&base_path)?;
+    for jira_id in &issues {
+        interrupt::check()?;
+        session.budget().check()?;
 
-        println!("=== {} ===", jira_id);
+        if track_batch && batch.completed.contains(jira_id) {
+            output::info(format!("Skipping {}, completed by the previous run", jira_id));
+            continue;
+        }
+
+        let session = source::jira_project(jira_id).and_then(|project| project_sessions.get(project)).unwrap_or(&session);
+        let mut timings = Vec::new();
+        let mut transfer = None;
+        let processed: anyhow::Result<()> = async {
+            // Failed downloads and conversions fail the issue once the remaining steps ran
+            let mut failures = Vec::new();
+            // A timed-out step is given up, the steps after it work with what it left
+            let mut issue_timed_out = false;
+            let base_path = PathBuf::from(&config.default_path).join(source::folder_name(jira_id));
+            fs::create_dir_all(&base_path)
+                .with_context(|| format!("Cannot create {}", base_path.display()))
+                .map_err(|err| disk::classify(err, &config.default_path))?;
+            disk::check_destination(&base_path)?;
+
+            // Keep stdout parseable when printing JSON
+            let json_output = cli.json && matches!(command, Command::Stats);
+            if !json_output {
+                output::section(jira_id);
+            }
+
+            if matches!(command, Command::Fetch | Command::All | Command::Diff) {
+                let mut state = IssueState::load(&base_path)?;
+                let fetch_options = FetchOptions {
+                    retry_failed: cli.retry_failed,
+                    created_after: if cli.since_last_run { state.last_fetch()? } else { None },
+                    smallest_first: cli.smallest_first,
+                    comment_id: cli.comment_id.clone(),
+                    filter: filter.clone(),
+                    fail_fast: cli.on_error == Some(OnError::FailFast),
+                    thumbnails: cli.thumbnails,
+                    worklogs: cli.worklogs,
+                    force: cli.force,
+                    sample: cli.sample,
+                    url: cli.url.clone(),
+                };
+
+                let started = OffsetDateTime::now_utc();
+                let timer = Instant::now();
+                let fetched = stage::run("fetch", cli.fetch_timeout, fetch_attachments(session, jira_id, &base_path, &fetch_options)).await;
+                timings.push(StageTiming::since("fetch", timer));
+                // Also lists the files that were downloaded when others failed
+                if cli.checksum_manifest {
+                    let listed = verify::write_checksums(&base_path, config.download_layout.unwrap_or_default())?;
+                    output::info(format!("Wrote the hashes of {} files to {}", listed, manifest::CHECKSUMS_FILE));
+                }
+                let fetched = skip_timed_out(fetched, cli.on_error);
+                // A failed fetch skips the rest of the issue, the report should still list its downloads
+                if let (Err(_), Some(report)) = (&fetched, &mut report) {
+                    report.write(&IssueReport::new(jira_id, &Manifest::load(&base_path)?).with_timings(if cli.profile_timing { &timings } else { &[] }))?;
+                }
+                match fetched? {
+                    Some(fetched) => {
+                        transfer = Some(fetched.transfer);
+
+                        // Attachments that failed, were left out or only sampled are fetched again by --since-last-run
+                        match fetched.failure {
+                            Some(failure) => failures.push(failure),
+                            None if fetch_options.is_complete() && cli.exclude.is_none() => {
+                                state.set_last_fetch(started)?;
+                                state.save(&base_path)?;
+                            }
+                            None => {}
+                        }
+                    }
+                    None => issue_timed_out = true,
+                }
+            }
+
+            let extract_path = base_path.join("logs-extracted");
+            if matches!(command, Command::Extract | Command::All | Command::Diff) {
+                let (src, dest, config, options) = (base_path.clone(), extract_path.clone(), config.clone(), extract_options.clone());
+                let timer = Instant::now();
+                let extracted = stage::run_blocking("extract", cli.extract_timeout, move || extract_logs(&src, &dest, &config, &options)).await;
+                let extracted = skip_timed_out(extracted, cli.on_error);
+                timings.push(StageTiming::since("extract", timer));
+                if extracted?.is_none() {
+                    issue_timed_out = true;
+                }
+
+                if cli.dedupe {
+                    let timer = Instant::now();
+                    print_dedupe(&dedupe::dedupe_logs(&extract_path, cli.dry_run)?, cli.dry_run);
+                    timings.push(StageTiming::since("dedupe", timer));
+                }
+            }
+
+            if matches!(command, Command::Convert | Command::All | Command::Diff) {
+                let (dir, options) = (extract_path.clone(), convert_options.clone());
+                let timer = Instant::now();
+                let converted = stage::run_blocking("convert", cli.convert_timeout, move || convert_logs(&dir, &options)).await;
+                let converted = skip_timed_out(converted, cli.on_error);
+                timings.push(StageTiming::since("convert", timer));
+                match converted? {
+                    Some(converted) => {
+                        if cli.combined {
+                            let timer = Instant::now();
+                            convert::append_combined(&base_path, &converted.converted, cli.rebuild, cli.header, cli.follow)?;
+                            timings.push(StageTiming::since("combine", timer));
+                        }
+                        failures.extend(converted.failure(&extract_path));
+                    }
+                    None => issue_timed_out = true,
+                }
+            }
+
+            if matches!(command, Command::Stats) {
+                let issue_stats = stats::stats_logs(&extract_path)?;
+                if !json_output {
+                    issue_stats.iter().for_each(print_stats);
+                }
+                all_stats.extend(issue_stats);
+            }
 
-        if matches!(cli.command, Command::Fetch | Command::All) {
-            fetch_attachments(&config, jira_id, &base_path).await?;
+            if cli.bundle && extract_path.is_dir() {
+                let name = source::folder_name(jira_id);
+                let archive = base_path.join(bundle::archive_name(&name));
+                let timer = Instant::now();
+                bundle_logs(&extract_path, &archive, &format!("{}-logs", name))?;
+                timings.push(StageTiming::since("bundle", timer));
+
+                if cli.remove_loose {
+                    fs::remove_dir_all(&extract_path)?;
+                }
+
+                output::success(format!("Bundled logs into {}", archive.display()));
+            }
+
+            if let Some(report) = &mut report {
+                let issue_report = IssueReport::new(jira_id, &Manifest::load(&base_path)?)
+                    .with_timings(if cli.profile_timing { &timings } else { &[] })
+                    .with_transfer(transfer.clone());
+                report.write(&issue_report)?;
+            }
+
+            if let Some(command) = config.post_issue_command.as_deref().filter(|_| !cli.dry_run) {
+                run_hook(hook::post_issue(command, jira_id, &base_path).await, &config)?;
+            }
+
+            // An issue with failed files or a timed-out step stays open, so --resume retries it
+            if track_batch && failures.is_empty() && !issue_timed_out {
+                batch.completed.insert(jira_id.clone());
+                batch.save(&data_path)?;
+            }
+            if issue_timed_out {
+                timed_out += 1;
+            }
+
+            match failures.as_slice() {
+                [] => Ok(()),
+                [_] => Err(failures.remove(0)),
+                _ => anyhow::bail!("{}", failures.iter().map(|err| format!("{:#}", err)).collect::<Vec<_>>().join("; ")),
+            }
         }
+        .await;
 
-        let extract_path = base_path.join("logs-extracted");
-        if matches!(cli.command, Command::Extract | Command::All) {
-            extract_logs(&base_path, &extract_path, &config)?;
+        // Also catches a full disk while extracting or converting
+        let processed = processed.map_err(|err| disk::classify(err, &config.default_path));
+        match processed {
+            Err(err) if cli.on_error == Some(OnError::Continue) && !err.is::<Interrupted>() && !err.is::<BudgetExceeded>() && !err.is::<DiskError>() => {
+                output::failure(format!("{:#}", err));
+                failed.push(jira_id.as_str());
+            }
+            result => result?,
         }
 
-        if matches!(cli.command, Command::Convert | Command::All) {
-            convert_logs(&extract_path)?;
+        if cli.profile_timing && !timings.is_empty() {
+            profile.push((jira_id.as_str(), timings));
         }
     }
 
-    Ok(())
-}
+    if cli.profile_timing {
+        print_profile(&profile);
+    }
 
-async fn auth_request(client: &Client, config: &Config, url: &str) -> anyhow::Result<reqwest::Response> {
-    if let Some(token) = &config.bearer_token {
-        let auth_val = format!("Bearer {}", token);
-        let mut headers = HeaderMap::new();
+    if let Some(command) = config.post_run_command.as_deref().filter(|_| !cli.dry_run) {
+        run_hook(hook::post_run(command, &issues, &data_path).await, &config)?;
+    }
 
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(&auth_val)?);
+    let hint = if track_batch { ", run again with --resume to retry them" } else { "" };
+    if !failed.is_empty() {
+        let timed_out = if timed_out > 0 { format!(" and {} timed out", timed_out) } else { String::new() };
+        anyhow::bail!("{} of {} issues failed ({}){}{}", failed.len(), issues.len(), failed.join(", "), timed_out, hint);
+    }
 
-        Ok(client.get(url).headers(headers).send().await?)
-    } else if let (Some(email), Some(api_token)) = (&config.user_email, &config.api_token) {
-        Ok(client.get(url).basic_auth(email, Some(api_token)).send().await?)
-    } else {
-        anyhow::bail!("No authentication configured: set either bearer_token or user_email+api_token in config");
+    if timed_out > 0 {
+        anyhow::bail!("{} of {} issues timed out{}", timed_out, issues.len(), hint);
+    }
+
+    if track_batch {
+        BatchState::clear(&data_path)?;
+    }
+
+    if cli.json && matches!(command, Command::Stats) {
+        println!("{}", serde_json::to_string_pretty(&all_stats)?);
     }
+
+    if cli.open {
+        match issues.as_slice() {
+            [issue] => open_output(&data_path.join(source::folder_name(issue))),
+            _ => output::warning("--open only works with a single issue"),
+        }
+    }
+
+    if let (Command::Diff, [left, right]) = (&command, issues.as_slice()) {
+        let left_path = PathBuf::from(&config.default_path).join(source::folder_name(left));
+        let right_path = PathBuf::from(&config.default_path).join(source::folder_name(right));
+        print_diff(left, right, &left_path, &right_path, config.download_layout.unwrap_or_default())?;
+    }
+
+    Ok(())
 }
 
-fn create_http_client(config: &Config) -> Client {
-    if let Some(proxy_url) = &config.proxy {
-        Client::builder()
-            .proxy(Proxy::all(proxy_url).expect("Could not resolve proxy URL"))
-            .build().expect("Could not create HTTP client with specified proxy URL")
-    } else {
-        Client::new()
+/// Passes on the error of a post command with `post_command_fails_run`, otherwise only reports it.
+fn run_hook(result: anyhow::Result<()>, config: &Config) -> anyhow::Result<()> {
+    match result {
+        Err(err) if !config.post_command_fails_run.unwrap_or(false) => {
+            output::warning(format!("{:#}", err));
+            Ok(())
+        }
+        result => result,
     }
 }
 
-async fn fetch_attachments(config: &Config, issue: &str, dest: &PathBuf) -> anyhow::Result<()> {
-    let client = create_http_client(&config);
+/// A session for `config` with the network settings of `cli`, drawing on `budget`.
+async fn open_session<'a>(config: &'a Config, cli: &Cli, budget: &Arc<Budget>) -> anyhow::Result<Session<'a>> {
+    let mut session = Session::with_source(config, cli.source).await?;
+    if let Some(path) = &cli.http_log {
+        session = session.with_http_log(path)?;
+    }
+    session = session
+        .with_budget(budget.clone())
+        .with_progress_interval(Duration::from_millis(cli.progress_interval));
+    if let Some(deadline) = cli.retry_deadline {
+        session = session.with_retry_deadline(deadline);
+    }
+    if cli.concurrency_auto {
+        session = session.with_adaptive_concurrency();
+    }
+    if let Some(url) = &cli.url {
+        session = session.with_url_credentials(url, cli.url_token.clone());
+    }
 
-    let url = format!("{}/rest/api/2/issue/{}?fields=attachment", config.jira_url, issue);
+    Ok(session)
+}
 
-    // Fetch the attachment field
-    let response = auth_request(&client, config, &url).await?;
-    let json: serde_json::Value = response.json().await?;
+/// Opens the extracted logs, or the issue folder if nothing was extracted, in the default file
+/// manager. Without a desktop session there is nothing to open them in, so only a note is shown.
+fn open_output(base_path: &Path) {
+    let extract_path = base_path.join("logs-extracted");
+    let target = if extract_path.is_dir() { extract_path } else { base_path.to_path_buf() };
 
-    if let Some(atts) = json["fields"]["attachment"].as_array() {
-        // Fetch the attachments
-        for att in atts {
-            let fname = att["filename"].as_str().unwrap();
-            let file_url = att["content"].as_str().unwrap();
-            let out_path = dest.join(fname);
+    let headless = std::env::var_os("CI").is_some()
+        || (cfg!(all(unix, not(target_os = "macos")))
+            && std::env::var_os("DISPLAY").is_none()
+            && std::env::var_os("WAYLAND_DISPLAY").is_none());
 
-            let response = auth_request(&client, config, file_url).await?;
-            let total = response.content_length().unwrap_or(0);
+    if headless {
+        println!("No desktop session, not opening {}", target.display());
+    } else if let Err(err) = open::that(&target) {
+        output::warning(format!("Could not open {}: {}", target.display(), err));
+    }
+}
 
-            let progress_bar = ProgressBar::new(total);
-            let style = ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")?
-                .progress_chars("=>-");
-            progress_bar.set_style(style);
+fn print_diff(left: &str, right: &str, left_path: &Path, right_path: &Path, layout: Layout) -> anyhow::Result<()> {
+    output::section(&format!("{} vs {}", left, right));
 
-            let mut file = fs::File::create(&out_path)?;
-            let mut stream = response.bytes_stream();
+    let mut attachments = diff::Comparison::default();
+    for (left_dir, right_dir) in layout.download_dirs(left_path).iter().zip(layout.download_dirs(right_path)) {
+        let comparison = diff::compare_dirs(left_dir, &right_dir)?;
+        attachments.only_left.extend(comparison.only_left);
+        attachments.only_right.extend(comparison.only_right);
+    }
 
-            while let Some(chunk) = stream.next().await {
-                let chunk = chunk?;
-                progress_bar.inc(chunk.len() as u64);
-                io::copy(&mut Cursor::new(chunk), &mut file)?;
-            }
+    for name in &attachments.only_left {
+        println!("Attachment only in {}: {}", left, name);
+    }
+    for name in &attachments.only_right {
+        println!("Attachment only in {}: {}", right, name);
+    }
+
+    let left_logs = left_path.join("logs-extracted");
+    let right_logs = right_path.join("logs-extracted");
+    let logs = diff::compare_dirs(&left_logs, &right_logs)?;
+    for name in &logs.only_left {
+        println!("Log only in {}: {}", left, name);
+    }
+    for name in &logs.only_right {
+        println!("Log only in {}: {}", right, name);
+    }
 
-            progress_bar.finish_and_clear();
-            println!("Downloaded {}", fname);
+    let mut identical = 0;
+    for name in &logs.common {
+        let left_label = format!("{}/{}", left, name);
+        let right_label = format!("{}/{}", right, name);
+
+        match diff::diff_files(&left_logs.join(name), &right_logs.join(name), &left_label, &right_label)? {
+            Some(diff) => print!("{}", diff),
+            None => identical += 1,
         }
     }
 
+    output::summary(format!("{} common logs, {} identical, {} only in {}, {} only in {}",
+        logs.common.len(), identical, logs.only_left.len(), left, logs.only_right.len(), right), true);
+
     Ok(())
 }
 
-fn extract_logs(src: &PathBuf, dest: &PathBuf, config: &Config) -> anyhow::Result<()> {
-    fs::create_dir_all(dest)?;
-    let logfile_regex = Regex::new(&config.logfile_regex).unwrap();
-    let zipfile_regex = Regex::new(&config.archive_regex.as_deref().unwrap_or(&config.logfile_regex)).expect("No zip archive regex");
+fn verify_issues(config: &Config, issues: &[String], on_error: Option<OnError>) -> anyhow::Result<()> {
+    let layout = config.download_layout.unwrap_or_default();
+    let mut failed = 0;
 
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let path = entry.path();
+    for issue in issues {
+        output::section(issue);
 
-        if path.is_file() {
-            let fname = path.file_name().unwrap().to_string_lossy();
+        let verification = verify::verify_issue(&config.default_path.join(source::folder_name(issue)), layout)?;
+        for name in &verification.corrupted {
+            output::failure(format!("Corrupted: {}", name));
+        }
+        for name in &verification.missing {
+            output::failure(format!("Missing: {}", name));
+        }
+        for name in &verification.unexpected {
+            output::failure(format!("Not in the manifest: {}", name));
+        }
+        if !verification.unhashed.is_empty() {
+            output::warning(format!("{} files were downloaded without a recorded hash, only their size was checked", verification.unhashed.len()));
+        }
 
-            if logfile_regex.is_match(&fname) {
-                fs::copy(&path, dest.join(&*fname))?;
-            } else if path.extension().map(|e| e == "zip").unwrap_or(false) {
-                let file = fs::File::open(&path)?;
-                let mut zip = ZipArchive::new(file)?;
+        output::summary(format!("{}: {} intact, {} corrupted, {} missing, {} unexpected", issue,
+            verification.intact.len(), verification.corrupted.len(), verification.missing.len(), verification.unexpected.len()), verification.is_ok());
 
-                for i in 0..zip.len() {
-                    let mut f = zip.by_index(i)?;
-                    let name = f.name().to_string();
+        if !verification.is_ok() {
+            if on_error == Some(OnError::FailFast) {
+                anyhow::bail!("{} failed verification", issue);
+            }
+            failed += 1;
+        }
+    }
 
-                    if zipfile_regex.is_match(&name) {
-                        let out_path = dest.join(PathBuf::from(&name).file_name().unwrap());
-                        let mut out = fs::File::create(&out_path)?;
-                        io::copy(&mut f, &mut out)?;
-                    }
-                }
+    if failed > 0 {
+        anyhow::bail!("{} of {} issues failed verification", failed, issues.len());
+    }
+
+    Ok(())
+}
+
+fn print_dedupe(report: &DedupeReport, dry_run: bool) {
+    for (removed, original) in &report.removed {
+        if dry_run {
+            output::info(format!("Would remove {}, identical to {}", removed, original));
+        } else {
+            output::success(format!("Removed {}, identical to {}", removed, original));
+        }
+    }
+
+    for overlap in &report.overlaps {
+        output::info(format!(
+            "{}: lines {}-{} also in {} from line {}",
+            overlap.file, overlap.first_line, overlap.last_line, overlap.other, overlap.other_line
+        ));
+    }
+
+    if report.removed.is_empty() && report.overlaps.is_empty() {
+        output::info("No duplicate content found");
+    }
+}
+
+/// One line per issue with the time of each step, then the totals over all issues.
+fn print_profile(profile: &[(&str, Vec<StageTiming>)]) {
+    let mut totals: Vec<StageTiming> = Vec::new();
+    for (issue, timings) in profile {
+        output::info(format!("{}: {}", issue, timing_line(timings)));
+
+        for timing in timings {
+            match totals.iter_mut().find(|total| total.stage == timing.stage) {
+                Some(total) => total.seconds += timing.seconds,
+                None => totals.push(timing.clone()),
             }
         }
     }
 
-    println!("Extraction complete to {}", dest.display());
+    let total: f64 = totals.iter().map(|t| t.seconds).sum();
+    output::summary(format!("Total: {} ({:.1}s)", timing_line(&totals), total), true);
+}
+
+fn timing_line(timings: &[StageTiming]) -> String {
+    timings.iter().map(|t| format!("{} {:.1}s", t.stage, t.seconds)).collect::<Vec<_>>().join(", ")
+}
+
+/// `None` after reporting a stage that timed out, so the run can continue with the next issue.
+/// With `--on-error fail-fast`, timeouts end the run like other errors.
+fn skip_timed_out<T>(result: anyhow::Result<T>, on_error: Option<OnError>) -> anyhow::Result<Option<T>> {
+    match result {
+        Err(err) if err.is::<StageTimedOut>() && on_error != Some(OnError::FailFast) => {
+            output::failure(&err);
+            Ok(None)
+        }
+        result => result.map(Some),
+    }
+}
+
+fn redact_issues(config: &Config, issues: &[String], redactor: &Redactor) -> anyhow::Result<()> {
+    for issue in issues {
+        output::section(issue);
+
+        let extract_path = config.default_path.join(source::folder_name(issue)).join("logs-extracted");
+        let redacted = redact::redact_logs(&extract_path, redactor)?;
+        output::success(format!("Redacted {} text files in {}", redacted, extract_path.display()));
+    }
 
     Ok(())
 }
 
-fn convert_logs(dir: &PathBuf) -> anyhow::Result<()> {
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
+fn print_stats(stats: &LogStats) {
+    println!("{}: {} lines, {} to {}", stats.file.display(), stats.lines,
+        stats.first.as_deref().unwrap_or("-"), stats.last.as_deref().unwrap_or("-"));
 
-        if path.extension().map(|e| e == "dlt").unwrap_or(false) {
-            // TODO. Pull out logcat out of dlt file.
+    let counts = |map: &std::collections::BTreeMap<String, u64>| {
+        let mut counts: Vec<_> = map.iter().collect();
+        counts.sort_by_key(|&(_, &n)| std::cmp::Reverse(n));
+        counts.iter().take(10).map(|(k, n)| format!("{} {}", k, n)).collect::<Vec<_>>().join(", ")
+    };
+
+    println!("  Levels: {}", counts(&stats.levels));
+    println!("  Top tags: {}", counts(&stats.tags));
+    println!("  Per hour:");
+    for (hour, n) in &stats.hours {
+        println!("    {}h {:>8}", hour, n);
+    }
+
+    if !stats.gaps.is_empty() {
+        println!("  Gaps over {}s:", stats::GAP_SECONDS);
+        for gap in stats.gaps.iter().take(10) {
+            println!("    {} to {} ({}s)", gap.from, gap.to, gap.seconds);
         }
     }
 
+    if stats.unparsed > 0 {
+        println!("  {} lines without a logcat prefix", stats.unparsed);
+    }
+}
+
+/// Sets the config fields the flags of `cli` override, returning their names.
+fn apply_overrides(cli: &Cli, config: &mut Config) -> Vec<&'static str> {
+    let mut overridden = Vec::new();
+    if let Some(exclude) = &cli.exclude {
+        config.exclude_regex = Some(exclude.clone());
+        overridden.push("exclude_regex");
+    }
+    if let Some(template) = &cli.name_from_field {
+        config.download_name_template = Some(template.clone());
+        overridden.push("download_name_template");
+    }
+    if let Some(command) = &cli.post_hook {
+        config.post_issue_command = Some(command.clone());
+        overridden.push("post_issue_command");
+    }
+    if cli.prefer_ipv4 || cli.prefer_ipv6 {
+        config.ip_family = Some(if cli.prefer_ipv4 { IpFamily::Ipv4 } else { IpFamily::Ipv6 });
+        overridden.push("ip_family");
+    }
+    if let Some(template) = &cli.template {
+        config.output_name_template = Some(template.clone());
+        overridden.push("output_name_template");
+    }
+    if let Some(fibex) = &cli.fibex {
+        config.fibex_path = Some(fibex.clone());
+        overridden.push("fibex_path");
+    }
+
+    overridden
+}
+
+fn print_config(cli: &Cli) -> anyhow::Result<()> {
+    let (mut config, sources) = Config::load_with_sources()?;
+    let overridden = apply_overrides(cli, &mut config);
+    let mut resolved = serde_json::Map::new();
+
+    for (key, value) in config.redacted().as_object().cloned().unwrap_or_default() {
+        let source = match sources.get(&key) {
+            _ if overridden.contains(&key.as_str()) => "cli".to_string(),
+            Some(path) => path.display().to_string(),
+            None => "default".to_string(),
+        };
+        resolved.insert(key, serde_json::json!({ "value": value, "source": source }));
+    }
+
+    println!("{}", serde_json::to_string_pretty(&resolved)?);
+
     Ok(())
 }
@@ -0,0 +1,133 @@
+use std::{collections::BTreeMap, fs, io, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::layout::{Layout, WORKLOGS_DIR};
+
+const MANIFEST_FILE: &str = ".getlogs-manifest.json";
+
+/// Suffix of downloads that are not complete yet.
+pub const PART_SUFFIX: &str = ".part";
+
+/// Suffix of the first bytes of attachments downloaded by `--sample`.
+pub const SAMPLE_SUFFIX: &str = ".sample";
+
+/// Hashes of the downloads in the format of `sha256sum`, written by `--checksum-manifest`.
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Record of the attachments downloaded into an issue folder, keyed by attachment ID.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Manifest {
+    pub attachments: BTreeMap<String, Entry>,
+    /// Converted logs already appended to the combined log, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub combined: Vec<Combined>,
+    /// Jira fields of the issue as of the last fetch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<IssueDetails>,
+}
+
+/// Jira fields describing an issue, for the header of the combined log.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IssueDetails {
+    pub key: String,
+    pub summary: Option<String>,
+    pub status: Option<String>,
+    pub assignee: Option<String>,
+    /// Time of the fetch, in RFC 3339.
+    pub fetched: Option<String>,
+    /// Values of the other fields named in `download_name_template`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+}
+
+/// A log appended to the combined log of the issue.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Combined {
+    /// File name of the log before conversion.
+    pub source: String,
+    /// Hex SHA-256 of the log before conversion.
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    /// Name of the file in the issue folder.
+    pub filename: String,
+    /// Name of the attachment, if it had to be changed to be a valid and unique file name.
+    #[serde(default)]
+    pub original_name: Option<String>,
+    pub size: Option<u64>,
+    pub status: Status,
+    pub error: Option<String>,
+    /// Hex SHA-256 of the downloaded file.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Whether the file is Jira's thumbnail of the image instead of the original.
+    #[serde(default)]
+    pub thumbnail: bool,
+    /// Whether the attachment was only referenced in the worklog or changelog of the issue.
+    #[serde(default)]
+    pub worklog: bool,
+    /// `ETag` and `Last-Modified` the server sent with the file, for a conditional request when
+    /// it is fetched again.
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+}
+
+impl Entry {
+    /// Where the file is downloaded to in the issue folder `dir`.
+    pub fn path(&self, dir: &Path, layout: Layout) -> PathBuf {
+        let folder = if self.worklog { dir.join(WORKLOGS_DIR) } else { layout.download_dir(dir, &self.filename) };
+        folder.join(&self.filename)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Downloaded,
+    Failed,
+}
+
+impl Manifest {
+    /// Loads the manifest of `dir`, or an empty one if nothing was downloaded there yet.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::write(dir.join(MANIFEST_FILE), serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    pub fn is_failed(&self, id: &str) -> bool {
+        self.attachments.get(id).map(|e| e.status == Status::Failed).unwrap_or(false)
+    }
+}
+
+/// Whether `name` is one of the bookkeeping files, unfinished downloads or samples getlogs keeps
+/// in an issue folder.
+pub fn is_internal_file(name: &str) -> bool {
+    name.starts_with(".getlogs-") || name.ends_with(PART_SUFFIX) || name.ends_with(SAMPLE_SUFFIX) || name == CHECKSUMS_FILE
+}
+
+/// Hex SHA-256 of the file at `path`.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    hash_reader(fs::File::open(path)?)
+}
+
+/// Hex SHA-256 of everything `reader` returns.
+pub fn hash_reader(mut reader: impl io::Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
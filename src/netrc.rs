@@ -0,0 +1,159 @@
+//! Credentials from the `.netrc` file other command line tools like curl and git read, so Jira
+//! credentials need not be copied into the config.
+
+use std::{fs, path::PathBuf};
+
+/// Login and password for `host` from `$NETRC` or `~/.netrc` (`~/_netrc` on Windows), falling
+/// back to the `default` entry. `None` if there is no file or no matching entry.
+pub fn credentials(host: &str) -> Option<(String, String)> {
+    let text = fs::read_to_string(path()?).ok()?;
+    find(&text, host)
+}
+
+fn path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+
+    let name = if cfg!(windows) { "_netrc" } else { ".netrc" };
+    dirs::home_dir().map(|home| home.join(name))
+}
+
+/// The entry for `host` in the netrc `text`, or the `default` entry.
+fn find(text: &str, host: &str) -> Option<(String, String)> {
+    let entries = parse(text);
+    let credentials = |entry: &Entry| Some((entry.login.clone()?, entry.password.clone()?));
+
+    entries.iter().filter(|e| e.machine.as_deref() == Some(host)).find_map(credentials)
+        .or_else(|| entries.iter().filter(|e| e.machine.is_none()).find_map(credentials))
+}
+
+/// A `machine` entry, or the `default` entry without a machine.
+#[derive(Default)]
+struct Entry {
+    machine: Option<String>,
+    login: Option<String>,
+    password: Option<String>,
+}
+
+fn parse(text: &str) -> Vec<Entry> {
+    let mut tokens = Tokens::new(text);
+    let mut entries: Vec<Entry> = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push(Entry { machine: tokens.next().map(str::to_string), ..Entry::default() }),
+            "default" => entries.push(Entry::default()),
+            "login" | "password" => {
+                let value = tokens.next().map(str::to_string);
+                if let Some(entry) = entries.last_mut() {
+                    if token == "login" {
+                        entry.login = value;
+                    } else {
+                        entry.password = value;
+                    }
+                }
+            }
+            "account" => {
+                tokens.next();
+            }
+            "macdef" => tokens.skip_macro(),
+            _ => {}
+        }
+    }
+
+    entries
+}
+
+/// Whitespace separated tokens; `#` starts a comment that runs to the end of the line.
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Self {
+        Tokens { rest: text }
+    }
+
+    /// Skips a macro definition, which ends at the next empty line, also with Windows line ends.
+    fn skip_macro(&mut self) {
+        let mut lines = self.rest.split_inclusive('\n');
+        // The rest of the line naming the macro
+        let mut end = lines.next().map_or(0, str::len);
+        for line in lines {
+            end += line.len();
+            if line.trim_end_matches(['\r', '\n']).is_empty() {
+                self.rest = &self.rest[end..];
+                return;
+            }
+        }
+
+        self.rest = "";
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        loop {
+            self.rest = self.rest.trim_start();
+            if self.rest.starts_with('#') {
+                self.rest = self.rest.find('\n').map(|end| &self.rest[end..]).unwrap_or("");
+                continue;
+            }
+
+            if self.rest.is_empty() {
+                return None;
+            }
+
+            let end = self.rest.find(char::is_whitespace).unwrap_or(self.rest.len());
+            let (token, rest) = self.rest.split_at(end);
+            self.rest = rest;
+
+            return Some(token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(login: &str, password: &str) -> Option<(String, String)> {
+        Some((login.to_string(), password.to_string()))
+    }
+
+    #[test]
+    fn finds_the_machine_entry() {
+        let text = "machine other.example.com login bob password b0b\nmachine jira.example.com\n  login alice\n  password s3cret\n";
+
+        assert_eq!(find(text, "jira.example.com"), pair("alice", "s3cret"));
+        assert_eq!(find(text, "other.example.com"), pair("bob", "b0b"));
+        assert_eq!(find(text, "unknown.example.com"), None);
+    }
+
+    #[test]
+    fn falls_back_to_the_default_entry() {
+        let text = "default login anyone password guest\nmachine jira.example.com login alice password s3cret";
+
+        assert_eq!(find(text, "jira.example.com"), pair("alice", "s3cret"));
+        assert_eq!(find(text, "wiki.example.com"), pair("anyone", "guest"));
+        // An entry without password does not hide the default
+        assert_eq!(find("machine jira.example.com login alice\ndefault login anyone password guest", "jira.example.com"), pair("anyone", "guest"));
+    }
+
+    #[test]
+    fn skips_comments_accounts_and_macros() {
+        let text = "# machine jira.example.com login mallory password x\n\
+            machine jira.example.com account team # login eve\n\
+            macdef init\nlogin mallory\npassword x\n\n\
+            login alice password s3cret\n";
+        assert_eq!(find(text, "jira.example.com"), pair("alice", "s3cret"));
+
+        let text = "machine jira.example.com\r\nmacdef init\r\npassword x\r\n\r\nlogin alice\r\npassword s3cret\r\n";
+        assert_eq!(find(text, "jira.example.com"), pair("alice", "s3cret"));
+
+        assert_eq!(find("machine jira.example.com login alice macdef init\npassword x", "jira.example.com"), None);
+    }
+}
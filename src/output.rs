@@ -0,0 +1,170 @@
+//! Styled terminal output. Colors are only used when writing to a terminal and honor `NO_COLOR`.
+
+use std::{fmt::Display, sync::{OnceLock, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
+use console::{Emoji, Term, style};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+static CHECK: Emoji = Emoji("✔ ", "+ ");
+static CROSS: Emoji = Emoji("✘ ", "x ");
+
+static STDERR_ONLY: AtomicBool = AtomicBool::new(false);
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// The progress bars of the downloads running at the same time, stacked below the messages.
+static BARS: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Sends the messages normally printed to stdout to stderr as well, keeping stdout for data like
+/// the output of `extract --stdout`.
+pub fn use_stderr_only() {
+    STDERR_ONLY.store(true, Ordering::Relaxed);
+}
+
+/// Prints the [`detail`] messages, which are otherwise only counted in a summary.
+pub fn use_verbose() {
+    VERBOSE.store(true, Ordering::Relaxed);
+}
+
+/// Whether [`use_stderr_only`] was called.
+pub fn is_stderr_only() -> bool {
+    STDERR_ONLY.load(Ordering::Relaxed)
+}
+
+/// Runs `write` with the progress bars cleared, so the line it writes is not drawn over.
+fn above_bars(write: impl FnOnce()) {
+    match BARS.get() {
+        Some(bars) => bars.suspend(write),
+        None => write(),
+    }
+}
+
+fn print(message: impl Display) {
+    above_bars(|| {
+        if is_stderr_only() {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    });
+}
+
+/// Heading starting the output of an issue.
+pub fn section(title: &str) {
+    print(style(format!("=== {} ===", title)).cyan().bold());
+}
+
+/// A plain note, like the number of skipped files.
+pub fn info(message: impl Display) {
+    print(message);
+}
+
+/// A line per file behind a summary, like each file skipped, only printed with `-v`.
+pub fn detail(message: impl Display) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        print(style(message).dim());
+    }
+}
+
+pub fn success(message: impl Display) {
+    print(format_args!("{}{}", style(CHECK).green(), message));
+}
+
+pub fn failure(message: impl Display) {
+    above_bars(|| eprintln!("{}{}", style(CROSS).red().for_stderr(), message));
+}
+
+pub fn warning(message: impl Display) {
+    above_bars(|| eprintln!("{}", style(message).yellow().for_stderr()));
+}
+
+/// Closing line of an issue section.
+pub fn summary(message: impl Display, ok: bool) {
+    let message = style(message).bold();
+    if ok {
+        print(message.green());
+    } else {
+        print(message.red());
+    }
+}
+
+/// Time between progress bar redraws, 10 per second.
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Seconds between the plain progress lines written instead of a bar.
+const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Download progress: a bar on a terminal, otherwise a plain line every few seconds, so logs of
+/// redirected runs stay readable.
+pub struct Progress {
+    bar: ProgressBar,
+    plain: Option<PlainProgress>,
+}
+
+struct PlainProgress {
+    label: String,
+    last: Instant,
+}
+
+impl Progress {
+    /// A bar redrawn at most once per `interval`, or plain lines for `label`.
+    pub fn new(label: impl Into<String>, interval: Duration) -> anyhow::Result<Self> {
+        if Term::stderr().is_term() {
+            let rate = (1000 / interval.as_millis().max(1)).clamp(1, u8::MAX as u128) as u8;
+            // Bars are drawn together, at the rate of the first
+            let bars = BARS.get_or_init(|| MultiProgress::with_draw_target(ProgressDrawTarget::stderr_with_hz(rate)));
+            let bar = bars.add(ProgressBar::new(0));
+            let style = ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}) {msg}")?
+                .progress_chars("=>-");
+            bar.set_style(style);
+            bar.set_message(label.into());
+
+            Ok(Progress { bar, plain: None })
+        } else {
+            let plain = PlainProgress { label: label.into(), last: Instant::now() };
+
+            Ok(Progress { bar: ProgressBar::hidden(), plain: Some(plain) })
+        }
+    }
+
+    pub fn set_length(&self, length: u64) {
+        self.bar.set_length(length);
+    }
+
+    pub fn set_position(&self, position: u64) {
+        self.bar.set_position(position);
+    }
+
+    pub fn inc(&mut self, delta: u64) {
+        self.bar.inc(delta);
+
+        if let Some(plain) = &mut self.plain && plain.last.elapsed() >= PLAIN_PROGRESS_INTERVAL {
+            plain.last = Instant::now();
+            let position = self.bar.position();
+            match self.bar.length().filter(|&length| length > 0) {
+                Some(length) => eprintln!(
+                    "{}: {} of {} ({}%)",
+                    plain.label, HumanBytes(position), HumanBytes(length), position * 100 / length
+                ),
+                None => eprintln!("{}: {}", plain.label, HumanBytes(position)),
+            }
+        }
+    }
+
+    /// Prints `message` above the bar.
+    pub fn println(&self, message: impl AsRef<str>) {
+        if self.plain.is_some() {
+            eprintln!("{}", message.as_ref());
+        } else {
+            self.bar.println(message);
+        }
+    }
+
+    /// Leaves the bar where it stopped.
+    pub fn abandon(&self) {
+        self.bar.abandon();
+    }
+
+    pub fn finish_and_clear(&self) {
+        self.bar.finish_and_clear();
+    }
+}
@@ -0,0 +1,114 @@
+//! Classic libpcap capture files, as read by Wireshark and tcpdump. A file holds frames of a
+//! single link type, so each bus of a trace gets its own file.
+
+use std::io::{self, Write};
+
+/// Ethernet frames starting with the MAC header.
+pub const LINKTYPE_ETHERNET: u32 = 1;
+/// CAN and CAN FD frames in the layout of Linux SocketCAN.
+pub const LINKTYPE_CAN_SOCKETCAN: u32 = 227;
+
+const MAGIC: u32 = 0xa1b2c3d4;
+const SNAPLEN: u32 = 262_144;
+
+/// Bit of the SocketCAN ID marking a 29 bit identifier.
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+/// SocketCAN flag marking a CAN FD frame.
+const CANFD_FDF: u8 = 0x04;
+
+/// Streams packets to `out`, after a global header for `link_type`.
+pub struct PcapWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    pub fn new(mut out: W, link_type: u32) -> io::Result<Self> {
+        out.write_all(&MAGIC.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&4u16.to_le_bytes())?;
+        // Time zone offset and timestamp accuracy, both unused
+        out.write_all(&0i32.to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&SNAPLEN.to_le_bytes())?;
+        out.write_all(&link_type.to_le_bytes())?;
+
+        Ok(PcapWriter { out })
+    }
+
+    /// Writes a packet captured at `seconds` and `microseconds` since the epoch.
+    pub fn packet(&mut self, seconds: u32, microseconds: u32, data: &[u8]) -> io::Result<()> {
+        let captured = &data[..data.len().min(SNAPLEN as usize)];
+
+        self.out.write_all(&seconds.to_le_bytes())?;
+        self.out.write_all(&microseconds.to_le_bytes())?;
+        self.out.write_all(&(captured.len() as u32).to_le_bytes())?;
+        self.out.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.out.write_all(captured)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// A frame for [`LINKTYPE_CAN_SOCKETCAN`]: ID and flags in network byte order, data length,
+/// FD flags and two reserved bytes, then the data padded to 8 bytes, or 64 for CAN FD frames.
+pub fn socketcan_frame(id: u32, data: &[u8]) -> Vec<u8> {
+    let data = &data[..data.len().min(64)];
+    let fd = data.len() > 8;
+    let id = if id > 0x7FF { id | CAN_EFF_FLAG } else { id };
+
+    let mut frame = Vec::with_capacity(if fd { 72 } else { 16 });
+    frame.extend_from_slice(&id.to_be_bytes());
+    frame.push(data.len() as u8);
+    frame.push(if fd { CANFD_FDF } else { 0 });
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(data);
+    frame.resize(if fd { 72 } else { 16 }, 0);
+
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_the_global_and_packet_headers() {
+        let mut writer = PcapWriter::new(Vec::new(), LINKTYPE_CAN_SOCKETCAN).unwrap();
+        writer.packet(1_700_000_000, 250_000, b"abc").unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        assert_eq!(bytes.len(), 24 + 16 + 3);
+        assert_eq!(&bytes[..4], [0xd4, 0xc3, 0xb2, 0xa1]);
+        assert_eq!(&bytes[4..8], [2, 0, 4, 0]);
+        assert_eq!((word(8), word(12), word(16), word(20)), (0, 0, SNAPLEN, LINKTYPE_CAN_SOCKETCAN));
+        assert_eq!((word(24), word(28), word(32), word(36)), (1_700_000_000, 250_000, 3, 3));
+        assert_eq!(&bytes[40..], b"abc");
+    }
+
+    #[test]
+    fn pads_classic_can_frames_to_16_bytes() {
+        let frame = socketcan_frame(0x123, &[1, 2, 3]);
+
+        assert_eq!(frame, [0, 0, 0x01, 0x23, 3, 0, 0, 0, 1, 2, 3, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn flags_extended_ids_and_fd_frames() {
+        let frame = socketcan_frame(0x800, &[0xaa; 8]);
+        assert_eq!(&frame[..4], [0x80, 0, 0x08, 0]);
+        assert_eq!((frame.len(), frame[5]), (16, 0));
+
+        let frame = socketcan_frame(0x7FF, &[0xbb; 12]);
+        assert_eq!(&frame[..8], [0, 0, 0x07, 0xff, 12, CANFD_FDF, 0, 0]);
+        assert_eq!(frame.len(), 72);
+        assert_eq!(&frame[8..20], [0xbb; 12]);
+        assert!(frame[20..].iter().all(|&b| b == 0));
+
+        let frame = socketcan_frame(1, &[0xcc; 80]);
+        assert_eq!((frame.len(), frame[4]), (72, 64));
+    }
+}
@@ -0,0 +1,1221 @@
+//! Text of PDF log reports. Only the text layer is read: the characters the content streams
+//! of the pages show, mapped to Unicode through the fonts' `ToUnicode` tables or encodings and
+//! broken into lines by their position. Scanned documents have no text layer to read.
+
+use std::{collections::{HashMap, HashSet}, io::Read, rc::Rc};
+use anyhow::bail;
+use flate2::read::ZlibDecoder;
+use regex::bytes::Regex;
+
+/// Streams larger than this once decoded are cut off, against compression bombs.
+const MAX_STREAM_SIZE: u64 = 256 << 20;
+
+/// Form XObjects nested deeper than this are not drawn, against loops in broken files.
+const MAX_FORM_DEPTH: usize = 8;
+
+/// Width of glyphs of fonts without widths, in thousandths of the font size.
+const DEFAULT_WIDTH: f64 = 500.0;
+
+type Dict = HashMap<Vec<u8>, Object>;
+
+/// Transformation matrix `[a b c d e f]`.
+type Matrix = [f64; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+#[derive(Debug, Clone)]
+enum Object {
+    Null,
+    /// `true` or `false`, whose value no text depends on.
+    Bool,
+    Number(f64),
+    String(Vec<u8>),
+    Name(Vec<u8>),
+    Array(Vec<Object>),
+    Dict(Dict),
+    /// Dictionary and the still encoded data.
+    Stream(Dict, Vec<u8>),
+    /// Reference to an indirect object by number.
+    Ref(u32),
+}
+
+impl Object {
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Object::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    fn as_name(&self) -> Option<&[u8]> {
+        match self {
+            Object::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn as_dict(&self) -> Option<&Dict> {
+        match self {
+            Object::Dict(dict) | Object::Stream(dict, _) => Some(dict),
+            _ => None,
+        }
+    }
+}
+
+/// The text of the PDF `data`, one line per line of text on the pages. Empty if the document
+/// has no text layer.
+pub fn text(data: &[u8]) -> anyhow::Result<String> {
+    if !data.starts_with(b"%PDF-") && !data[..data.len().min(1024)].windows(5).any(|w| w == b"%PDF-") {
+        bail!("not a PDF document");
+    }
+
+    let document = Document::parse(data);
+    if document.encrypted {
+        bail!("encrypted PDFs are not supported");
+    }
+
+    let pages = document.pages();
+    if pages.is_empty() {
+        bail!("no pages found, the PDF may be damaged");
+    }
+
+    let mut text = TextWriter::default();
+    let mut fonts = HashMap::new();
+    for (page, resources) in pages {
+        let content = match page.get(b"Contents".as_slice()).map(|contents| document.resolve(contents)) {
+            Some(Object::Array(parts)) => parts.iter().filter_map(|part| document.stream_data(part)).collect::<Vec<_>>().join(&b'\n'),
+            Some(contents) => document.stream_data(contents).unwrap_or_default(),
+            None => continue,
+        };
+
+        let mut page = Page { document: &document, fonts: &mut fonts, text: &mut text, ctm: IDENTITY, text_state: TextState::default() };
+        page.run(&content, &resources, 0);
+        text.end_line();
+    }
+
+    Ok(text.finish())
+}
+
+/// The name the text of the PDF `name` is extracted to, `report.pdf` becomes `report.txt`.
+pub fn text_name(name: &str) -> String {
+    std::path::Path::new(name).with_extension("txt").to_string_lossy().into_owned()
+}
+
+/// Whether `name` is a PDF by its extension.
+pub fn is_pdf(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".pdf")
+}
+
+/// The objects of a PDF file. They are found by scanning for `N G obj` rather than through the
+/// cross-reference table, which also reads files whose offsets are broken.
+struct Document {
+    objects: HashMap<u32, Object>,
+    root: Option<u32>,
+    encrypted: bool,
+}
+
+impl Document {
+    fn parse(data: &[u8]) -> Self {
+        let header = Regex::new(r"(?-u)(?:^|[^0-9])([0-9]+)[ \t\r\n\x0c\x00]+[0-9]+[ \t\r\n\x0c\x00]+obj").unwrap();
+        let mut document = Document { objects: HashMap::new(), root: None, encrypted: false };
+
+        // Later definitions win, as incremental updates append to the file
+        let mut streams = Vec::new();
+        for captures in header.captures_iter(data) {
+            let Some(number) = std::str::from_utf8(&captures[1]).ok().and_then(|number| number.parse().ok()) else {
+                continue;
+            };
+            let mut parser = Parser::new(data, captures.get(0).unwrap().end());
+            let Some(object) = parser.indirect_object() else {
+                continue;
+            };
+            if let Some(dict) = object.as_dict() {
+                match dict.get(b"Type".as_slice()).and_then(Object::as_name) {
+                    Some(b"ObjStm") => streams.push(object.clone()),
+                    Some(b"XRef") => document.trailer(dict),
+                    _ => {}
+                }
+            }
+            document.objects.insert(number, object);
+        }
+
+        for captures in Regex::new(r"(?-u)trailer[ \t\r\n\x0c\x00]*<<").unwrap().find_iter(data) {
+            if let Some(Object::Dict(dict)) = Parser::new(data, captures.end() - 2).object() {
+                document.trailer(&dict);
+            }
+        }
+
+        for stream in streams {
+            document.unpack(&stream);
+        }
+
+        document
+    }
+
+    /// Takes the catalog and encryption from a trailer or cross-reference stream.
+    fn trailer(&mut self, dict: &Dict) {
+        if let Some(Object::Ref(root)) = dict.get(b"Root".as_slice()) {
+            self.root = Some(*root);
+        }
+        if dict.contains_key(b"Encrypt".as_slice()) {
+            self.encrypted = true;
+        }
+    }
+
+    /// Adds the objects compressed into the object stream `stream`, unless they are defined
+    /// directly in the file as well.
+    fn unpack(&mut self, stream: &Object) {
+        let (Some(dict), Some(data)) = (stream.as_dict(), self.stream_data(stream)) else {
+            return;
+        };
+        let count = dict.get(b"N".as_slice()).and_then(Object::as_number).unwrap_or(0.0) as usize;
+        let first = dict.get(b"First".as_slice()).and_then(Object::as_number).unwrap_or(0.0) as usize;
+
+        let mut header = Parser::new(&data, 0);
+        let mut offsets = Vec::with_capacity(count.min(data.len()));
+        for _ in 0..count {
+            match (header.object(), header.object()) {
+                (Some(Object::Number(number)), Some(Object::Number(offset))) => offsets.push((number as u32, offset as usize)),
+                _ => break,
+            }
+        }
+
+        for (number, offset) in offsets {
+            if let Some(object) = Parser::new(&data, first + offset).object() {
+                self.objects.entry(number).or_insert(object);
+            }
+        }
+    }
+
+    /// `object`, or the object it refers to.
+    fn resolve<'o>(&'o self, object: &'o Object) -> &'o Object {
+        let mut object = object;
+        // References to references are allowed, but not endless ones
+        for _ in 0..8 {
+            match object {
+                Object::Ref(number) => object = self.objects.get(number).unwrap_or(&Object::Null),
+                _ => break,
+            }
+        }
+        object
+    }
+
+    fn get<'o>(&'o self, dict: &'o Dict, key: &[u8]) -> Option<&'o Object> {
+        dict.get(key).map(|object| self.resolve(object))
+    }
+
+    fn get_dict<'o>(&'o self, dict: &'o Dict, key: &[u8]) -> Option<&'o Dict> {
+        self.get(dict, key).and_then(Object::as_dict)
+    }
+
+    /// The decoded data of the stream `object`, `None` if it is none or uses a filter that is
+    /// not supported.
+    fn stream_data(&self, object: &Object) -> Option<Vec<u8>> {
+        let Object::Stream(dict, data) = self.resolve(object) else {
+            return None;
+        };
+
+        let filters = match self.get(dict, b"Filter") {
+            Some(Object::Name(name)) => vec![name.as_slice()],
+            Some(Object::Array(names)) => names.iter().filter_map(|name| self.resolve(name).as_name()).collect(),
+            _ => Vec::new(),
+        };
+        let params = match self.get(dict, b"DecodeParms") {
+            Some(Object::Array(params)) => params.iter().map(|param| self.resolve(param).as_dict()).collect(),
+            Some(Object::Dict(params)) => vec![Some(params)],
+            _ => Vec::new(),
+        };
+
+        let mut data = data.clone();
+        for (i, filter) in filters.into_iter().enumerate() {
+            data = match filter {
+                b"FlateDecode" | b"Fl" => predict(inflate(&data), params.get(i).copied().flatten())?,
+                b"ASCIIHexDecode" | b"AHx" => hex_decode(&data),
+                b"ASCII85Decode" | b"A85" => ascii85_decode(&data),
+                _ => return None,
+            };
+        }
+
+        Some(data)
+    }
+
+    /// The pages in order, each with the resources it inherits if it has none of its own.
+    fn pages(&self) -> Vec<(Dict, Dict)> {
+        let mut pages = Vec::new();
+        let tree = self.root
+            .and_then(|root| self.objects.get(&root))
+            .and_then(Object::as_dict)
+            .and_then(|catalog| catalog.get(b"Pages".as_slice()));
+        if let Some(tree) = tree {
+            self.collect_pages(tree, &Dict::new(), &mut HashSet::new(), &mut pages);
+        }
+
+        // Without a usable catalog, the pages in the order they were written
+        if pages.is_empty() {
+            let mut numbers: Vec<u32> = self.objects.iter()
+                .filter(|(_, object)| object.as_dict().is_some_and(|dict| dict.get(b"Type".as_slice()).and_then(Object::as_name) == Some(b"Page")))
+                .map(|(number, _)| *number)
+                .collect();
+            numbers.sort();
+            for number in numbers {
+                let page = self.objects[&number].as_dict().unwrap();
+                let resources = self.get_dict(page, b"Resources").cloned().unwrap_or_default();
+                pages.push((page.clone(), resources));
+            }
+        }
+
+        pages
+    }
+
+    fn collect_pages(&self, node: &Object, inherited: &Dict, visited: &mut HashSet<u32>, pages: &mut Vec<(Dict, Dict)>) {
+        if let Object::Ref(number) = node && !visited.insert(*number) {
+            return;
+        }
+        let Some(dict) = self.resolve(node).as_dict() else {
+            return;
+        };
+
+        let resources = self.get_dict(dict, b"Resources").unwrap_or(inherited);
+        match self.get(dict, b"Kids") {
+            Some(Object::Array(kids)) => {
+                for kid in kids {
+                    self.collect_pages(kid, resources, visited, pages);
+                }
+            }
+            _ => pages.push((dict.clone(), resources.clone())),
+        }
+    }
+}
+
+/// Tokens of PDF files and content streams.
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+enum Token {
+    Object(Object),
+    /// An operator of a content stream, or any other bare word.
+    Keyword(Vec<u8>),
+    ArrayEnd,
+    DictEnd,
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0')
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    matches!(byte, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+impl<'a> Parser<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Parser { data, pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            if is_whitespace(byte) {
+                self.pos += 1;
+            } else if byte == b'%' {
+                while self.peek().is_some_and(|byte| byte != b'\r' && byte != b'\n') {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// The object after `N G obj`, with the data if it is a stream.
+    fn indirect_object(&mut self) -> Option<Object> {
+        let object = self.object()?;
+        let Object::Dict(dict) = object else {
+            return Some(object);
+        };
+
+        self.skip_whitespace();
+        if !self.data[self.pos..].starts_with(b"stream") {
+            return Some(Object::Dict(dict));
+        }
+        self.pos += b"stream".len();
+        if self.peek() == Some(b'\r') {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'\n') {
+            self.pos += 1;
+        }
+
+        let start = self.pos;
+        let rest = &self.data[start..];
+        // Trust a direct length only if `endstream` follows it, otherwise look for the keyword
+        let length = dict.get(b"Length".as_slice()).and_then(Object::as_number).map(|length| length as usize)
+            .filter(|length| rest.get(*length..).is_some_and(|after| {
+                let after = &after[after.iter().take_while(|byte| is_whitespace(**byte)).count()..];
+                after.starts_with(b"endstream")
+            }));
+        let length = length.or_else(|| {
+            let end = rest.windows(9).position(|w| w == b"endstream")?;
+            let data = &rest[..end];
+            let data = data.strip_suffix(b"\n").unwrap_or(data);
+            Some(data.strip_suffix(b"\r").unwrap_or(data).len())
+        })?;
+
+        self.pos = start + length;
+        Some(Object::Stream(dict, rest[..length].to_vec()))
+    }
+
+    fn object(&mut self) -> Option<Object> {
+        match self.token()? {
+            Token::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    fn token(&mut self) -> Option<Token> {
+        self.skip_whitespace();
+        let byte = self.peek()?;
+        match byte {
+            b'/' => {
+                self.pos += 1;
+                Some(Token::Object(Object::Name(self.name())))
+            }
+            b'(' => {
+                self.pos += 1;
+                Some(Token::Object(Object::String(self.literal_string())))
+            }
+            b'<' if self.data.get(self.pos + 1) == Some(&b'<') => {
+                self.pos += 2;
+                let mut dict = Dict::new();
+                loop {
+                    match self.token()? {
+                        Token::DictEnd => break,
+                        Token::Object(Object::Name(key)) => {
+                            let value = match self.token()? {
+                                Token::Object(value) => value,
+                                Token::DictEnd => break,
+                                _ => Object::Null,
+                            };
+                            dict.insert(key, value);
+                        }
+                        _ => {}
+                    }
+                }
+                Some(Token::Object(Object::Dict(dict)))
+            }
+            b'<' => {
+                self.pos += 1;
+                let end = self.data[self.pos..].iter().position(|byte| *byte == b'>').map_or(self.data.len(), |end| self.pos + end);
+                let string = hex_decode(&self.data[self.pos..end]);
+                self.pos = (end + 1).min(self.data.len());
+                Some(Token::Object(Object::String(string)))
+            }
+            b'>' if self.data.get(self.pos + 1) == Some(&b'>') => {
+                self.pos += 2;
+                Some(Token::DictEnd)
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut array = Vec::new();
+                loop {
+                    match self.token() {
+                        None | Some(Token::ArrayEnd) => break,
+                        Some(Token::Object(object)) => array.push(object),
+                        _ => {}
+                    }
+                }
+                Some(Token::Object(Object::Array(array)))
+            }
+            b']' => {
+                self.pos += 1;
+                Some(Token::ArrayEnd)
+            }
+            b'0'..=b'9' | b'+' | b'-' | b'.' => {
+                let number = self.number();
+                // `N G R` is a reference
+                if number.fract() == 0.0 && number >= 0.0 {
+                    let saved = self.pos;
+                    self.skip_whitespace();
+                    if self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+                        self.number();
+                        self.skip_whitespace();
+                        if self.peek() == Some(b'R') && self.data.get(self.pos + 1).is_none_or(|byte| is_whitespace(*byte) || is_delimiter(*byte)) {
+                            self.pos += 1;
+                            return Some(Token::Object(Object::Ref(number as u32)));
+                        }
+                    }
+                    self.pos = saved;
+                }
+                Some(Token::Object(Object::Number(number)))
+            }
+            _ if is_delimiter(byte) => {
+                // A stray `)`, `>` or brace
+                self.pos += 1;
+                Some(Token::Keyword(vec![byte]))
+            }
+            _ => {
+                let start = self.pos;
+                while self.peek().is_some_and(|byte| !is_whitespace(byte) && !is_delimiter(byte)) {
+                    self.pos += 1;
+                }
+                let word = &self.data[start..self.pos];
+                Some(match word {
+                    b"true" | b"false" => Token::Object(Object::Bool),
+                    b"null" => Token::Object(Object::Null),
+                    _ => Token::Keyword(word.to_vec()),
+                })
+            }
+        }
+    }
+
+    fn name(&mut self) -> Vec<u8> {
+        let mut name = Vec::new();
+        while let Some(byte) = self.peek().filter(|byte| !is_whitespace(*byte) && !is_delimiter(*byte)) {
+            self.pos += 1;
+            if byte == b'#' && let Some(code) = self.data.get(self.pos..self.pos + 2)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                name.push(code);
+                self.pos += 2;
+            } else {
+                name.push(byte);
+            }
+        }
+        name
+    }
+
+    fn number(&mut self) -> f64 {
+        let start = self.pos;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit() || matches!(byte, b'+' | b'-' | b'.')) {
+            self.pos += 1;
+        }
+        // Some writers produce `--5` or `5.-3`, read as far as it makes sense
+        let text = String::from_utf8_lossy(&self.data[start..self.pos]);
+        let text = text.trim_start_matches(['+', '-']);
+        let negative = self.data[start..self.pos].first() == Some(&b'-');
+        let end = text.find(['+', '-']).unwrap_or(text.len());
+        let number = text[..end].parse::<f64>().unwrap_or(0.0);
+        if negative { -number } else { number }
+    }
+
+    fn literal_string(&mut self) -> Vec<u8> {
+        let mut string = Vec::new();
+        let mut depth = 1;
+        while let Some(byte) = self.peek() {
+            self.pos += 1;
+            match byte {
+                b'(' => {
+                    depth += 1;
+                    string.push(byte);
+                }
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                    string.push(byte);
+                }
+                b'\\' => {
+                    let Some(escaped) = self.peek() else {
+                        break;
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => string.push(b'\n'),
+                        b'r' => string.push(b'\r'),
+                        b't' => string.push(b'\t'),
+                        b'b' => string.push(b'\x08'),
+                        b'f' => string.push(b'\x0c'),
+                        b'0'..=b'7' => {
+                            let mut code = u32::from(escaped - b'0');
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(digit @ b'0'..=b'7') => {
+                                        code = code * 8 + u32::from(digit - b'0');
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            string.push(code as u8);
+                        }
+                        // A line break after the backslash continues the string
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        _ => string.push(escaped),
+                    }
+                }
+                _ => string.push(byte),
+            }
+        }
+        string
+    }
+
+    /// Skips the data of an inline image after its `ID` operator, up to and including `EI`.
+    fn skip_inline_image(&mut self) {
+        self.pos += 1;
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let end = rest.windows(4)
+            .position(|w| is_whitespace(w[0]) && &w[1..3] == b"EI" && (is_whitespace(w[3]) || is_delimiter(w[3])))
+            .map_or(rest.len(), |end| end + 3);
+        self.pos += end;
+    }
+}
+
+fn inflate(data: &[u8]) -> Vec<u8> {
+    let mut inflated = Vec::new();
+    // Keep what could be read of streams that are cut off or corrupt at the end
+    let _ = ZlibDecoder::new(data).take(MAX_STREAM_SIZE).read_to_end(&mut inflated);
+    inflated
+}
+
+/// Undoes the PNG predictors of `params`, `None` for the TIFF predictor, which is not supported.
+fn predict(data: Vec<u8>, params: Option<&Dict>) -> Option<Vec<u8>> {
+    let param = |key: &[u8], default: f64| params.and_then(|params| params.get(key)).and_then(Object::as_number).unwrap_or(default) as usize;
+    let predictor = param(b"Predictor", 1.0);
+    if predictor < 2 {
+        return Some(data);
+    }
+    if predictor == 2 {
+        return None;
+    }
+
+    let pixel = (param(b"Colors", 1.0) * param(b"BitsPerComponent", 8.0)).div_ceil(8).max(1);
+    let row = (param(b"Columns", 1.0) * param(b"Colors", 1.0) * param(b"BitsPerComponent", 8.0)).div_ceil(8);
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut previous = vec![0u8; row];
+    for line in data.chunks(row + 1) {
+        let (kind, line) = (line[0], &line[1..]);
+        let mut current = line.to_vec();
+        for i in 0..current.len() {
+            let left = if i >= pixel { current[i - pixel] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= pixel { previous[i - pixel] } else { 0 };
+            current[i] = current[i].wrapping_add(match kind {
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => 0,
+            });
+        }
+        decoded.extend_from_slice(&current);
+        current.resize(row, 0);
+        previous = current;
+    }
+
+    Some(decoded)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let (a, b, c) = ((estimate - i16::from(left)).abs(), (estimate - i16::from(up)).abs(), (estimate - i16::from(up_left)).abs());
+    if a <= b && a <= c { left } else if b <= c { up } else { up_left }
+}
+
+fn hex_decode(data: &[u8]) -> Vec<u8> {
+    let digits: Vec<u8> = data.iter().take_while(|byte| **byte != b'>')
+        .filter_map(|byte| (*byte as char).to_digit(16).map(|digit| digit as u8))
+        .collect();
+    // An odd last digit is followed by an implied 0
+    digits.chunks(2).map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0)).collect()
+}
+
+fn ascii85_decode(data: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    let mut group = Vec::with_capacity(5);
+    let data = data.strip_prefix(b"<~").unwrap_or(data);
+    for &byte in data {
+        match byte {
+            b'~' => break,
+            b'z' if group.is_empty() => decoded.extend_from_slice(&[0; 4]),
+            b'!'..=b'u' => {
+                group.push(byte - b'!');
+                if group.len() == 5 {
+                    let value = group.iter().fold(0u64, |value, digit| value * 85 + u64::from(*digit));
+                    decoded.extend_from_slice(&(value as u32).to_be_bytes());
+                    group.clear();
+                }
+            }
+            _ => {}
+        }
+    }
+    if group.len() > 1 {
+        let kept = group.len() - 1;
+        group.resize(5, 84);
+        let value = group.iter().fold(0u64, |value, digit| value * 85 + u64::from(*digit));
+        decoded.extend_from_slice(&(value as u32).to_be_bytes()[..kept]);
+    }
+    decoded
+}
+
+fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+fn matrix(operands: &[Object]) -> Option<Matrix> {
+    let numbers: Vec<f64> = operands.iter().filter_map(Object::as_number).collect();
+    numbers.try_into().ok()
+}
+
+/// How the codes of a font's strings map to text and widths.
+struct Font {
+    /// Codes of composite fonts are two bytes, unless the `ToUnicode` table says otherwise.
+    composite: bool,
+    /// Encoding of composite fonts whose codes are UTF-16 already.
+    utf16: bool,
+    to_unicode: Option<CMap>,
+    /// Glyph names of the `Differences` of simple fonts, by code.
+    differences: HashMap<u32, String>,
+    /// Glyph widths as ranges of codes, sorted.
+    widths: Vec<(u32, u32, f64)>,
+    default_width: f64,
+}
+
+/// A `ToUnicode` table.
+#[derive(Default)]
+struct CMap {
+    /// Byte lengths of the codes, with the range they cover.
+    code_ranges: Vec<(usize, u32, u32)>,
+    chars: HashMap<u32, String>,
+}
+
+impl Font {
+    fn load(document: &Document, dict: &Dict) -> Self {
+        let composite = document.get(dict, b"Subtype").and_then(Object::as_name) == Some(b"Type0");
+        let encoding = document.get(dict, b"Encoding");
+        let utf16 = composite && encoding.and_then(Object::as_name)
+            .is_some_and(|name| name.windows(4).any(|w| w == b"UCS2" || w == b"UTF1"));
+        let to_unicode = dict.get(b"ToUnicode".as_slice()).and_then(|cmap| document.stream_data(cmap)).map(|data| CMap::parse(&data));
+
+        let mut differences = HashMap::new();
+        if let Some(Object::Dict(encoding)) = encoding && let Some(Object::Array(entries)) = document.get(encoding, b"Differences") {
+            let mut code = 0;
+            for entry in entries {
+                match entry {
+                    Object::Number(number) => code = *number as u32,
+                    Object::Name(name) => {
+                        if let Some(text) = glyph_text(name) {
+                            differences.insert(code, text);
+                        }
+                        code += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut widths = Vec::new();
+        let default_width;
+        if composite {
+            let descendant = match document.get(dict, b"DescendantFonts") {
+                Some(Object::Array(fonts)) => fonts.first().and_then(|font| document.resolve(font).as_dict()),
+                _ => None,
+            };
+            default_width = descendant.and_then(|font| document.get(font, b"DW")).and_then(Object::as_number).unwrap_or(1000.0);
+            if let Some(Object::Array(entries)) = descendant.and_then(|font| document.get(font, b"W")) {
+                let mut entries = entries.iter().map(|entry| document.resolve(entry));
+                while let Some(first) = entries.next().and_then(Object::as_number) {
+                    match entries.next() {
+                        Some(Object::Array(list)) => {
+                            for (i, width) in list.iter().enumerate() {
+                                let code = first as u32 + i as u32;
+                                widths.push((code, code, document.resolve(width).as_number().unwrap_or(default_width)));
+                            }
+                        }
+                        Some(Object::Number(last)) => {
+                            let width = entries.next().and_then(Object::as_number).unwrap_or(default_width);
+                            widths.push((first as u32, *last as u32, width));
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        } else {
+            let monospaced = document.get(dict, b"BaseFont").and_then(Object::as_name).is_some_and(|name| name.windows(7).any(|w| w == b"Courier"));
+            default_width = document.get_dict(dict, b"FontDescriptor")
+                .and_then(|descriptor| document.get(descriptor, b"MissingWidth"))
+                .and_then(Object::as_number)
+                .filter(|width| *width > 0.0)
+                .unwrap_or(if monospaced { 600.0 } else { DEFAULT_WIDTH });
+            let first = document.get(dict, b"FirstChar").and_then(Object::as_number).unwrap_or(0.0) as u32;
+            if let Some(Object::Array(list)) = document.get(dict, b"Widths") {
+                for (i, width) in list.iter().enumerate() {
+                    let code = first + i as u32;
+                    widths.push((code, code, document.resolve(width).as_number().unwrap_or(default_width)));
+                }
+            }
+        }
+        widths.sort_by_key(|(first, _, _)| *first);
+
+        Font { composite, utf16, to_unicode, differences, widths, default_width }
+    }
+
+    /// The codes of `string`, with their byte length.
+    fn codes(&self, string: &[u8]) -> Vec<(u32, usize)> {
+        let mut codes = Vec::new();
+        let mut pos = 0;
+        while pos < string.len() {
+            let ranges = self.to_unicode.as_ref().map_or(&[][..], |cmap| &cmap.code_ranges);
+            let length = ranges.iter()
+                .find(|(length, first, last)| {
+                    string.get(pos..pos + length).is_some_and(|bytes| (*first..=*last).contains(&code_of(bytes)))
+                })
+                .map(|(length, _, _)| *length)
+                .unwrap_or(if self.composite { 2 } else { 1 })
+                .min(string.len() - pos);
+            codes.push((code_of(&string[pos..pos + length]), length));
+            pos += length;
+        }
+        codes
+    }
+
+    fn text(&self, code: u32) -> String {
+        if let Some(text) = self.to_unicode.as_ref().and_then(|cmap| cmap.chars.get(&code)) {
+            return text.clone();
+        }
+        if self.composite {
+            // Without a table the codes of composite fonts are glyph IDs, which say nothing
+            return match self.utf16 {
+                true => char::from_u32(code).map(String::from).unwrap_or_default(),
+                false => String::new(),
+            };
+        }
+        if let Some(text) = self.differences.get(&code) {
+            return text.clone();
+        }
+        win_ansi(code as u8).map(String::from).unwrap_or_default()
+    }
+
+    fn width(&self, code: u32) -> f64 {
+        let i = self.widths.partition_point(|(first, _, _)| *first <= code);
+        match i.checked_sub(1).map(|i| self.widths[i]) {
+            Some((_, last, width)) if code <= last => width,
+            _ => self.default_width,
+        }
+    }
+}
+
+fn code_of(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |code, byte| code << 8 | u32::from(*byte))
+}
+
+/// The text of UTF-16BE `bytes`.
+fn utf16_text(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+impl CMap {
+    fn parse(data: &[u8]) -> Self {
+        let mut cmap = CMap::default();
+        let mut parser = Parser::new(data, 0);
+        let mut operands = Vec::new();
+        let mut section: &[u8] = b"";
+        while let Some(token) = parser.token() {
+            match token {
+                Token::Object(object) if !section.is_empty() => operands.push(object),
+                Token::Keyword(keyword) => {
+                    match keyword.as_slice() {
+                        b"begincodespacerange" => section = b"codespace",
+                        b"beginbfchar" => section = b"bfchar",
+                        b"beginbfrange" => section = b"bfrange",
+                        b"endcodespacerange" => cmap.code_spaces(&operands),
+                        b"endbfchar" => cmap.bf_chars(&operands),
+                        b"endbfrange" => cmap.bf_ranges(&operands),
+                        _ => continue,
+                    }
+                    if keyword.starts_with(b"end") {
+                        section = b"";
+                    }
+                    operands.clear();
+                }
+                _ => {}
+            }
+        }
+        // Longest codes first would misread strings of shorter ones, so try the shortest first
+        cmap.code_ranges.sort_by_key(|(length, _, _)| *length);
+        cmap
+    }
+
+    fn code_spaces(&mut self, operands: &[Object]) {
+        for pair in operands.chunks(2) {
+            if let [Object::String(first), Object::String(last)] = pair && !first.is_empty() {
+                self.code_ranges.push((first.len(), code_of(first), code_of(last)));
+            }
+        }
+    }
+
+    fn bf_chars(&mut self, operands: &[Object]) {
+        for pair in operands.chunks(2) {
+            if let [Object::String(code), Object::String(text)] = pair {
+                self.chars.insert(code_of(code), utf16_text(text));
+            }
+        }
+    }
+
+    fn bf_ranges(&mut self, operands: &[Object]) {
+        for range in operands.chunks(3) {
+            let [Object::String(first), Object::String(last), target] = range else {
+                continue;
+            };
+            let (first, last) = (code_of(first), code_of(last));
+            // Against tables claiming the whole code space
+            let last = last.min(first.saturating_add(0xFFFF));
+            match target {
+                Object::String(start) if !start.is_empty() => {
+                    let mut text = start.clone();
+                    for code in first..=last {
+                        self.chars.insert(code, utf16_text(&text));
+                        let end = text.len() - 1;
+                        text[end] = text[end].wrapping_add(1);
+                    }
+                }
+                Object::Array(texts) => {
+                    for (code, text) in (first..=last).zip(texts) {
+                        if let Object::String(text) = text {
+                            self.chars.insert(code, utf16_text(text));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Characters of the WinAnsi encoding, also used for simple fonts without an encoding.
+fn win_ansi(code: u8) -> Option<char> {
+    const HIGH: [u16; 32] = [
+        0x20AC, 0, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0, 0x017D, 0,
+        0, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0, 0x017E, 0x0178,
+    ];
+    match code {
+        b'\t' | b' '..=b'~' | 0xA0..=0xFF => Some(char::from(code)),
+        0x80..=0x9F => char::from_u32(u32::from(HIGH[usize::from(code - 0x80)])).filter(|c| *c != '\0'),
+        _ => None,
+    }
+}
+
+/// The text of the glyph `name` of an encoding's `Differences`, for the names of the Latin
+/// characters and the `uniXXXX` names.
+fn glyph_text(name: &[u8]) -> Option<String> {
+    const NAMES: &[(&str, &str)] = &[
+        ("space", " "), ("exclam", "!"), ("quotedbl", "\""), ("numbersign", "#"), ("dollar", "$"), ("percent", "%"),
+        ("ampersand", "&"), ("quotesingle", "'"), ("quoteright", "\u{2019}"), ("parenleft", "("), ("parenright", ")"),
+        ("asterisk", "*"), ("plus", "+"), ("comma", ","), ("hyphen", "-"), ("minus", "\u{2212}"), ("period", "."),
+        ("slash", "/"), ("zero", "0"), ("one", "1"), ("two", "2"), ("three", "3"), ("four", "4"), ("five", "5"),
+        ("six", "6"), ("seven", "7"), ("eight", "8"), ("nine", "9"), ("colon", ":"), ("semicolon", ";"), ("less", "<"),
+        ("equal", "="), ("greater", ">"), ("question", "?"), ("at", "@"), ("bracketleft", "["), ("backslash", "\\"),
+        ("bracketright", "]"), ("asciicircum", "^"), ("underscore", "_"), ("grave", "`"), ("quoteleft", "\u{2018}"),
+        ("braceleft", "{"), ("bar", "|"), ("braceright", "}"), ("asciitilde", "~"), ("bullet", "\u{2022}"),
+        ("endash", "\u{2013}"), ("emdash", "\u{2014}"), ("quotedblleft", "\u{201C}"), ("quotedblright", "\u{201D}"),
+        ("ellipsis", "\u{2026}"), ("degree", "\u{B0}"), ("copyright", "\u{A9}"), ("registered", "\u{AE}"),
+        ("trademark", "\u{2122}"), ("germandbls", "\u{DF}"), ("adieresis", "\u{E4}"), ("odieresis", "\u{F6}"),
+        ("udieresis", "\u{FC}"), ("Adieresis", "\u{C4}"), ("Odieresis", "\u{D6}"), ("Udieresis", "\u{DC}"),
+        ("eacute", "\u{E9}"), ("egrave", "\u{E8}"), ("agrave", "\u{E0}"), ("ccedilla", "\u{E7}"), ("fi", "fi"),
+        ("fl", "fl"), ("ff", "ff"), ("ffi", "ffi"), ("ffl", "ffl"), ("nbspace", "\u{A0}"),
+    ];
+    let name = std::str::from_utf8(name).ok()?;
+    if name.len() == 1 && name.as_bytes()[0].is_ascii_alphabetic() {
+        return Some(name.to_string());
+    }
+    if let Some((_, text)) = NAMES.iter().find(|(glyph, _)| *glyph == name) {
+        return Some(text.to_string());
+    }
+
+    let hex = name.strip_prefix("uni").or_else(|| name.strip_prefix('u'))?;
+    let units: Option<Vec<u16>> = (0..hex.len() / 4).map(|i| u16::from_str_radix(&hex[i * 4..i * 4 + 4], 16).ok()).collect();
+    match units {
+        Some(units) if !units.is_empty() && hex.len() % 4 == 0 => Some(String::from_utf16_lossy(&units)),
+        _ => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32).map(String::from),
+    }
+}
+
+/// Text state of a content stream, reset by `BT`.
+#[derive(Clone)]
+struct TextState {
+    font: Option<Rc<Font>>,
+    size: f64,
+    char_spacing: f64,
+    word_spacing: f64,
+    scale: f64,
+    leading: f64,
+    rise: f64,
+    matrix: Matrix,
+    line_matrix: Matrix,
+}
+
+impl Default for TextState {
+    fn default() -> Self {
+        TextState {
+            font: None,
+            size: 1.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            scale: 1.0,
+            leading: 0.0,
+            rise: 0.0,
+            matrix: IDENTITY,
+            line_matrix: IDENTITY,
+        }
+    }
+}
+
+impl TextState {
+    fn move_line(&mut self, x: f64, y: f64) {
+        self.line_matrix = multiply(&[1.0, 0.0, 0.0, 1.0, x, y], &self.line_matrix);
+        self.matrix = self.line_matrix;
+    }
+}
+
+/// Draws the text of a page's content stream into the [`TextWriter`].
+struct Page<'d, 'w> {
+    document: &'d Document,
+    /// Fonts by object number, shared by the pages.
+    fonts: &'w mut HashMap<u32, Rc<Font>>,
+    text: &'w mut TextWriter,
+    ctm: Matrix,
+    text_state: TextState,
+}
+
+impl Page<'_, '_> {
+    fn run(&mut self, content: &[u8], resources: &Dict, depth: usize) {
+        let mut parser = Parser::new(content, 0);
+        let mut operands: Vec<Object> = Vec::new();
+        let mut saved = Vec::new();
+        while let Some(token) = parser.token() {
+            let operator = match token {
+                Token::Object(object) => {
+                    operands.push(object);
+                    continue;
+                }
+                Token::Keyword(operator) => operator,
+                _ => continue,
+            };
+
+            let number = |i: usize| operands.get(i).and_then(Object::as_number).unwrap_or(0.0);
+            let state = &mut self.text_state;
+            match operator.as_slice() {
+                b"q" => saved.push((self.ctm, state.clone())),
+                b"Q" => {
+                    if let Some((ctm, text_state)) = saved.pop() {
+                        self.ctm = ctm;
+                        *state = text_state;
+                    }
+                }
+                b"cm" => {
+                    if let Some(matrix) = matrix(&operands) {
+                        self.ctm = multiply(&matrix, &self.ctm);
+                    }
+                }
+                b"BT" => {
+                    state.matrix = IDENTITY;
+                    state.line_matrix = IDENTITY;
+                }
+                b"Tf" => {
+                    let font = operands.first().and_then(Object::as_name).and_then(|name| self.font(resources, name));
+                    self.text_state.size = number(1);
+                    self.text_state.font = font;
+                }
+                b"Tc" => state.char_spacing = number(0),
+                b"Tw" => state.word_spacing = number(0),
+                b"Tz" => state.scale = number(0) / 100.0,
+                b"TL" => state.leading = number(0),
+                b"Ts" => state.rise = number(0),
+                b"Td" => state.move_line(number(0), number(1)),
+                b"TD" => {
+                    state.leading = -number(1);
+                    state.move_line(number(0), number(1));
+                }
+                b"Tm" => {
+                    if let Some(matrix) = matrix(&operands) {
+                        state.matrix = matrix;
+                        state.line_matrix = matrix;
+                    }
+                }
+                b"T*" => {
+                    let leading = state.leading;
+                    state.move_line(0.0, -leading);
+                }
+                b"Tj" | b"'" | b"\"" => {
+                    if operator == b"\"" {
+                        state.word_spacing = number(0);
+                        state.char_spacing = number(1);
+                    }
+                    if operator != b"Tj" {
+                        let leading = state.leading;
+                        state.move_line(0.0, -leading);
+                    }
+                    if let Some(Object::String(string)) = operands.last() {
+                        self.show(string);
+                    }
+                }
+                b"TJ" => {
+                    if let Some(Object::Array(parts)) = operands.last() {
+                        for part in parts {
+                            match part {
+                                Object::String(string) => self.show(string),
+                                Object::Number(adjust) => {
+                                    let state = &mut self.text_state;
+                                    let advance = -adjust / 1000.0 * state.size * state.scale;
+                                    state.matrix = multiply(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &state.matrix);
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                b"Do" if depth < MAX_FORM_DEPTH => {
+                    if let Some(name) = operands.first().and_then(Object::as_name) {
+                        self.form(resources, name, depth);
+                    }
+                }
+                b"ID" => parser.skip_inline_image(),
+                _ => {}
+            }
+            operands.clear();
+        }
+    }
+
+    /// Draws the form XObject `name`, which may contain text of its own.
+    fn form(&mut self, resources: &Dict, name: &[u8], depth: usize) {
+        let document = self.document;
+        let Some(object) = document.get_dict(resources, b"XObject").and_then(|xobjects| xobjects.get(name)) else {
+            return;
+        };
+        let Some(dict) = document.resolve(object).as_dict() else {
+            return;
+        };
+        if document.get(dict, b"Subtype").and_then(Object::as_name) != Some(b"Form") {
+            return;
+        }
+        let Some(content) = document.stream_data(object) else {
+            return;
+        };
+
+        let (ctm, text_state) = (self.ctm, self.text_state.clone());
+        if let Some(Object::Array(matrix_operands)) = document.get(dict, b"Matrix") && let Some(matrix) = matrix(matrix_operands) {
+            self.ctm = multiply(&matrix, &self.ctm);
+        }
+        let resources = document.get_dict(dict, b"Resources").unwrap_or(resources);
+        self.run(&content, resources, depth + 1);
+        self.ctm = ctm;
+        self.text_state = text_state;
+    }
+
+    fn font(&mut self, resources: &Dict, name: &[u8]) -> Option<Rc<Font>> {
+        let document = self.document;
+        let object = document.get_dict(resources, b"Font")?.get(name)?;
+        match object {
+            Object::Ref(number) => {
+                if let Some(font) = self.fonts.get(number) {
+                    return Some(font.clone());
+                }
+                let font = Rc::new(Font::load(document, document.resolve(object).as_dict()?));
+                self.fonts.insert(*number, font.clone());
+                Some(font)
+            }
+            Object::Dict(dict) => Some(Rc::new(Font::load(document, dict))),
+            _ => None,
+        }
+    }
+
+    /// Shows `string` at the current position and moves past it.
+    fn show(&mut self, string: &[u8]) {
+        let state = &mut self.text_state;
+        let font = state.font.clone();
+        let codes = match &font {
+            Some(font) => font.codes(string),
+            None => string.iter().map(|byte| (u32::from(*byte), 1)).collect(),
+        };
+
+        let start = self.position();
+        let mut shown = String::new();
+        for (code, length) in codes {
+            let (text, width) = match &font {
+                Some(font) => (font.text(code), font.width(code)),
+                None => (win_ansi(code as u8).map(String::from).unwrap_or_default(), DEFAULT_WIDTH),
+            };
+            shown.push_str(&text);
+
+            let state = &mut self.text_state;
+            let word_spacing = if length == 1 && code == 32 { state.word_spacing } else { 0.0 };
+            let advance = (width / 1000.0 * state.size + state.char_spacing + word_spacing) * state.scale;
+            state.matrix = multiply(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &state.matrix);
+        }
+        let end = self.position();
+
+        self.text.show(&shown, start, end);
+    }
+
+    /// Position and font size of the text on the page.
+    fn position(&self) -> (f64, f64, f64) {
+        let state = &self.text_state;
+        let matrix = multiply(&multiply(&[state.size * state.scale, 0.0, 0.0, state.size, 0.0, state.rise], &state.matrix), &self.ctm);
+        (matrix[4], matrix[5], matrix[2].hypot(matrix[3]))
+    }
+}
+
+/// Collects the shown text into lines: text further from the last in height than half its size
+/// starts a new line, a gap on the same line becomes a space, as does going back on it.
+#[derive(Default)]
+struct TextWriter {
+    text: String,
+    /// End of the last text shown on the current line, with the font size.
+    last: Option<(f64, f64, f64)>,
+}
+
+impl TextWriter {
+    fn show(&mut self, text: &str, start: (f64, f64, f64), end: (f64, f64, f64)) {
+        let text: String = text.chars().filter(|c| !c.is_control() || *c == '\t').collect();
+        if text.is_empty() {
+            return;
+        }
+
+        if let Some((x, y, size)) = self.last {
+            let size = size.max(start.2).max(1e-3);
+            if (start.1 - y).abs() > size / 2.0 {
+                self.end_line();
+            } else if (start.0 - x > size * 0.15 || start.0 < x - size)
+                && !self.text.ends_with([' ', '\t']) && !text.starts_with([' ', '\t'])
+            {
+                self.text.push(' ');
+            }
+        }
+
+        self.text.push_str(&text);
+        self.last = Some(end);
+    }
+
+    fn end_line(&mut self) {
+        if self.last.take().is_some() {
+            let trimmed = self.text.trim_end_matches([' ', '\t']).len();
+            self.text.truncate(trimmed);
+            self.text.push('\n');
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.end_line();
+        self.text
+    }
+}
+
@@ -0,0 +1,107 @@
+//! Chrome trace event JSON, which the Perfetto UI and `chrome://tracing` load directly. Log
+//! messages become instant events on one track per application and context.
+
+use std::{collections::HashMap, io::{self, Write}};
+use serde_json::json;
+
+/// Streams trace events to `out`. Processes and threads are numbered in order of appearance,
+/// their names are written by [`TraceWriter::finish`].
+pub struct TraceWriter<W: Write> {
+    out: W,
+    processes: HashMap<String, u32>,
+    threads: HashMap<(u32, String), u32>,
+    first: bool,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(b"{\"traceEvents\":[\n")?;
+
+        Ok(TraceWriter { out, processes: HashMap::new(), threads: HashMap::new(), first: true })
+    }
+
+    /// Writes an instant event at `micros` since the epoch on the track of `process` and `thread`.
+    pub fn instant(&mut self, micros: u64, process: &str, thread: &str, category: &str, text: &str) -> io::Result<()> {
+        let next_pid = self.processes.len() as u32 + 1;
+        let pid = *self.processes.entry(process.to_string()).or_insert(next_pid);
+        let next_tid = self.threads.len() as u32 + 1;
+        let tid = *self.threads.entry((pid, thread.to_string())).or_insert(next_tid);
+
+        self.write(json!({
+            "name": text,
+            "cat": category,
+            "ph": "i",
+            "s": "t",
+            "ts": micros,
+            "pid": pid,
+            "tid": tid,
+        }))
+    }
+
+    /// Names the tracks and closes the JSON document.
+    pub fn finish(mut self) -> io::Result<W> {
+        let processes: Vec<(String, u32)> = self.processes.drain().collect();
+        for (name, pid) in processes {
+            self.write(json!({ "name": "process_name", "ph": "M", "pid": pid, "args": { "name": name } }))?;
+        }
+
+        let threads: Vec<((u32, String), u32)> = self.threads.drain().collect();
+        for ((pid, name), tid) in threads {
+            self.write(json!({ "name": "thread_name", "ph": "M", "pid": pid, "tid": tid, "args": { "name": name } }))?;
+        }
+
+        self.out.write_all(b"\n]}\n")?;
+        self.out.flush()?;
+
+        Ok(self.out)
+    }
+
+    fn write(&mut self, event: serde_json::Value) -> io::Result<()> {
+        if !self.first {
+            self.out.write_all(b",\n")?;
+        }
+        self.first = false;
+
+        serde_json::to_writer(&mut self.out, &event)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use serde_json::Value;
+
+    #[test]
+    fn writes_instants_and_names_their_tracks() {
+        let mut writer = TraceWriter::new(Vec::new()).unwrap();
+        writer.instant(100, "ECU1 APP1", "CTX1", "info", "said \"hi\"\n").unwrap();
+        writer.instant(200, "ECU1 APP1", "CTX2", "warn", "b").unwrap();
+        writer.instant(300, "ECU1 APP2", "CTX1", "info", "c").unwrap();
+        let trace: Value = serde_json::from_slice(&writer.finish().unwrap()).unwrap();
+
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 3 + 2 + 3);
+        assert_eq!(events[0], json!({ "name": "said \"hi\"\n", "cat": "info", "ph": "i", "s": "t", "ts": 100, "pid": 1, "tid": 1 }));
+        assert_eq!((&events[1]["pid"], &events[1]["tid"]), (&json!(1), &json!(2)));
+        assert_eq!((&events[2]["pid"], &events[2]["tid"]), (&json!(2), &json!(3)));
+
+        let names = |kind: &str| -> BTreeSet<String> {
+            events.iter()
+                .filter(|event| event["ph"] == "M" && event["name"] == kind)
+                .map(|event| format!("{} {} {}", event["pid"], event["tid"], event["args"]["name"]))
+                .collect()
+        };
+        assert_eq!(names("process_name"), BTreeSet::from(["1 null \"ECU1 APP1\"".to_string(), "2 null \"ECU1 APP2\"".to_string()]));
+        assert_eq!(names("thread_name"), BTreeSet::from(["1 1 \"CTX1\"".to_string(), "1 2 \"CTX2\"".to_string(), "2 3 \"CTX1\"".to_string()]));
+    }
+
+    #[test]
+    fn writes_an_empty_trace() {
+        let trace: Value = serde_json::from_slice(&TraceWriter::new(Vec::new()).unwrap().finish().unwrap()).unwrap();
+
+        assert_eq!(trace, json!({ "traceEvents": [] }));
+    }
+}
@@ -0,0 +1,253 @@
+//! Scrubbing of sensitive data like serial numbers, IMEIs and tokens from log text, before logs
+//! are shared outside the team.
+
+use std::{borrow::Cow, fs, io::{self, BufRead, BufReader, BufWriter, Read, Write}, path::{Path, PathBuf}};
+use anyhow::Context;
+use regex::Regex;
+use crate::config::RedactionRule;
+
+/// Replacement for rules that do not name one.
+pub const DEFAULT_PLACEHOLDER: &str = "[REDACTED]";
+
+/// The `redaction_rules` of the config, compiled.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    rules: Vec<(Regex, String)>,
+    /// The same rules for text files of any encoding, which are redacted without decoding.
+    byte_rules: Vec<(regex::bytes::Regex, String)>,
+}
+
+impl Redactor {
+    pub fn new(rules: &[RedactionRule]) -> anyhow::Result<Self> {
+        if rules.is_empty() {
+            anyhow::bail!("Redacting needs at least one entry in redaction_rules");
+        }
+
+        let rules: Vec<_> = rules
+            .iter()
+            .map(|rule| {
+                let pattern = Regex::new(&rule.pattern)
+                    .map_err(|e| anyhow::anyhow!("Invalid pattern `{}` in redaction_rules: {}", rule.pattern, e))?;
+                let replacement = rule.replacement.clone().unwrap_or_else(|| DEFAULT_PLACEHOLDER.to_string());
+
+                Ok((pattern, replacement))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        // A pattern valid as text is valid on bytes
+        let byte_rules = rules.iter()
+            .map(|(pattern, replacement)| (regex::bytes::Regex::new(pattern.as_str()).unwrap(), replacement.clone()))
+            .collect();
+
+        Ok(Redactor { rules, byte_rules })
+    }
+
+    /// `line` with the spans matched by each rule replaced, in rule order.
+    pub fn redact<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let mut line = Cow::Borrowed(line);
+        for (pattern, replacement) in &self.rules {
+            if let Cow::Owned(replaced) = pattern.replace_all(&line, replacement.as_str()) {
+                line = Cow::Owned(replaced);
+            }
+        }
+
+        line
+    }
+
+    /// `line` with the spans matched by each rule replaced, in rule order. Bytes that are not
+    /// UTF-8, like Latin-1 text, are kept as they are.
+    pub fn redact_bytes<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut line = Cow::Borrowed(line);
+        for (pattern, replacement) in &self.byte_rules {
+            if let Cow::Owned(replaced) = pattern.replace_all(&line, replacement.as_bytes()) {
+                line = Cow::Owned(replaced);
+            }
+        }
+
+        line
+    }
+
+    /// Redacts the text file at `path` in place, UTF-8 or UTF-16 with a byte order mark as
+    /// written by `--output-encoding`. Returns false for binary files, which are left alone.
+    pub fn redact_file(&self, path: &Path) -> anyhow::Result<bool> {
+        let mut head = Vec::new();
+        fs::File::open(path)?.take(8192).read_to_end(&mut head)?;
+        let big_endian = match head.get(..2) {
+            Some([0xFF, 0xFE]) => Some(false),
+            Some([0xFE, 0xFF]) => Some(true),
+            _ if head.contains(&0) => return Ok(false),
+            _ => None,
+        };
+
+        let tmp_path = PathBuf::from(format!("{}.redacting", path.display()));
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut out = BufWriter::new(fs::File::create(&tmp_path)?);
+
+        let written = match big_endian {
+            Some(big_endian) => self.redact_utf16(&mut reader, &mut out, big_endian),
+            None => self.redact_text(&mut reader, &mut out).map_err(anyhow::Error::from),
+        };
+        let written = written.and_then(|()| Ok(out.flush()?));
+        drop(out);
+
+        if let Err(err) = written.and_then(|()| Ok(fs::rename(&tmp_path, path)?)) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err.context(format!("Redacting {}", path.display())));
+        }
+
+        Ok(true)
+    }
+
+    fn redact_text(&self, reader: &mut impl BufRead, out: &mut impl Write) -> io::Result<()> {
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            let newline = line.last() == Some(&b'\n');
+            if newline {
+                line.pop();
+            }
+
+            out.write_all(&self.redact_bytes(&line))?;
+            if newline {
+                out.write_all(b"\n")?;
+            }
+            line.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Redacts UTF-16 text after its byte order mark line by line. Text that is not valid UTF-16
+    /// is refused rather than written back damaged.
+    fn redact_utf16(&self, reader: &mut impl Read, out: &mut impl Write, big_endian: bool) -> anyhow::Result<()> {
+        let decode = if big_endian { u16::from_be_bytes } else { u16::from_le_bytes };
+        let encode = if big_endian { u16::to_be_bytes } else { u16::to_le_bytes };
+
+        let mut unit = [0u8; 2];
+        reader.read_exact(&mut unit)?;
+        out.write_all(&unit)?;
+
+        let mut line = Vec::new();
+        loop {
+            let read = reader.read(&mut unit[..1])?;
+            if read > 0 {
+                reader.read_exact(&mut unit[1..]).context("The UTF-16 text ends in half a character")?;
+                line.push(decode(unit));
+                if line.last() != Some(&u16::from(b'\n')) {
+                    continue;
+                }
+            }
+            if line.is_empty() {
+                return Ok(());
+            }
+
+            let newline = line.last() == Some(&u16::from(b'\n'));
+            if newline {
+                line.pop();
+            }
+            let text = String::from_utf16(&line).context("The text is not valid UTF-16")?;
+            for unit in self.redact(&text).encode_utf16().chain(newline.then_some(u16::from(b'\n'))) {
+                out.write_all(&encode(unit))?;
+            }
+            line.clear();
+
+            if read == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Redacts every text file in `dir` in place, returning the number of files redacted.
+pub fn redact_logs(dir: &Path, redactor: &Redactor) -> anyhow::Result<usize> {
+    if !dir.is_dir() {
+        anyhow::bail!("Cannot read {}, extract the logs first", dir.display());
+    }
+
+    let mut paths: Vec<_> = fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut redacted = 0;
+    for path in paths.into_iter().filter(|p| p.is_file()) {
+        if redactor.redact_file(&path)? {
+            redacted += 1;
+        }
+    }
+
+    Ok(redacted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(rules: &[(&str, Option<&str>)]) -> Redactor {
+        let rules: Vec<RedactionRule> = rules.iter()
+            .map(|(pattern, replacement)| RedactionRule { pattern: pattern.to_string(), replacement: replacement.map(str::to_string) })
+            .collect();
+        Redactor::new(&rules).unwrap()
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let bom = if big_endian { [0xFE, 0xFF] } else { [0xFF, 0xFE] };
+        bom.into_iter()
+            .chain(text.encode_utf16().flat_map(|unit| if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() }))
+            .collect()
+    }
+
+    #[test]
+    fn redacts_utf16_logs_in_their_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let redactor = redactor(&[(r"^imei \d+", Some("imei ?"))]);
+
+        for big_endian in [false, true] {
+            let path = dir.path().join("log.txt");
+            fs::write(&path, utf16("imei 123456\nok\nimei 42", big_endian)).unwrap();
+
+            assert!(redactor.redact_file(&path).unwrap());
+            assert_eq!(fs::read(&path).unwrap(), utf16("imei ?\nok\nimei ?", big_endian));
+        }
+    }
+
+    #[test]
+    fn applies_the_rules_in_order() {
+        let redactor = redactor(&[(r"serial=(\w+)", Some("serial=<$1>")), (r"<\w+>", None)]);
+
+        assert_eq!(redactor.redact("boot serial=AB12 id=1"), "boot serial=[REDACTED] id=1");
+        assert_eq!(redactor.redact_bytes(b"boot serial=AB12 id=1").as_ref(), b"boot serial=[REDACTED] id=1");
+        assert!(matches!(redactor.redact("nothing to hide"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn redacts_text_files_in_place_and_leaves_binary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let redactor = redactor(&[(r"\b\d{15}\b", None)]);
+
+        let log = dir.path().join("main.log");
+        fs::write(&log, b"imei 490154203237518\nstra\xdfe 490154203237518 ok\r\nlast").unwrap();
+        assert!(redactor.redact_file(&log).unwrap());
+        assert_eq!(fs::read(&log).unwrap(), b"imei [REDACTED]\nstra\xdfe [REDACTED] ok\r\nlast");
+
+        let core = dir.path().join("core.bin");
+        fs::write(&core, b"490154203237518\0\x01").unwrap();
+        assert!(!redactor.redact_file(&core).unwrap());
+        assert_eq!(fs::read(&core).unwrap(), b"490154203237518\0\x01");
+
+        let paths: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(paths.len(), 2, "{:?}", paths);
+    }
+
+    #[test]
+    fn refuses_broken_utf16() {
+        let dir = tempfile::tempdir().unwrap();
+        let redactor = redactor(&[("x", None)]);
+
+        let path = dir.path().join("odd.txt");
+        fs::write(&path, [&utf16("x", false)[..], b"x"].concat()).unwrap();
+        assert!(redactor.redact_file(&path).is_err());
+
+        let path = dir.path().join("surrogate.txt");
+        fs::write(&path, [0xFF, 0xFE, 0x00, 0xD8, b'x', 0]).unwrap();
+        assert!(redactor.redact_file(&path).is_err());
+        assert_eq!(fs::read(&path).unwrap(), [0xFF, 0xFE, 0x00, 0xD8, b'x', 0]);
+        assert!(!dir.path().join("surrogate.txt.redacting").exists());
+    }
+}
@@ -0,0 +1,161 @@
+//! Machine-readable record of a run, one entry per issue written as soon as the issue is done:
+//! CSV with a row per attachment for spreadsheets, or a JSON object per line.
+
+use std::{fs, io::{self, BufWriter, Write}, path::Path};
+use serde::Serialize;
+use crate::{fetch::Transfer, manifest::{Manifest, Status}, stage::StageTiming};
+
+/// Layout of the report file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// A header line and one row per attachment.
+    Csv,
+    /// One JSON object per issue and line.
+    Json,
+}
+
+const CSV_HEADER: &str = "issue,filename,size,status,sha256,error";
+
+/// What the report lists for an issue.
+#[derive(Serialize, Debug)]
+pub struct IssueReport {
+    pub issue: String,
+    pub attachments: Vec<AttachmentRow>,
+    /// Only in JSON reports of runs with `--profile-timing`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<StageTiming>,
+    /// Only in JSON reports of issues fetched in full.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<Transfer>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AttachmentRow {
+    pub filename: String,
+    pub size: Option<u64>,
+    pub status: Status,
+    pub sha256: Option<String>,
+    pub error: Option<String>,
+}
+
+impl IssueReport {
+    /// The attachments recorded in `manifest`, ordered by file name.
+    pub fn new(issue: &str, manifest: &Manifest) -> Self {
+        let mut attachments: Vec<AttachmentRow> = manifest
+            .attachments
+            .values()
+            .map(|entry| AttachmentRow {
+                filename: entry.filename.clone(),
+                size: entry.size,
+                status: entry.status,
+                sha256: entry.sha256.clone(),
+                error: entry.error.clone(),
+            })
+            .collect();
+        attachments.sort_by(|a, b| a.filename.cmp(&b.filename));
+
+        IssueReport { issue: issue.to_string(), attachments, timings: Vec::new(), transfer: None }
+    }
+
+    pub fn with_timings(mut self, timings: &[StageTiming]) -> Self {
+        self.timings = timings.to_vec();
+        self
+    }
+
+    pub fn with_transfer(mut self, transfer: Option<Transfer>) -> Self {
+        self.transfer = transfer;
+        self
+    }
+}
+
+/// Writes [`IssueReport`]s to a file, flushing after each so rows are there while the run goes on.
+pub struct Report {
+    out: BufWriter<fs::File>,
+    format: ReportFormat,
+}
+
+impl Report {
+    pub fn create(path: &Path, format: ReportFormat) -> io::Result<Self> {
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        if format == ReportFormat::Csv {
+            writeln!(out, "{}", CSV_HEADER)?;
+            out.flush()?;
+        }
+
+        Ok(Report { out, format })
+    }
+
+    pub fn write(&mut self, report: &IssueReport) -> io::Result<()> {
+        match self.format {
+            ReportFormat::Csv => {
+                for row in &report.attachments {
+                    let status = match row.status {
+                        Status::Downloaded => "downloaded",
+                        Status::Failed => "failed",
+                    };
+                    let size = row.size.map(|s| s.to_string()).unwrap_or_default();
+
+                    writeln!(
+                        self.out,
+                        "{},{},{},{},{},{}",
+                        csv_field(&report.issue), csv_field(&row.filename), size, status, row.sha256.as_deref().unwrap_or_default(),
+                        csv_field(row.error.as_deref().unwrap_or_default())
+                    )?;
+                }
+            }
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut self.out, report)?;
+                writeln!(self.out)?;
+            }
+        }
+
+        self.out.flush()
+    }
+}
+
+/// Quotes `value` if it contains a separator, quote or line break, doubling inner quotes.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_csv_fields_only_when_needed() {
+        assert_eq!(csv_field("main.log"), "main.log");
+        assert_eq!(csv_field("a,b.log"), "\"a,b.log\"");
+        assert_eq!(csv_field("say \"hi\".log"), "\"say \"\"hi\"\".log\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn writes_a_csv_row_per_attachment() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.csv");
+        let report = IssueReport {
+            issue: "KEY-1".to_string(),
+            attachments: vec![
+                AttachmentRow { filename: "main.log".to_string(), size: Some(5), status: Status::Downloaded, sha256: Some("ab12".to_string()), error: None },
+                AttachmentRow { filename: "gone, really.log".to_string(), size: None, status: Status::Failed, sha256: None, error: Some("HTTP 410, gone".to_string()) },
+            ],
+            timings: Vec::new(),
+            transfer: None,
+        };
+
+        let mut csv = Report::create(&path, ReportFormat::Csv).unwrap();
+        csv.write(&report).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), concat!(
+            "issue,filename,size,status,sha256,error\n",
+            "KEY-1,main.log,5,downloaded,ab12,\n",
+            "KEY-1,\"gone, really.log\",,failed,,\"HTTP 410, gone\"\n",
+        ));
+    }
+}
@@ -0,0 +1,106 @@
+//! Waiting between attempts with exponential backoff.
+
+use std::time::{Duration, Instant};
+use crate::interrupt::{self, Interrupted};
+
+/// Fraction of each delay it is randomly shortened or lengthened by, unless `retry_jitter` in
+/// the config says otherwise.
+pub const DEFAULT_JITTER: f64 = 0.25;
+
+/// Delays doubling from `initial` up to `max`, until `window` has passed since the first one.
+/// With jitter, parallel downloads failing at the same moment do not retry in lockstep.
+pub struct Backoff {
+    delay: Duration,
+    max: Duration,
+    deadline: Option<Instant>,
+    jitter: f64,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration, window: Duration) -> Self {
+        Backoff { delay: initial, max, deadline: Instant::now().checked_add(window), jitter: 0.0 }
+    }
+
+    /// Randomly shortens or lengthens each delay by up to `fraction` of it.
+    pub fn with_jitter(mut self, fraction: f64) -> Self {
+        self.jitter = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Gives up at `deadline` if that comes before the end of the window.
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        if let Some(deadline) = deadline {
+            self.deadline = Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
+        }
+        self
+    }
+
+    /// The delay before the next attempt, or `None` once the window is used up.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        let remaining = match self.deadline {
+            Some(deadline) => deadline.checked_duration_since(Instant::now())?,
+            None => Duration::MAX,
+        };
+        let factor = 1.0 + self.jitter * (2.0 * fastrand::f64() - 1.0);
+        let delay = self.delay.mul_f64(factor).min(remaining);
+        self.delay = (self.delay * 2).min(self.max);
+
+        Some(delay)
+    }
+}
+
+/// Sleeps for `delay`, failing with [`Interrupted`] as soon as Ctrl-C is pressed.
+pub async fn sleep(delay: Duration) -> anyhow::Result<()> {
+    tokio::select! {
+        _ = tokio::time::sleep(delay) => Ok(()),
+        _ = interrupt::wait() => Err(Interrupted.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn doubles_delays_up_to_the_maximum() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5), HOUR);
+
+        let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().unwrap().as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 5, 5]);
+    }
+
+    #[test]
+    fn clamps_and_applies_jitter() {
+        assert_eq!(Backoff::new(Duration::ZERO, Duration::ZERO, HOUR).with_jitter(3.0).jitter, 1.0);
+        assert_eq!(Backoff::new(Duration::ZERO, Duration::ZERO, HOUR).with_jitter(-0.5).jitter, 0.0);
+
+        let second = Duration::from_secs(1);
+        let mut backoff = Backoff::new(second, second, HOUR).with_jitter(0.25);
+        for _ in 0..100 {
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay >= second.mul_f64(0.75) && delay <= second.mul_f64(1.25), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn caps_delays_at_the_deadline() {
+        let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(10), HOUR)
+            .with_deadline(Some(Instant::now() + Duration::from_millis(50)));
+        assert!(backoff.next_delay().unwrap() <= Duration::from_millis(50));
+
+        // The end of the window still counts when the deadline is later
+        let mut backoff = Backoff::new(Duration::from_secs(10), Duration::from_secs(10), Duration::from_millis(50)).with_deadline(Some(Instant::now() + HOUR));
+        assert!(backoff.next_delay().unwrap() <= Duration::from_millis(50));
+        assert!(backoff.with_deadline(None).deadline.is_some());
+    }
+
+    #[test]
+    fn gives_up_after_the_deadline() {
+        let past = Instant::now().checked_sub(Duration::from_millis(1)).unwrap();
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(1), HOUR).with_deadline(Some(past));
+
+        assert_eq!(backoff.next_delay(), None);
+    }
+}
@@ -0,0 +1,225 @@
+//! `getlogs setup`: asks for the Jira server, credentials, proxy and download folder, checks
+//! that they work and writes the user config, instead of leaving a template to edit by hand.
+
+use std::{fs, io::{self, Write}, path::{Path, PathBuf}};
+use anyhow::Context;
+use console::{Term, style};
+use crate::{config::{self, Config, PLACEHOLDER_JIRA_URL}, disk, fetch::{self, Session}, output};
+
+/// How the wizard signs in to Jira.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Auth {
+    /// Personal access token of Jira Server and Data Center, as `bearer_token`.
+    Token,
+    /// Jira Cloud account email with an API token.
+    Email,
+    /// Command printing a token, as `token_command`.
+    Command,
+}
+
+/// Asks for the settings needed to fetch from Jira and writes them to the user config once
+/// signing in with them works. An existing config is only replaced with `force`, unless it is
+/// still the template of the first run; its other fields are kept.
+pub async fn run(force: bool) -> anyhow::Result<()> {
+    let path = config::config_file();
+    // A broken config is replaced from scratch
+    let existing = match read_existing(&path) {
+        Err(_) if force => None,
+        result => result?,
+    };
+    if existing.as_ref().is_some_and(|config| config.jira_url != PLACEHOLDER_JIRA_URL) && !force {
+        anyhow::bail!("{} already exists, pass --force to replace it", path.display());
+    }
+
+    let term = Term::stderr();
+    if !term.is_term() {
+        anyhow::bail!("setup asks for the settings and needs a terminal, edit {} instead", path.display());
+    }
+
+    let mut config = existing.unwrap_or_else(Config::template);
+    loop {
+        ask(&term, &mut config)?;
+
+        output::info("Signing in to check the settings...");
+        let result = match Session::new(&config).await {
+            Ok(session) => fetch::current_user(&session).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(user) => {
+                output::success(format!("Signed in as {}", user));
+                break;
+            }
+            Err(err) => {
+                output::failure(format!("{:#}", err));
+                match choose(&term, "Change the settings, save them anyway or quit?", &["change", "save", "quit"], 0)? {
+                    0 => continue,
+                    1 => break,
+                    _ => anyhow::bail!("Setup cancelled, {} was not written", path.display()),
+                }
+            }
+        }
+    }
+
+    save(&config, &path)?;
+    output::success(format!("Saved the config to {}, fetch an issue with `getlogs all ISSUE-1`", path.display()));
+
+    Ok(())
+}
+
+/// The config at `path`, `None` if there is none yet.
+fn read_existing(path: &Path) -> anyhow::Result<Option<Config>> {
+    match fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data).map(Some).with_context(|| format!("Invalid config {}, fix it or pass --force to replace it", path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("Could not read {}", path.display())),
+    }
+}
+
+/// Asks for each setting, offering the current value of `config`.
+fn ask(term: &Term, config: &mut Config) -> anyhow::Result<()> {
+    let current = (config.jira_url != PLACEHOLDER_JIRA_URL).then(|| config.jira_url.clone());
+    config.jira_url = loop {
+        let url = prompt(term, "Jira URL, like https://jira.example.com", current.as_deref())?;
+        match parse_url(&url, &["http", "https"]) {
+            Ok(()) => break url.trim_end_matches('/').to_string(),
+            Err(err) => output::warning(err),
+        }
+    };
+
+    let cloud = config.jira_url.contains(".atlassian.net");
+    let current = if config.token_command.is_some() {
+        Auth::Command
+    } else if config.user_email.is_some() || (config.bearer_token.is_none() && cloud) {
+        Auth::Email
+    } else {
+        Auth::Token
+    };
+    let methods = [Auth::Token, Auth::Email, Auth::Command];
+    let labels = ["personal access token (Jira Server and Data Center)", "email and API token (Jira Cloud)", "command printing a token"];
+    let chosen = choose(term, "Sign in with", &labels, methods.iter().position(|m| *m == current).unwrap_or(0))?;
+    match methods[chosen] {
+        Auth::Token => {
+            config.bearer_token = Some(secret(term, "Personal access token", config.bearer_token.as_deref())?);
+            (config.user_email, config.api_token, config.token_command) = (None, None, None);
+        }
+        Auth::Email => {
+            config.user_email = Some(prompt(term, "Account email", config.user_email.as_deref())?);
+            config.api_token = Some(secret(term, "API token", config.api_token.as_deref())?);
+            (config.bearer_token, config.token_command) = (None, None);
+        }
+        Auth::Command => {
+            config.token_command = Some(prompt(term, "Command printing the token", config.token_command.as_deref())?);
+            (config.bearer_token, config.user_email, config.api_token) = (None, None, None);
+        }
+    }
+
+    config.proxy = loop {
+        let proxy = prompt(term, "Proxy URL, `none` for a direct connection", Some(config.proxy.as_deref().unwrap_or("none")))?;
+        if proxy.eq_ignore_ascii_case("none") {
+            break None;
+        }
+        match parse_url(&proxy, &["http", "https", "socks5", "socks5h"]) {
+            Ok(()) => break Some(proxy),
+            Err(err) => output::warning(err),
+        }
+    };
+
+    config.default_path = loop {
+        let path = expand_home(&prompt(term, "Folder for the downloads", Some(&config.default_path.to_string_lossy()))?);
+        match fs::create_dir_all(&path).map_err(anyhow::Error::from).and_then(|()| disk::check_destination(&path)) {
+            Ok(()) => break path,
+            Err(err) => output::warning(format!("Cannot use {}: {:#}", path.display(), err)),
+        }
+    };
+
+    Ok(())
+}
+
+/// Fails unless `url` is an absolute URL with a host and one of the `schemes`.
+fn parse_url(url: &str, schemes: &[&str]) -> anyhow::Result<()> {
+    let parsed = reqwest::Url::parse(url).with_context(|| format!("`{}` is no valid URL", url))?;
+    if !schemes.contains(&parsed.scheme()) || parsed.host_str().is_none() {
+        anyhow::bail!("`{}` should start with {}:// and name a host", url, schemes.join("://, "));
+    }
+    Ok(())
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Reads a line for `label`, `default` if it is left empty. Asks again while it is empty and
+/// there is no default.
+fn prompt(term: &Term, label: &str, default: Option<&str>) -> io::Result<String> {
+    loop {
+        match default {
+            Some(default) if !default.is_empty() => term.write_str(&format!("{} [{}]: ", style(label).bold(), default))?,
+            _ => term.write_str(&format!("{}: ", style(label).bold()))?,
+        }
+        let line = term.read_line()?.trim().to_string();
+        match (line.is_empty(), default) {
+            (false, _) => return Ok(line),
+            (true, Some(default)) => return Ok(default.to_string()),
+            (true, None) => {}
+        }
+    }
+}
+
+/// Reads a line for `label` without showing it, keeping `current` if it is left empty.
+fn secret(term: &Term, label: &str, current: Option<&str>) -> io::Result<String> {
+    loop {
+        match current {
+            Some(_) => term.write_str(&format!("{} (hidden, empty keeps the current one): ", style(label).bold()))?,
+            None => term.write_str(&format!("{} (hidden): ", style(label).bold()))?,
+        }
+        let line = term.read_secure_line()?.trim().to_string();
+        match (line.is_empty(), current) {
+            (false, _) => return Ok(line),
+            (true, Some(current)) => return Ok(current.to_string()),
+            (true, None) => {}
+        }
+    }
+}
+
+/// Index of the option picked from `options` by number, `default` on an empty line.
+fn choose(term: &Term, label: &str, options: &[&str], default: usize) -> io::Result<usize> {
+    term.write_line(&style(label).bold().to_string())?;
+    for (i, option) in options.iter().enumerate() {
+        term.write_line(&format!("  {}) {}", i + 1, option))?;
+    }
+    loop {
+        let answer = prompt(term, "Choice", Some(&(default + 1).to_string()))?;
+        match answer.parse::<usize>() {
+            Ok(choice) if (1..=options.len()).contains(&choice) => return Ok(choice - 1),
+            _ => output::warning(format!("Enter a number from 1 to {}", options.len())),
+        }
+    }
+}
+
+/// Writes `config` to `path`, readable only by the user as it holds credentials.
+fn save(config: &Config, path: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files, the first-run template already exists
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    let mut file = options.open(path).with_context(|| format!("Could not write {}", path.display()))?;
+    file.write_all(serde_json::to_string_pretty(config)?.as_bytes())?;
+
+    Ok(())
+}
@@ -0,0 +1,287 @@
+//! `--source sftp`: log archives on an SSH file server, one folder per issue. The files are
+//! listed and downloaded with the OpenSSH `sftp` client in batch mode, so `~/.ssh/config`, the
+//! SSH agent and known hosts work as they do for `ssh`.
+
+use std::{fs, path::{Path, PathBuf}, process::Stdio};
+use anyhow::Context;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use reqwest::Url;
+use time::{Date, Month, OffsetDateTime, Time};
+use crate::{attachment::Attachment, config::SftpConfig, manifest};
+
+/// Seconds `sftp` waits for the server to answer before giving up.
+const CONNECT_TIMEOUT: u64 = 30;
+
+/// Environment variable the askpass script reads the password from.
+const PASSWORD_ENV: &str = "GETLOGS_SFTP_PASSWORD";
+
+/// A folder or file on the server.
+struct Remote {
+    /// `user@host` or `host`, as passed to `sftp`.
+    destination: String,
+    port: Option<u16>,
+    path: String,
+}
+
+impl Remote {
+    fn parse(url: &Url) -> anyhow::Result<Self> {
+        if url.scheme() != "sftp" {
+            anyhow::bail!("Expected an sftp:// URL, got {}", url);
+        }
+        let host = url.host_str().with_context(|| format!("No host in {}", url))?;
+        let destination = match url.username() {
+            "" => host.to_string(),
+            user => format!("{}@{}", percent_decode_str(user).decode_utf8_lossy(), host),
+        };
+        let path = percent_decode_str(url.path()).decode_utf8_lossy().into_owned();
+
+        Ok(Remote { destination, port: url.port(), path })
+    }
+}
+
+/// URL of the folder of `issue`: `url` of the config with `{issue}` replaced, or with the issue
+/// appended as a subfolder if it has no placeholder.
+fn issue_url(config: &SftpConfig, issue: &str) -> anyhow::Result<Url> {
+    let mut url = if config.url.contains("{issue}") {
+        Url::parse(&config.url.replace("{issue}", issue))
+    } else {
+        Url::parse(&config.url).map(|mut url| {
+            url.path_segments_mut().map(|mut segments| { segments.pop_if_empty().push(issue); }).ok();
+            url
+        })
+    }.with_context(|| format!("Invalid sftp url {}", config.url))?;
+
+    // Files are added as segments of the folder, which must not end in a slash for that
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty();
+    }
+    Ok(url)
+}
+
+/// The regular files in the folder of `issue`, as attachments whose ID is their name.
+pub(crate) async fn attachments(config: Option<&SftpConfig>, issue: &str) -> anyhow::Result<Vec<Attachment>> {
+    let config = config.context("No `sftp` section in config")?;
+    let url = issue_url(config, issue)?;
+    let remote = Remote::parse(&url)?;
+
+    let listing = run(config, &remote, &format!("ls -ln {}\n", quote(&remote.path))).await
+        .with_context(|| format!("Could not list {}", url))?;
+
+    Ok(parse_listing(&listing, &url, OffsetDateTime::now_utc()))
+}
+
+/// The regular files of the `ls -ln` output `listing` of the folder at `url`.
+fn parse_listing(listing: &str, url: &Url, now: OffsetDateTime) -> Vec<Attachment> {
+    // `-rw-r--r--    1 1000     1000         1234 Jan  1 10:00 name`, the year instead of the
+    // time for older files
+    let line = Regex::new(r"^-\S*\s+\d+\s+\S+\s+\S+\s+(\d+)\s+([A-Z][a-z]{2})\s+(\d{1,2})\s+(\d{1,2}:\d{2}|\d{4})\s+(.+)$").unwrap();
+    let mut attachments = Vec::new();
+    for captures in listing.lines().filter_map(|l| line.captures(l)) {
+        let name = captures[5].rsplit('/').next().unwrap_or(&captures[5]).to_string();
+        let mut content = url.clone();
+        content.path_segments_mut().map(|mut segments| { segments.push(&name); }).ok();
+
+        attachments.push(Attachment {
+            id: name.clone(),
+            filename: name,
+            content: content.to_string(),
+            size: captures[1].parse().ok(),
+            mime_type: None,
+            created: listed_time(&captures[2], &captures[3], &captures[4], now),
+            thumbnail: None,
+        });
+    }
+
+    attachments
+}
+
+/// The modification time of a listing in Jira's format. Times without a year are from the last
+/// twelve months.
+fn listed_time(month: &str, day: &str, time_or_year: &str, now: OffsetDateTime) -> Option<String> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let month = Month::try_from(MONTHS.iter().position(|m| *m == month)? as u8 + 1).ok()?;
+    let day = day.parse().ok()?;
+
+    let (year, hour, minute) = match time_or_year.split_once(':') {
+        Some((hour, minute)) => {
+            // A date still ahead this year, or a 29 February it does not have, is from last year
+            let year = match Date::from_calendar_date(now.year(), month, day) {
+                Ok(date) if date <= now.date() => now.year(),
+                _ => now.year() - 1,
+            };
+            (year, hour.parse().ok()?, minute.parse().ok()?)
+        }
+        None => (time_or_year.parse().ok()?, 0, 0),
+    };
+    let date = Date::from_calendar_date(year, month, day).ok()?;
+    let time = Time::from_hms(hour, minute, 0).ok()?;
+    Some(format!("{}T{:02}:{:02}:00.000+0000", date, time.hour(), time.minute()))
+}
+
+/// Downloads the file at `url` into `<out_path>.part`, continuing a part left by an earlier
+/// run, and renames it to `out_path` once it has `size` bytes. Returns the bytes transferred.
+pub(crate) async fn download(config: Option<&SftpConfig>, url: &str, out_path: &Path, size: Option<u64>) -> anyhow::Result<u64> {
+    let config = config.context("No `sftp` section in config")?;
+    let remote = Remote::parse(&Url::parse(url)?)?;
+    let part_path = PathBuf::from(format!("{}{}", out_path.display(), manifest::PART_SUFFIX));
+    let offset = fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+
+    if !part_path.exists() {
+        fs::File::create(&part_path)?;
+    }
+    let get = if offset > 0 { "reget" } else { "get" };
+    run(config, &remote, &format!("{} {} {}\n", get, quote(&remote.path), quote(&part_path.to_string_lossy()))).await?;
+
+    let received = fs::metadata(&part_path)?.len();
+    if let Some(size) = size && received != size {
+        anyhow::bail!("Downloaded {} of {} bytes, the file may have changed on the server", received, size);
+    }
+    fs::rename(&part_path, out_path)?;
+
+    Ok(received.saturating_sub(offset))
+}
+
+/// Runs the sftp `commands` against `remote`, returning what they printed.
+async fn run(config: &SftpConfig, remote: &Remote, commands: &str) -> anyhow::Result<String> {
+    let mut process = tokio::process::Command::new("sftp");
+    let askpass = match &config.password {
+        Some(password) => {
+            let script = askpass_script()?;
+            // `-b` turns on BatchMode, which rules out passwords; the first value given wins
+            process.args(["-o", "BatchMode=no", "-o", "NumberOfPasswordPrompts=1"])
+                .env("SSH_ASKPASS", &script.0)
+                .env("SSH_ASKPASS_REQUIRE", "force")
+                .env(PASSWORD_ENV, password);
+            Some(script)
+        }
+        None => None,
+    };
+    process.args(["-b", "-", "-o", &format!("ConnectTimeout={}", CONNECT_TIMEOUT)]);
+    if let Some(port) = remote.port {
+        process.args(["-P", &port.to_string()]);
+    }
+    if let Some(identity) = &config.identity_file {
+        process.arg("-i").arg(identity);
+    }
+    // Listings show times in UTC, as recorded for attachments
+    process.arg(&remote.destination)
+        .env("TZ", "UTC")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = process.spawn().context("Could not run sftp, is the OpenSSH client installed?")?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    tokio::io::AsyncWriteExt::write_all(&mut stdin, commands.as_bytes()).await?;
+    drop(stdin);
+
+    let output = child.wait_with_output().await?;
+    drop(askpass);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // `ssh` names the cause before `sftp` adds `Connection closed`
+        let message: Vec<_> = stderr.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        anyhow::bail!("sftp to {} failed ({}): {}", remote.destination, output.status, message.join(", "));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `value` in double quotes as sftp batch commands take paths with spaces.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Script printing the password from [`PASSWORD_ENV`] for `SSH_ASKPASS`, removed on drop.
+struct Askpass(PathBuf);
+
+impl Drop for Askpass {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(unix)]
+fn askpass_script() -> anyhow::Result<Askpass> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    let path = std::env::temp_dir().join(format!("getlogs-askpass-{}-{}", std::process::id(), fastrand::u64(..)));
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).mode(0o700).open(&path)
+        .with_context(|| format!("Could not create {}", path.display()))?;
+    writeln!(file, "#!/bin/sh\nprintf '%s\\n' \"${}\"", PASSWORD_ENV)?;
+
+    Ok(Askpass(path))
+}
+
+#[cfg(not(unix))]
+fn askpass_script() -> anyhow::Result<Askpass> {
+    anyhow::bail!("The sftp password is only supported on Unix, use identity_file instead")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn config(url: &str) -> SftpConfig {
+        SftpConfig { url: url.to_string(), identity_file: None, password: None }
+    }
+
+    #[test]
+    fn dates_listings_without_a_year_within_the_last_twelve_months() {
+        let now = datetime!(2026-01-05 12:00 UTC);
+        assert_eq!(listed_time("Jan", "5", "10:00", now).as_deref(), Some("2026-01-05T10:00:00.000+0000"));
+        assert_eq!(listed_time("Dec", "30", "23:59", now).as_deref(), Some("2025-12-30T23:59:00.000+0000"));
+        assert_eq!(listed_time("Jan", "6", "08:00", now).as_deref(), Some("2025-01-06T08:00:00.000+0000"));
+        assert_eq!(listed_time("Feb", "29", "10:00", datetime!(2025-03-01 0:00 UTC)).as_deref(), Some("2024-02-29T10:00:00.000+0000"));
+        assert_eq!(listed_time("Mar", "7", "2019", now).as_deref(), Some("2019-03-07T00:00:00.000+0000"));
+    }
+
+    #[test]
+    fn rejects_dates_that_do_not_exist() {
+        let now = datetime!(2026-01-05 12:00 UTC);
+        assert_eq!(listed_time("Foo", "1", "10:00", now), None);
+        assert_eq!(listed_time("Apr", "31", "10:00", now), None);
+        assert_eq!(listed_time("Jan", "1", "25:00", now), None);
+        assert_eq!(listed_time("Feb", "29", "2023", now), None);
+    }
+
+    #[test]
+    fn lists_regular_files_with_their_urls() {
+        let url = Url::parse("sftp://me@logs.example.com/archive/P-1").unwrap();
+        let listing = "sftp> ls -ln \"/archive/P-1\"\n\
+            drwxr-xr-x    2 1000     1000         4096 Jan  1 10:00 sub\n\
+            -rw-r--r--    1 1000     1000         1234 Jan  2 10:00 /archive/P-1/app one.log\n\
+            -rw-r--r--    1 1000     1000        99999 Mar  7  2019 trace ä#1.dlt\n\
+            lrwxrwxrwx    1 1000     1000            9 Jan  2 10:00 link -> app.log\n";
+        let attachments = parse_listing(listing, &url, datetime!(2026-01-05 12:00 UTC));
+
+        let names: Vec<_> = attachments.iter().map(|a| (a.filename.as_str(), a.size, a.created.as_deref())).collect();
+        assert_eq!(names, [
+            ("app one.log", Some(1234), Some("2026-01-02T10:00:00.000+0000")),
+            ("trace ä#1.dlt", Some(99999), Some("2019-03-07T00:00:00.000+0000")),
+        ]);
+        assert_eq!(attachments[0].content, "sftp://me@logs.example.com/archive/P-1/app%20one.log");
+        let parsed = Remote::parse(&Url::parse(&attachments[1].content).unwrap()).unwrap();
+        assert_eq!(parsed.path, "/archive/P-1/trace ä#1.dlt");
+    }
+
+    #[test]
+    fn builds_the_folder_url_of_an_issue() {
+        assert_eq!(issue_url(&config("sftp://me@h/logs/{issue}/"), "P-1").unwrap().as_str(), "sftp://me@h/logs/P-1");
+        assert_eq!(issue_url(&config("sftp://me@h:2222/logs/"), "P-1").unwrap().as_str(), "sftp://me@h:2222/logs/P-1");
+        assert_eq!(issue_url(&config("sftp://h/logs"), "P-1").unwrap().as_str(), "sftp://h/logs/P-1");
+
+        let remote = Remote::parse(&issue_url(&config("sftp://m%40x@h:2222/logs"), "P-1").unwrap()).unwrap();
+        assert_eq!((remote.destination.as_str(), remote.port, remote.path.as_str()), ("m@x@h", Some(2222), "/logs/P-1"));
+        assert!(Remote::parse(&Url::parse("https://h/logs").unwrap()).is_err());
+    }
+
+    #[test]
+    fn quotes_batch_paths() {
+        assert_eq!(quote(r#"/a b/"c"\d"#), r#""/a b/\"c\"\\d""#);
+    }
+}
@@ -0,0 +1,74 @@
+//! Time limits for the steps of an issue, so one stuck download or huge archive does not hang an
+//! unattended run.
+
+use std::{future::Future, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
+use serde::Serialize;
+use crate::interrupt;
+
+/// Wall-clock time a step of an issue took, for `--profile-timing`.
+#[derive(Serialize, Debug, Clone)]
+pub struct StageTiming {
+    pub stage: &'static str,
+    pub seconds: f64,
+}
+
+impl StageTiming {
+    /// The time from `started` until now.
+    pub fn since(stage: &'static str, started: Instant) -> Self {
+        StageTiming { stage, seconds: started.elapsed().as_secs_f64() }
+    }
+}
+
+/// A step of an issue ran out of time; the run continues with the next issue.
+#[derive(Debug)]
+pub struct StageTimedOut {
+    pub stage: &'static str,
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for StageTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The {} stage timed out after {}s", self.stage, self.timeout.as_secs())
+    }
+}
+
+impl std::error::Error for StageTimedOut {}
+
+/// Runs `future`, dropping it with [`StageTimedOut`] when it takes longer than `timeout`.
+pub async fn run<T>(stage: &'static str, timeout: Option<Duration>, future: impl Future<Output = anyhow::Result<T>>) -> anyhow::Result<T> {
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result,
+        Err(_) => Err(StageTimedOut { stage, timeout }.into()),
+    }
+}
+
+/// Runs the blocking `f` on a blocking thread, failing with [`StageTimedOut`] when it takes
+/// longer than `timeout`. `f` is then told to stop at its next [`interrupt::check`]. Without a
+/// timeout, `f` runs right away on the current thread.
+pub async fn run_blocking<T: Send + 'static>(
+    stage: &'static str,
+    timeout: Option<Duration>,
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    let Some(timeout) = timeout else {
+        return f();
+    };
+
+    let token = Arc::new(AtomicBool::new(false));
+    let task = tokio::task::spawn_blocking({
+        let token = token.clone();
+        move || interrupt::with_stage_token(Some(token), f)
+    });
+
+    match tokio::time::timeout(timeout, task).await {
+        Ok(result) => result?,
+        Err(_) => {
+            token.store(true, Ordering::SeqCst);
+            Err(StageTimedOut { stage, timeout }.into())
+        }
+    }
+}
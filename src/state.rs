@@ -0,0 +1,74 @@
+use std::{collections::BTreeSet, fs, path::Path};
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+const STATE_FILE: &str = ".getlogs-state.json";
+const BATCH_FILE: &str = ".getlogs-batch.json";
+
+/// Progress kept between runs for a single issue folder.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct IssueState {
+    /// Start time of the last fetch that completed without errors, in RFC 3339.
+    pub last_fetch: Option<String>,
+}
+
+impl IssueState {
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(STATE_FILE);
+        if !path.exists() {
+            return Ok(IssueState::default());
+        }
+
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::write(dir.join(STATE_FILE), serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    pub fn last_fetch(&self) -> anyhow::Result<Option<OffsetDateTime>> {
+        Ok(self.last_fetch.as_deref().map(|t| OffsetDateTime::parse(t, &Rfc3339)).transpose()?)
+    }
+
+    pub fn set_last_fetch(&mut self, time: OffsetDateTime) -> anyhow::Result<()> {
+        self.last_fetch = Some(time.format(&Rfc3339)?);
+
+        Ok(())
+    }
+}
+
+/// Issues completed by the current multi-issue run, kept in the data directory so a batch that
+/// died halfway can be resumed.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct BatchState {
+    pub completed: BTreeSet<String>,
+}
+
+impl BatchState {
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(BATCH_FILE);
+        if !path.exists() {
+            return Ok(BatchState::default());
+        }
+
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::write(dir.join(BATCH_FILE), serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Forgets the progress, e.g. once the batch is complete.
+    pub fn clear(dir: &Path) -> anyhow::Result<()> {
+        let path = dir.join(BATCH_FILE);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! Summaries of converted logcat files: message counts per level, tag and hour, the time span
+//! covered and large gaps between messages.
+
+use std::{collections::BTreeMap, fs, io::{BufRead, BufReader}, path::{Path, PathBuf}};
+use anyhow::Context;
+use serde::Serialize;
+use time::{Date, Month, PrimitiveDateTime, Time};
+
+/// Pauses between two messages longer than this are reported as gaps.
+pub const GAP_SECONDS: i64 = 60;
+
+#[derive(Serialize, Default, Debug)]
+pub struct LogStats {
+    pub file: PathBuf,
+    pub lines: u64,
+    /// Lines without a recognizable logcat prefix.
+    pub unparsed: u64,
+    pub levels: BTreeMap<String, u64>,
+    /// Counts per application ID for converted DLT, per tag for Android logcat.
+    pub tags: BTreeMap<String, u64>,
+    /// Counts per `MM-DD HH` bucket.
+    pub hours: BTreeMap<String, u64>,
+    pub first: Option<String>,
+    pub last: Option<String>,
+    pub gaps: Vec<Gap>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Gap {
+    pub from: String,
+    pub to: String,
+    pub seconds: i64,
+}
+
+struct Line<'a> {
+    timestamp: String,
+    time: PrimitiveDateTime,
+    level: &'a str,
+    tag: &'a str,
+}
+
+/// Statistics of every `.logcat` file in `dir`.
+pub fn stats_logs(dir: &Path) -> anyhow::Result<Vec<LogStats>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Cannot read {}, extract and convert the logs first", dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().map(|e| e == "logcat").unwrap_or(false))
+        .collect();
+    paths.sort();
+
+    paths.iter().map(|p| log_stats(p)).collect()
+}
+
+pub fn log_stats(path: &Path) -> anyhow::Result<LogStats> {
+    let reader = BufReader::new(fs::File::open(path)?);
+    let mut stats = LogStats { file: path.to_path_buf(), ..Default::default() };
+    let mut previous: Option<(String, PrimitiveDateTime)> = None;
+
+    for text in reader.split(b'\n') {
+        let text = String::from_utf8_lossy(&text?).into_owned();
+        stats.lines += 1;
+
+        let Some(line) = parse_line(&text) else {
+            stats.unparsed += 1;
+            continue;
+        };
+
+        *stats.levels.entry(line.level.to_string()).or_default() += 1;
+        *stats.tags.entry(line.tag.to_string()).or_default() += 1;
+        *stats.hours.entry(line.timestamp[..8].to_string()).or_default() += 1;
+
+        if let Some((from, time)) = &previous {
+            let seconds = (line.time - *time).whole_seconds();
+            if seconds > GAP_SECONDS {
+                stats.gaps.push(Gap { from: from.clone(), to: line.timestamp.clone(), seconds });
+            }
+        }
+
+        stats.first.get_or_insert_with(|| line.timestamp.clone());
+        stats.last = Some(line.timestamp.clone());
+        previous = Some((line.timestamp, line.time));
+    }
+
+    stats.gaps.sort_by_key(|g| -g.seconds);
+
+    Ok(stats)
+}
+
+/// Parses the `MM-DD HH:MM:SS.mmm` prefix of both layouts: `ECU APID CTID L text` as written by
+/// the DLT converter, and Android's threadtime `PID TID L TAG: text`.
+fn parse_line(text: &str) -> Option<Line<'_>> {
+    let fields: Vec<&str> = text.split_whitespace().take(6).collect();
+    if fields.len() < 5 || fields[0].len() != 5 || fields[1].len() != 12 || !fields[0].is_ascii() || !fields[1].is_ascii() {
+        return None;
+    }
+
+    let time = parse_timestamp(fields[0], fields[1])?;
+    let timestamp = format!("{} {}", fields[0], fields[1]);
+
+    if let Some((level, tag)) = dlt_columns(text) {
+        Some(Line { timestamp, time, level, tag })
+    } else if is_level(fields[4]) {
+        let tag = fields.get(5).map(|t| t.trim_end_matches(':')).unwrap_or_default();
+        Some(Line { timestamp, time, level: fields[4], tag })
+    } else {
+        None
+    }
+}
+
+/// Level and application ID of a line written by the DLT converter. Its IDs are padded to four
+/// columns and may be empty or look like a level, so they are read by position: the threadtime
+/// PID ends on one of the separators.
+fn dlt_columns(text: &str) -> Option<(&str, &str)> {
+    let bytes = text.as_bytes();
+    if bytes.len() < 36 || [18, 23, 28, 33, 35].iter().any(|&i| bytes[i] != b' ') {
+        return None;
+    }
+
+    let level = text.get(34..35)?;
+    is_level(level).then_some((level, text.get(24..28)?.trim_end()))
+}
+
+fn is_level(field: &str) -> bool {
+    matches!(field, "V" | "D" | "I" | "W" | "E" | "F" | "-")
+}
+
+/// logcat timestamps have no year, a leap year keeps `02-29` valid.
+fn parse_timestamp(date: &str, time: &str) -> Option<PrimitiveDateTime> {
+    let month = Month::try_from(date[..2].parse::<u8>().ok()?).ok()?;
+    let day = date[3..5].parse().ok()?;
+    let (hms, millis) = time.split_once('.')?;
+    let mut hms = hms.split(':').map(|v| v.parse::<u8>().ok());
+
+    let time = Time::from_hms_milli(hms.next()??, hms.next()??, hms.next()??, millis.parse().ok()?).ok()?;
+
+    Some(PrimitiveDateTime::new(Date::from_calendar_date(2000, month, day).ok()?, time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level_and_tag(text: &str) -> Option<(&str, &str)> {
+        parse_line(text).map(|line| (line.level, line.tag))
+    }
+
+    #[test]
+    fn reads_converted_dlt_lines_by_column() {
+        assert_eq!(level_and_tag("01-02 03:04:05.678 ECU1 APP1 CTX1 W low battery"), Some(("W", "APP1")));
+        assert_eq!(level_and_tag("01-02 03:04:05.678 EC   AP   I    E I said so"), Some(("E", "AP")));
+        // No extended header: no application or context ID
+        assert_eq!(level_and_tag("01-02 03:04:05.678 ECU1           - I D boot"), Some(("-", "")));
+        assert_eq!(level_and_tag("01-02 03:04:05.678 ECU1 APP1 CTX1 I "), Some(("I", "APP1")));
+    }
+
+    #[test]
+    fn reads_threadtime_lines_by_field() {
+        assert_eq!(level_and_tag("01-02 03:04:05.678  1234  5678 E ActivityManager: crashed"), Some(("E", "ActivityManager")));
+        assert_eq!(level_and_tag("01-02 03:04:05.678 12345 23456 I W I : x"), Some(("I", "W")));
+        assert_eq!(parse_line("01-02 03:04:05.678  1234  5678 I Tag: x").unwrap().timestamp, "01-02 03:04:05.678");
+    }
+
+    #[test]
+    fn leaves_other_lines_unparsed() {
+        assert_eq!(level_and_tag("--------- beginning of main"), None);
+        assert_eq!(level_and_tag("13-02 03:04:05.678 ECU1 APP1 CTX1 W bad month"), None);
+        assert_eq!(level_and_tag("01-02 03:04:05.678 ECU1 APP1 CTX1 X no level"), None);
+    }
+}
@@ -0,0 +1,181 @@
+//! Offline check of the downloads of an issue folder against its manifest, e.g. after copying
+//! the data directory to another machine, and the `SHA256SUMS` files for checking them with
+//! standard tools instead.
+
+use std::{collections::{BTreeMap, HashSet}, fs, path::Path};
+use anyhow::Context;
+use crate::{bundle, convert, layout::Layout, manifest::{self, CHECKSUMS_FILE, Manifest, Status}};
+
+/// Files of an issue folder, grouped by the outcome of the check.
+#[derive(Debug, Default)]
+pub struct Verification {
+    pub intact: Vec<String>,
+    /// Files whose content no longer matches the recorded hash.
+    pub corrupted: Vec<String>,
+    pub missing: Vec<String>,
+    /// Files the manifest does not list.
+    pub unexpected: Vec<String>,
+    /// Files downloaded before hashes were recorded; only their size was checked.
+    pub unhashed: Vec<String>,
+}
+
+impl Verification {
+    pub fn is_ok(&self) -> bool {
+        self.corrupted.is_empty() && self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Re-hashes every download recorded in the manifest of `dir`, and looks for files it does not
+/// list. Files getlogs creates itself, like the extracted logs, the bundle and the combined log, are not checked.
+/// A `SHA256SUMS` in `dir` supplies the hashes the manifest lacks, and the files it lists are
+/// checked even without a manifest entry.
+pub fn verify_issue(dir: &Path, layout: Layout) -> anyhow::Result<Verification> {
+    let manifest = Manifest::load(dir)?;
+    let mut checksums = read_checksums(dir)?;
+    let mut verification = Verification::default();
+    let mut known = HashSet::new();
+
+    for entry in manifest.attachments.values() {
+        known.insert(entry.filename.clone());
+        if entry.status != Status::Downloaded {
+            continue;
+        }
+
+        let name = entry.filename.clone();
+        let path = entry.path(dir, layout);
+        let listed = checksums.remove(&relative_path(dir, &path));
+        if !path.is_file() {
+            verification.missing.push(name);
+            continue;
+        }
+
+        let intact = match entry.sha256.as_ref().or(listed.as_ref()) {
+            Some(sha256) => manifest::hash_file(&path)? == *sha256,
+            None => {
+                verification.unhashed.push(name.clone());
+                entry.size.is_none_or(|size| fs::metadata(&path).map(|m| m.len() == size).unwrap_or(false))
+            }
+        };
+
+        if intact {
+            verification.intact.push(name);
+        } else {
+            verification.corrupted.push(name);
+        }
+    }
+
+    for (name, sha256) in checksums {
+        let path = dir.join(&name);
+        if let Some(file_name) = path.file_name() {
+            known.insert(file_name.to_string_lossy().into_owned());
+        }
+
+        if !path.is_file() {
+            verification.missing.push(name);
+        } else if manifest::hash_file(&path)? == sha256 {
+            verification.intact.push(name);
+        } else {
+            verification.corrupted.push(name);
+        }
+    }
+
+    let folder = dir.file_name().map(|folder| folder.to_string_lossy().into_owned()).unwrap_or_default();
+    let own_files = [bundle::archive_name(&folder), convert::combined_name(&folder)];
+    for download_dir in layout.download_dirs(dir) {
+        if !download_dir.is_dir() {
+            continue;
+        }
+
+        for entry in fs::read_dir(download_dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            let own_file = manifest::is_internal_file(&name) || own_files.contains(&name);
+            if entry.path().is_file() && !own_file && !known.contains(&name) {
+                verification.unexpected.push(name);
+            }
+        }
+    }
+
+    verification.unexpected.sort();
+
+    Ok(verification)
+}
+
+/// Writes the hashes recorded for the downloads of `dir` to `SHA256SUMS`, one
+/// `<hash>  <path>` line per file, so `sha256sum -c SHA256SUMS` run in the issue folder checks
+/// them. Returns the number of files listed.
+pub fn write_checksums(dir: &Path, layout: Layout) -> anyhow::Result<usize> {
+    let manifest = Manifest::load(dir)?;
+    let lines: BTreeMap<String, &str> = manifest.attachments.values()
+        .filter(|entry| entry.status == Status::Downloaded)
+        .filter_map(|entry| {
+            let path = entry.path(dir, layout);
+            Some((relative_path(dir, &path), entry.sha256.as_deref()?))
+        })
+        .collect();
+
+    let mut contents = String::new();
+    for (name, sha256) in &lines {
+        // Like sha256sum, names with a backslash or line break are escaped and the line marked
+        if name.contains(['\\', '\n', '\r']) {
+            let escaped = name.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r");
+            contents += &format!("\\{}  {}\n", sha256, escaped);
+        } else {
+            contents += &format!("{}  {}\n", sha256, name);
+        }
+    }
+    fs::write(dir.join(CHECKSUMS_FILE), contents)?;
+
+    Ok(lines.len())
+}
+
+/// Hashes by path from the `SHA256SUMS` of `dir`, empty if there is none.
+fn read_checksums(dir: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    let path = dir.join(CHECKSUMS_FILE);
+    if !path.is_file() {
+        return Ok(BTreeMap::new());
+    }
+
+    let mut checksums = BTreeMap::new();
+    for (number, line) in fs::read_to_string(&path)?.lines().enumerate() {
+        let (escaped, line) = match line.strip_prefix('\\') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        // `*` marks files hashed in binary mode, which makes no difference for SHA-256
+        let (sha256, name) = line.split_once("  ").or_else(|| line.split_once(" *"))
+            .filter(|(sha256, _)| sha256.len() == 64 && sha256.bytes().all(|b| b.is_ascii_hexdigit()))
+            .with_context(|| format!("{} line {} is not a sha256sum line", path.display(), number + 1))?;
+        let name = if escaped { unescape(name) } else { name.to_string() };
+
+        checksums.insert(name, sha256.to_ascii_lowercase());
+    }
+
+    Ok(checksums)
+}
+
+fn unescape(name: &str) -> String {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => unescaped.push('\n'),
+            ('\\', Some('r')) => unescaped.push('\r'),
+            ('\\', Some('\\')) => unescaped.push('\\'),
+            _ => {
+                unescaped.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+
+    unescaped
+}
+
+/// `path` inside `dir` with `/` separators, as listed in `SHA256SUMS`.
+fn relative_path(dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
@@ -0,0 +1,252 @@
+//! The `getlogs` binary run on issue folders prepared in a temporary data directory, against a
+//! Jira server that is either never contacted or served by [`serve`].
+
+use std::{fs, io::{BufRead, BufReader, Write}, net::TcpListener, path::{Path, PathBuf}, process::{Command, Output}, thread};
+use serde_json::{Value, json};
+use tempfile::TempDir;
+
+/// A config directory and the `default_path` the issue folders are in.
+struct Data {
+    dir: TempDir,
+}
+
+impl Data {
+    fn new() -> Self {
+        Self::with_config(json!({}))
+    }
+
+    /// With `extra` merged over a config for a Jira server on a port nobody listens on.
+    fn with_config(extra: Value) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("config")).unwrap();
+        fs::create_dir(dir.path().join("logs")).unwrap();
+        let mut config = json!({
+            "default_path": dir.path().join("logs"),
+            "jira_url": "http://127.0.0.1:9",
+            "bearer_token": "unused",
+            "logfile_regex": r".*\.(log|txt|dlt)$",
+        });
+        config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        fs::write(dir.path().join("config/config.json"), config.to_string()).unwrap();
+
+        Data { dir }
+    }
+
+    fn logs(&self) -> PathBuf {
+        self.dir.path().join("logs")
+    }
+
+    /// Writes `name` into the extracted folder of `issue`.
+    fn extracted(&self, issue: &str, name: &str, content: &[u8]) {
+        let dir = self.logs().join(issue).join("logs-extracted");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(name), content).unwrap();
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.run_in(Path::new("."), args)
+    }
+
+    /// Runs in the working directory `dir`, where a `.getlogs.json` is looked up.
+    fn run_in(&self, dir: &Path, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_getlogs"))
+            .args(args)
+            .current_dir(dir)
+            .env("GETLOGS_CONFIG_DIR", self.dir.path().join("config"))
+            .env("HOME", self.dir.path())
+            .output()
+            .unwrap()
+    }
+}
+
+/// Answers each request on a local port with the status and body `route` gives for its path,
+/// returning the base URL.
+fn serve(route: fn(&str, &str) -> (u16, String)) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let host = base.clone();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let host = host.clone();
+            thread::spawn(move || {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body) = route(&host, path);
+                let response = format!("HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+                // getlogs may have given up on slow answers
+                let _ = stream.write_all(response.as_bytes());
+            });
+        }
+    });
+
+    base
+}
+
+/// A DLT message without extended header or payload; `len` below 4 makes it malformed.
+fn dlt_message(len: u16) -> Vec<u8> {
+    let mut bytes = b"DLT\x01".to_vec();
+    bytes.extend(1_700_000_000u32.to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(b"ECU1");
+    bytes.extend([0, 0]);
+    bytes.extend(len.to_be_bytes());
+    bytes
+}
+
+/// The issues the batch state of `logs` records as completed.
+fn completed(logs: &Path) -> Vec<String> {
+    match fs::read_to_string(logs.join(".getlogs-batch.json")) {
+        Ok(batch) => serde_json::from_str::<serde_json::Value>(&batch).unwrap()["completed"].as_array().unwrap()
+            .iter()
+            .map(|key| key.as_str().unwrap().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[test]
+fn leaves_issues_with_failed_conversions_out_of_the_batch() {
+    let data = Data::new();
+    data.extracted("GOOD-1", "good.dlt", &dlt_message(4));
+    data.extracted("BAD-1", "good.dlt", &dlt_message(4));
+    data.extracted("BAD-1", "bad.dlt", &[dlt_message(4), dlt_message(2)].concat());
+
+    let output = data.run(&["convert", "BAD-1", "GOOD-1", "--on-error", "continue"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 of 2 issues failed (BAD-1)"), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(completed(&data.logs()), ["GOOD-1"]);
+    assert!(data.logs().join("BAD-1/logs-extracted/good.logcat").is_file());
+}
+
+#[test]
+fn processes_the_downloaded_files_when_others_fail() {
+    let jira = serve(|host, path| match path {
+        p if p.starts_with("/rest/api/2/issue/FETCH-1?") => (200, json!({ "fields": { "attachment": [
+            { "id": "1", "filename": "good.log", "content": format!("{}/att/1", host), "size": 5 },
+            { "id": "2", "filename": "gone.log", "content": format!("{}/att/2", host), "size": 5 },
+        ] } }).to_string()),
+        "/att/1" => (200, "good\n".to_string()),
+        _ => (410, String::new()),
+    });
+    let data = Data::with_config(json!({ "jira_url": jira }));
+
+    let output = data.run(&["all", "FETCH-1", "--combined"]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("1 of 2 downloads failed for FETCH-1"), "{}", stderr);
+    assert_eq!(fs::read_to_string(data.logs().join("FETCH-1/logs-extracted/good.log")).unwrap(), "good\n");
+    assert!(data.logs().join("FETCH-1/FETCH-1.combined.log").is_file());
+    assert!(completed(&data.logs()).is_empty());
+}
+
+#[test]
+fn reports_and_hooks_issues_with_a_timed_out_step() {
+    let jira = serve(|host, path| match path {
+        p if p.starts_with("/rest/api/2/issue/SLOW-1?") => (200, json!({ "fields": { "attachment": [
+            { "id": "1", "filename": "good.log", "content": format!("{}/att/1", host), "size": 5 },
+            { "id": "2", "filename": "slow.log", "content": format!("{}/att/2", host), "size": 5 },
+        ] } }).to_string()),
+        "/att/1" => (200, "good\n".to_string()),
+        _ => {
+            thread::sleep(std::time::Duration::from_secs(5));
+            (200, "slow\n".to_string())
+        }
+    });
+    let data = Data::with_config(json!({ "jira_url": jira, "post_issue_command": "touch \"$GETLOGS_PATH/hooked\"" }));
+    let report = data.dir.path().join("report.json");
+
+    let output = data.run(&["all", "SLOW-1", "--fetch-timeout", "1s", "--report", "json", "--report-file", report.to_str().unwrap()]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("1 of 1 issues timed out"), "{}", stderr);
+    assert!(data.logs().join("SLOW-1/logs-extracted/good.log").is_file());
+    assert!(data.logs().join("SLOW-1/hooked").is_file());
+    assert_eq!(fs::read_to_string(&report).unwrap().matches("SLOW-1").count(), 1, "{}", fs::read_to_string(&report).unwrap());
+}
+
+#[test]
+fn remembers_only_complete_fetches_for_since_last_run() {
+    let jira = serve(|host, path| match path {
+        p if p.starts_with("/rest/api/2/issue/SINCE-1?") => (200, json!({ "fields": { "attachment": [
+            { "id": "1", "filename": "main.log", "content": format!("{}/att/1", host), "size": 5, "created": "2024-01-01T00:00:00.000+0000" },
+        ] } }).to_string()),
+        "/att/1" => (200, "main\n".to_string()),
+        _ => (404, String::new()),
+    });
+    let data = Data::with_config(json!({ "jira_url": jira }));
+    let state = data.logs().join("SINCE-1/.getlogs-state.json");
+    let last_fetch = || fs::read_to_string(&state).ok().map(|state| serde_json::from_str::<Value>(&state).unwrap()["last_fetch"].clone());
+
+    for narrowed in [&["--sample", "2"][..], &["--filter-expr", "size>1M"], &["--exclude", "main"]] {
+        let output = data.run(&[&["fetch", "SINCE-1"][..], narrowed].concat());
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(last_fetch(), None, "{:?}", narrowed);
+    }
+
+    assert!(data.run(&["fetch", "SINCE-1"]).status.success());
+    assert!(last_fetch().unwrap().is_string());
+}
+
+#[test]
+fn merges_the_local_config_over_the_global_one() {
+    let data = Data::with_config(json!({ "sftp": { "url": "sftp://logs.example.com/{issue}", "password": "global" } }));
+    let project = data.dir.path().join("project");
+    fs::create_dir_all(project.join(".git")).unwrap();
+    fs::create_dir_all(project.join("src/deep")).unwrap();
+    fs::write(project.join(".getlogs.json"), json!({
+        "default_path": "issues",
+        "logfile_regex": r".*\.trace$",
+        "sftp": { "url": "sftp://project.example.com/{issue}" },
+    }).to_string()).unwrap();
+
+    let output = data.run_in(&project.join("src/deep"), &["print-config"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let config: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let local = project.join(".getlogs.json").display().to_string();
+    let global = data.dir.path().join("config/config.json").display().to_string();
+
+    assert_eq!(config["logfile_regex"], json!({ "value": r".*\.trace$", "source": local }));
+    assert_eq!(config["default_path"], json!({ "value": project.join("issues"), "source": local }));
+    assert_eq!(config["sftp"]["value"]["url"], "sftp://project.example.com/{issue}");
+    assert_eq!(config["sftp"]["value"]["password"], "***");
+    assert_eq!(config["jira_url"]["source"], global);
+
+    // The lookup stops at the repository root
+    fs::create_dir_all(project.join("src/.git")).unwrap();
+    let output = data.run_in(&project.join("src/deep"), &["print-config"]);
+    let config: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(config["logfile_regex"]["value"], r".*\.(log|txt|dlt)$");
+    assert_eq!(config["logfile_regex"]["source"], global);
+}
+
+#[test]
+fn fails_the_run_on_a_failed_hook_only_when_configured() {
+    for (fails_run, success) in [(None, true), (Some(true), false)] {
+        let data = Data::with_config(json!({
+            "post_issue_command": "exit 3",
+            "post_run_command": "touch \"$GETLOGS_PATH/ran\"",
+            "post_command_fails_run": fails_run,
+        }));
+        data.extracted("HOOK-1", "good.dlt", &dlt_message(4));
+
+        let output = data.run(&["convert", "HOOK-1"]);
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.success(), success, "{}", stderr);
+        assert!(stderr.contains("post_issue_command failed with"), "{}", stderr);
+        // A failing post_issue_command ends the run before post_run_command when it fails the run
+        assert_eq!(data.logs().join("ran").is_file(), success);
+    }
+}
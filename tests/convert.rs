@@ -0,0 +1,203 @@
+//! `convert_logs` run against DLT traces written to a temporary folder.
+
+use std::{fs, path::{Path, PathBuf}};
+use getlogs::{convert::{ConvertOptions, SplitBy, append_combined, convert_logs}, manifest::{IssueDetails, Manifest}};
+
+/// A DLT message without extended header or payload, behind the storage header of ECU `ECU1`.
+fn message(len: u16) -> Vec<u8> {
+    let mut bytes = b"DLT\x01".to_vec();
+    bytes.extend(1_700_000_000u32.to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(b"ECU1");
+    bytes.extend([0, 0]);
+    bytes.extend(len.to_be_bytes());
+    bytes
+}
+
+/// A verbose info message of `ecu` and application `app` whose only argument is `text`.
+fn log(ecu: &[u8; 4], app: &[u8; 4], text: &str) -> Vec<u8> {
+    let mut payload = 0x200u32.to_le_bytes().to_vec();
+    payload.extend((text.len() as u16 + 1).to_le_bytes());
+    payload.extend(text.as_bytes());
+    payload.push(0);
+
+    let mut bytes = b"DLT\x01".to_vec();
+    bytes.extend(1_700_000_000u32.to_le_bytes());
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(ecu);
+    bytes.extend([0x01, 0]);
+    bytes.extend((4 + 10 + payload.len() as u16).to_be_bytes());
+    bytes.extend([0x41, 1]);
+    bytes.extend(app);
+    bytes.extend(b"CTX1");
+    bytes.extend(payload);
+    bytes
+}
+
+/// The message texts of the converted logcat file `path`.
+fn texts(path: &Path) -> Vec<String> {
+    fs::read_to_string(path).unwrap().lines().map(|line| line.rsplit(' ').next().unwrap().to_string()).collect()
+}
+
+#[test]
+fn converts_the_other_files_when_one_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("good.dlt"), message(4)).unwrap();
+    // A message shorter than its own header fails the file after the first one was written
+    fs::write(dir.path().join("bad.dlt"), [message(4), message(2)].concat()).unwrap();
+
+    let report = convert_logs(dir.path(), &ConvertOptions::default()).unwrap();
+
+    assert_eq!(report.converted, [(dir.path().join("good.dlt"), dir.path().join("good.logcat"))]);
+    assert_eq!(report.failed, ["bad.dlt"]);
+    assert!(dir.path().join("good.logcat").is_file());
+    assert!(!dir.path().join("bad.logcat").exists(), "the partial output of bad.dlt is left behind");
+
+    let failure = report.failure(dir.path()).unwrap().to_string();
+    assert!(failure.starts_with("1 of 2 files could not be converted"), "{}", failure);
+}
+
+#[test]
+fn leaves_extracted_text_files_named_like_an_event_log() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("System.evtx"), b"not an event log").unwrap();
+    fs::write(dir.path().join("System.txt"), b"extracted\n").unwrap();
+
+    let report = convert_logs(dir.path(), &ConvertOptions::default()).unwrap();
+
+    assert_eq!(report.failed, ["System.evtx"]);
+    assert_eq!(fs::read_to_string(dir.path().join("System.txt")).unwrap(), "extracted\n");
+    assert!(!dir.path().join("System.evtx.txt").exists());
+}
+
+#[test]
+fn keeps_only_the_head_or_tail_of_each_trace() {
+    let dir = tempfile::tempdir().unwrap();
+    let trace: Vec<u8> = ["one", "two", "three", "four", "five"].iter().flat_map(|text| log(b"ECU1", b"APP1", text)).collect();
+    fs::write(dir.path().join("trace.dlt"), trace).unwrap();
+    let output = dir.path().join("trace.logcat");
+
+    for (head, tail, expected) in [
+        (None, None, &["one", "two", "three", "four", "five"][..]),
+        (Some(2), None, &["one", "two"]),
+        (None, Some(2), &["four", "five"]),
+        (Some(4), Some(2), &["three", "four"]),
+        (Some(9), Some(9), &["one", "two", "three", "four", "five"]),
+        (None, Some(0), &[]),
+    ] {
+        convert_logs(dir.path(), &ConvertOptions { head, tail, ..ConvertOptions::default() }).unwrap();
+        assert_eq!(texts(&output), expected, "head {:?}, tail {:?}", head, tail);
+    }
+}
+
+/// Writes the input `name` with `content` into the issue folder `dir`, and its converted output
+/// `<name>.logcat` with `output`.
+fn converted(dir: &Path, name: &str, content: &str, output: &str) -> (PathBuf, PathBuf) {
+    let (input, converted) = (dir.join(name), dir.join(format!("{}.logcat", name)));
+    fs::write(&input, content).unwrap();
+    fs::write(&converted, output).unwrap();
+    (input, converted)
+}
+
+#[test]
+fn appends_each_input_to_the_combined_log_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let issue = dir.path().join("KEY-1");
+    fs::create_dir(&issue).unwrap();
+    let a = converted(&issue, "a.dlt", "a", "A\n");
+    let b = converted(&issue, "b.dlt", "b", "B\n");
+
+    let path = append_combined(&issue, &[b.clone(), a.clone()], false, false, false).unwrap();
+    assert_eq!(path, issue.join("KEY-1.combined.log"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "A\nB\n");
+
+    append_combined(&issue, &[a.clone(), b.clone()], false, false, false).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "A\nB\n");
+
+    // A changed input is appended again
+    let b = converted(&issue, "b.dlt", "b, longer", "B2\n");
+    append_combined(&issue, &[a.clone(), b.clone()], false, false, false).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "A\nB\nB2\n");
+
+    append_combined(&issue, &[a, b], true, false, false).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "A\nB2\n");
+    assert_eq!(Manifest::load(&issue).unwrap().combined.len(), 2);
+}
+
+#[test]
+fn replaces_the_header_of_the_combined_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let issue = dir.path().join("KEY-1");
+    fs::create_dir(&issue).unwrap();
+    let details = IssueDetails { key: "KEY-1".to_string(), summary: Some("Crash on boot".to_string()), ..IssueDetails::default() };
+    Manifest { details: Some(details), ..Manifest::default() }.save(&issue).unwrap();
+
+    let a = converted(&issue, "a.dlt", "a", "A\n");
+    let path = append_combined(&issue, &[a], false, true, false).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# getlogs combined log\n# Issue: KEY-1\n# Summary: Crash on boot\n# Sources:\n#   a.dlt\n# ---\nA\n");
+
+    let b = converted(&issue, "b.dlt", "b", "B\n");
+    append_combined(&issue, &[b], false, true, false).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# getlogs combined log\n# Issue: KEY-1\n# Summary: Crash on boot\n# Sources:\n#   a.dlt\n#   b.dlt\n# ---\nA\nB\n"
+    );
+    assert!(!issue.join("KEY-1.combined.log.writing").exists());
+}
+
+/// The files in `dir`, sorted.
+fn files(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn splits_dlt_logs_by_application() {
+    let dir = tempfile::tempdir().unwrap();
+    let trace = [log(b"ECU1", b"APP1", "one"), log(b"ECU1", b"A/B\0", "two"), log(b"ECU1", b"APP1", "three"), message(4)].concat();
+    fs::write(dir.path().join("trace.dlt"), trace).unwrap();
+
+    let report = convert_logs(dir.path(), &ConvertOptions { split_by: Some(SplitBy::AppId), ..ConvertOptions::default() }).unwrap();
+
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+    assert_eq!(files(dir.path()), ["trace.APP1.logcat", "trace.A_B.logcat", "trace.dlt", "trace.none.logcat"]);
+    assert_eq!(texts(&dir.path().join("trace.APP1.logcat")), ["one", "three"]);
+    assert_eq!(texts(&dir.path().join("trace.A_B.logcat")), ["two"]);
+    assert_eq!(fs::read_to_string(dir.path().join("trace.none.logcat")).unwrap().lines().count(), 1);
+}
+
+#[test]
+fn removes_the_split_outputs_of_a_failed_trace() {
+    let dir = tempfile::tempdir().unwrap();
+    let trace = [log(b"ECU1", b"APP1", "one"), log(b"ECU1", b"APP2", "two"), message(2)].concat();
+    fs::write(dir.path().join("trace.dlt"), trace).unwrap();
+
+    let report = convert_logs(dir.path(), &ConvertOptions { split_by: Some(SplitBy::ContextId), ..ConvertOptions::default() }).unwrap();
+
+    assert_eq!(report.failed, ["trace.dlt"]);
+    assert_eq!(files(dir.path()), ["trace.dlt"]);
+}
+
+#[test]
+fn converts_only_the_messages_of_the_given_ecus() {
+    let dir = tempfile::tempdir().unwrap();
+    let trace = [log(b"ECU1", b"APP1", "one"), log(b"ECU2", b"APP1", "two"), log(b"ECU3", b"APP1", "three"), log(b"ECU2", b"APP2", "four")].concat();
+    fs::write(dir.path().join("trace.dlt"), trace).unwrap();
+    let output = dir.path().join("trace.logcat");
+
+    let ecus = |ecus: &[&str]| ConvertOptions { ecus: ecus.iter().map(|ecu| ecu.to_string()).collect(), ..ConvertOptions::default() };
+
+    convert_logs(dir.path(), &ecus(&["ECU2"])).unwrap();
+    assert_eq!(texts(&output), ["two", "four"]);
+    assert!(fs::read_to_string(&output).unwrap().lines().all(|line| line.contains(" ECU2 ")));
+
+    convert_logs(dir.path(), &ecus(&["ECU1", "ECU3"])).unwrap();
+    assert_eq!(texts(&output), ["one", "three"]);
+
+    convert_logs(dir.path(), &ecus(&["ECU9"])).unwrap();
+    assert_eq!(texts(&output), Vec::<String>::new());
+
+    convert_logs(dir.path(), &ecus(&[])).unwrap();
+    assert_eq!(texts(&output).len(), 4);
+}
@@ -0,0 +1,606 @@
+//! `extract_logs` run against zip archives and tarballs built in a temporary issue folder.
+
+use std::{fs, io::Write, path::{Path, PathBuf}};
+use flate2::{Compression, write::GzEncoder};
+use getlogs::{bundle::bundle_logs, config::Config, extract::{ExtractOptions, extract_logs}};
+use serde_json::{Value, json};
+use tempfile::TempDir;
+use zip::{ZipWriter, write::FileOptions};
+
+/// A download folder with the `logs-extracted` destination inside, as `getlogs` lays it out.
+struct Issue {
+    _dir: TempDir,
+    src: PathBuf,
+    dest: PathBuf,
+}
+
+impl Issue {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("ISSUE-1");
+        fs::create_dir(&src).unwrap();
+        let dest = src.join("logs-extracted");
+
+        Issue { _dir: dir, src, dest }
+    }
+
+    fn file(&self, name: &str, content: &[u8]) {
+        fs::write(self.src.join(name), content).unwrap();
+    }
+
+    fn zip(&self, name: &str, entries: &[(&str, &[u8])]) {
+        fs::write(self.src.join(name), zip_bytes(entries)).unwrap();
+    }
+
+    fn extracted(&self) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(&self.dest).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.dest.join(name)).unwrap()
+    }
+}
+
+fn zip_bytes(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, content) in entries {
+        zip.start_file(*name, FileOptions::default()).unwrap();
+        zip.write_all(content).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+fn tar_gz_bytes(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (name, content) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, name, *content).unwrap();
+    }
+    tar.into_inner().unwrap().finish().unwrap()
+}
+
+/// A minimal ext2 image with 1 KiB blocks and `files` in its root directory: superblock in block
+/// 1, group descriptor in block 2, inode table from block 5, the root directory in block 7 and
+/// the file contents after it.
+fn ext2_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+    const BLOCK: usize = 1024;
+    let mut image = vec![0u8; 64 * BLOCK];
+    let mut put = |offset: usize, bytes: &[u8]| image[offset..offset + bytes.len()].copy_from_slice(bytes);
+
+    let superblock = BLOCK;
+    for (offset, value) in [(0, 16u32), (4, 64), (20, 1), (32, 8192), (40, 16), (76, 1), (84, 11)] {
+        put(superblock + offset, &value.to_le_bytes());
+    }
+    put(superblock + 56, &0xEF53u16.to_le_bytes());
+    put(superblock + 88, &128u16.to_le_bytes());
+    put(2 * BLOCK + 8, &5u32.to_le_bytes());
+
+    let mut inode = |number: usize, mode: u16, size: usize, block: usize| {
+        let offset = 5 * BLOCK + (number - 1) * 128;
+        put(offset, &mode.to_le_bytes());
+        put(offset + 4, &(size as u32).to_le_bytes());
+        for i in 0..size.div_ceil(BLOCK) {
+            put(offset + 40 + i * 4, &((block + i) as u32).to_le_bytes());
+        }
+    };
+    inode(2, 0x41ED, BLOCK, 7);
+
+    let mut entries = vec![(2, ".".to_string()), (2, "..".to_string())];
+    let mut block = 8;
+    for (i, (name, content)) in files.iter().enumerate() {
+        inode(12 + i, 0x81A4, content.len(), block);
+        entries.push((12 + i, name.to_string()));
+        block += content.len().div_ceil(BLOCK);
+    }
+
+    let mut offset = 7 * BLOCK;
+    for (i, (number, name)) in entries.iter().enumerate() {
+        let rec_len = if i + 1 == entries.len() { 8 * BLOCK - offset } else { (8 + name.len()).next_multiple_of(4) };
+        put(offset, &(*number as u32).to_le_bytes());
+        put(offset + 4, &(rec_len as u16).to_le_bytes());
+        put(offset + 6, &[name.len() as u8, 1]);
+        put(offset + 8, name.as_bytes());
+        offset += rec_len;
+    }
+
+    let mut block = 8;
+    for (_, content) in files {
+        put(block * BLOCK, content);
+        block += content.len().div_ceil(BLOCK);
+    }
+
+    image
+}
+
+/// A one page PDF drawing `content` with Helvetica as font `F1`, without cross-reference table,
+/// which `getlogs` does not need.
+fn pdf_bytes(content: &str) -> Vec<u8> {
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        "<< /Type /Page /Parent 2 0 R /Contents 4 0 R /Resources << /Font << /F1 5 0 R >> >> >>".to_string(),
+        format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+    ];
+
+    let mut pdf = "%PDF-1.4\n".to_string();
+    for (i, object) in objects.iter().enumerate() {
+        pdf += &format!("{} 0 obj\n{}\nendobj\n", i + 1, object);
+    }
+    pdf += "trailer\n<< /Size 6 /Root 1 0 R >>\n%%EOF\n";
+    pdf.into_bytes()
+}
+
+/// A config extracting `.log` and `.txt` files, with the fields of `extra` added.
+fn config(dir: &Path, extra: Value) -> Config {
+    let mut value = json!({
+        "default_path": dir,
+        "jira_url": "https://jira.example.com",
+        "logfile_regex": r".*\.(log|txt)$",
+    });
+    value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+
+    serde_json::from_value(value).unwrap()
+}
+
+/// A `logfile_regex` that also takes PDFs.
+fn pdfs() -> Value {
+    json!({ "logfile_regex": r".*\.(log|txt|pdf)$" })
+}
+
+#[test]
+fn extracts_matching_entries_and_loose_files() {
+    let issue = Issue::new();
+    issue.file("main.log", b"loose\n");
+    issue.file("screenshot.png", b"png");
+    issue.zip("bundle.zip", &[("logs/app.log", b"app\n"), ("logs/core.bin", b"\0\0"), ("readme.txt", b"read me\n")]);
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["app.log", "main.log", "readme.txt"]);
+    assert_eq!(issue.read("app.log"), "app\n");
+    assert!(report.failed_entries.is_empty() && report.failed_archives.is_empty());
+
+    let counts: Vec<_> = report.sources.iter().map(|c| (c.archive.as_deref(), c.seen, c.matched)).collect();
+    assert_eq!(counts, [(None, 2, 1), (Some("bundle.zip"), 3, 2)]);
+}
+
+#[test]
+fn refuses_entries_leaving_the_archive() {
+    let issue = Issue::new();
+    issue.zip("evil.zip", &[("../../escaped.log", b"x"), ("/tmp/absolute.log", b"x"), ("logs/../inside.log", b"kept\n")]);
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["inside.log"]);
+    assert_eq!(report.failed_entries.len(), 2, "{:?}", report.failed_entries);
+    assert!(report.failed_entries.iter().all(|e| e.contains("leaves the archive")));
+    assert!(!issue.src.parent().unwrap().join("escaped.log").exists());
+    assert!(!issue.dest.join("escaped.log").exists());
+}
+
+#[test]
+fn renames_files_with_the_same_name() {
+    let issue = Issue::new();
+    issue.file("app.log", b"loose\n");
+    issue.zip("bundle.zip", &[("a/app.log", b"first\n"), ("b/app.log", b"second\n")]);
+
+    extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["app-2.log", "app-3.log", "app.log"]);
+    assert_eq!(issue.read("app.log"), "loose\n");
+    assert_eq!(issue.read("app-2.log"), "first\n");
+    assert_eq!(issue.read("app-3.log"), "second\n");
+}
+
+#[test]
+fn rerun_overwrites_its_own_output() {
+    let issue = Issue::new();
+    issue.zip("bundle.zip", &[("a/app.log", b"first\n"), ("b/app.log", b"second\n")]);
+    let config = config(&issue.src, json!({}));
+
+    extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+    extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["app-2.log", "app.log"]);
+}
+
+#[test]
+fn unpacks_nested_archives() {
+    let issue = Issue::new();
+    let inner = zip_bytes(&[("deep.log", b"deep\n")]);
+    issue.zip("outer.zip", &[("inner.zip", &inner), ("top.log", b"top\n")]);
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["deep.log", "top.log"]);
+    let counts: Vec<_> = report.sources.iter().map(|c| (c.archive.as_deref(), c.matched)).collect();
+    assert!(counts.contains(&(Some("outer.zip/inner.zip"), 1)), "{:?}", counts);
+}
+
+#[test]
+fn unpacks_tarballs_inside_zips() {
+    let issue = Issue::new();
+    let tarball = tar_gz_bytes(&[("./var/log/app.log", b"app\n"), ("var/log/core.bin", b"\0")]);
+    issue.zip("bundle.zip", &[("device/logs.tar.gz", &tarball), ("top.log", b"top\n")]);
+    issue.file("loose.tgz", &tar_gz_bytes(&[("syslog.txt", b"sys\n")]));
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["app.log", "syslog.txt", "top.log"]);
+    assert_eq!(issue.read("app.log"), "app\n");
+    assert!(report.failed_entries.is_empty() && report.failed_archives.is_empty());
+}
+
+#[test]
+fn stops_unpacking_deeply_nested_archives() {
+    let issue = Issue::new();
+    let mut nested = zip_bytes(&[("deep.log", b"deep\n")]);
+    for _ in 0..4 {
+        nested = zip_bytes(&[("level.zip", &nested)]);
+    }
+    issue.file("bomb.zip", &nested);
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert!(!issue.dest.join("deep.log").exists());
+    assert_eq!(report.failed_entries.len(), 1, "{:?}", report.failed_entries);
+    assert!(report.failed_entries[0].contains("levels deep"));
+}
+
+#[test]
+fn extracts_files_from_ext_images() {
+    let issue = Issue::new();
+    let large: Vec<u8> = (0..3000u32).flat_map(|n| format!("{}\n", n % 10).into_bytes()).collect();
+    issue.file("rootfs.img", &ext2_bytes(&[("app.log", b"app\n"), ("core.bin", b"\0"), ("large.log", &large)]));
+    issue.zip("bundle.zip", &[("data.ext4", &ext2_bytes(&[("nested.txt", b"nested\n")]))]);
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["app.log", "large.log", "nested.txt"]);
+    assert_eq!(issue.read("app.log"), "app\n");
+    assert_eq!(issue.read("large.log").as_bytes(), large);
+    assert!(report.failed_entries.is_empty() && report.failed_archives.is_empty());
+}
+
+#[test]
+fn reports_unsupported_images() {
+    let issue = Issue::new();
+    let mut squashfs = b"hsqs".to_vec();
+    squashfs.resize(4096, 0);
+    issue.file("system.img", &squashfs);
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(report.failed_archives.len(), 1);
+    assert!(report.failed_archives[0].contains("squashfs"), "{:?}", report.failed_archives);
+}
+
+#[test]
+fn extracts_the_text_of_pdfs() {
+    let issue = Issue::new();
+    issue.file("report.pdf", &pdf_bytes(
+        "BT /F1 10 Tf 14 TL 72 720 Td (10:00:01 boot) Tj T* [(10:00:02 w) 20 (atchdog) -600 (reset \\(2\\))] TJ ET"
+    ));
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, pdfs()), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["report.txt"]);
+    assert_eq!(issue.read("report.txt"), "10:00:01 boot\n10:00:02 watchdog reset (2)\n");
+    assert!(report.failed_documents.is_empty());
+}
+
+#[test]
+fn reports_pdfs_without_text() {
+    let issue = Issue::new();
+    issue.file("scan.pdf", &pdf_bytes("q 595 0 0 842 0 0 cm /Im1 Do Q"));
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, pdfs()), &ExtractOptions::default()).unwrap();
+
+    assert!(issue.extracted().is_empty());
+    assert_eq!(report.failed_documents.len(), 1);
+    assert!(report.failed_documents[0].starts_with("scan.pdf: no text layer"), "{:?}", report.failed_documents);
+}
+
+#[test]
+fn skips_pdfs_not_matching_the_logfile_regex() {
+    let issue = Issue::new();
+    issue.file("report.pdf", &pdf_bytes("BT /F1 10 Tf 72 720 Td (10:00:01 boot) Tj ET"));
+    issue.file("main.log", b"loose\n");
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["main.log"]);
+    assert!(report.failed_documents.is_empty());
+}
+
+#[test]
+fn does_not_extract_the_bundle_of_an_earlier_run() {
+    let issue = Issue::new();
+    issue.file("main.log", b"loose\n");
+    let config = config(&issue.src, json!({}));
+    extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+    bundle_logs(&issue.dest, &issue.src.join("ISSUE-1-logs.tar.gz"), "ISSUE-1-logs").unwrap();
+
+    let report = extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["main.log"]);
+    assert_eq!(report.sources.len(), 1, "{:?}", report.sources.iter().map(|s| &s.archive).collect::<Vec<_>>());
+}
+
+#[test]
+fn does_not_extract_the_combined_log() {
+    let issue = Issue::new();
+    issue.file("main.log", b"loose\n");
+    issue.file("ISSUE-1.combined.log", b"loose\n");
+
+    extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["main.log"]);
+}
+
+/// `10:00:01 decoded boot` and a newline, base64 encoded.
+const BASE64_LOG: &[u8] = b"MTA6MDA6MDEgZGVjb2RlZCBib290Cg==";
+
+/// A config decoding `.b64` files that `logfile_regex` takes.
+fn base64s() -> Value {
+    json!({ "logfile_regex": r".*\.(log|b64)$", "base64_regex": r"\.b64$" })
+}
+
+#[test]
+fn decodes_base64_files_matching_the_logfile_regex() {
+    let issue = Issue::new();
+    issue.file("boot.log.b64", BASE64_LOG);
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, base64s()), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["boot.log"]);
+    assert_eq!(issue.read("boot.log"), "10:00:01 decoded boot\n");
+    assert_eq!((report.sources[0].seen, report.sources[0].matched), (1, 1));
+}
+
+#[test]
+fn skips_base64_files_not_matching_the_patterns() {
+    let issue = Issue::new();
+    issue.file("boot.log.b64", BASE64_LOG);
+    issue.file("secret.log.b64", BASE64_LOG);
+    issue.file("main.log", b"loose\n");
+    let not_included = config(&issue.src, json!({ "base64_regex": r"\.b64$", "exclude_regex": "^secret" }));
+
+    extract_logs(&issue.src, &issue.dest, &not_included, &ExtractOptions::default()).unwrap();
+    assert_eq!(issue.extracted(), ["main.log"]);
+
+    let included = config(&issue.src, json!({ "logfile_regex": r".*\.(log|b64)$", "base64_regex": r"\.b64$", "exclude_regex": "^secret" }));
+    extract_logs(&issue.src, &issue.dest, &included, &ExtractOptions::default()).unwrap();
+    assert_eq!(issue.extracted(), ["boot.log", "main.log"]);
+}
+
+#[test]
+fn counts_base64_files_as_matched_only_when_their_content_matches() {
+    let issue = Issue::new();
+    issue.file("boot.log.b64", BASE64_LOG);
+    let options = ExtractOptions { content_regex: Some(regex::bytes::Regex::new("watchdog").unwrap()), ..ExtractOptions::default() };
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, base64s()), &options).unwrap();
+
+    assert!(issue.extracted().is_empty());
+    assert_eq!((report.sources[0].seen, report.sources[0].matched), (1, 0));
+}
+
+#[test]
+fn keeps_one_of_rotated_base64_logs() {
+    let issue = Issue::new();
+    issue.file("app.1.log.b64", BASE64_LOG);
+    issue.file("app.2.log.b64", BASE64_LOG);
+    let config = config(&issue.src, json!({
+        "logfile_regex": r".*\.b64$",
+        "base64_regex": r"\.b64$",
+        "rotated_logs": { "pattern": r"^(?P<group>app)\.(?P<order>\d+)\.log\.b64$" },
+    }));
+
+    extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["app.1.log"]);
+}
+
+#[test]
+fn picks_loose_files_for_stdout_by_entry_next_to_base64_files() {
+    let issue = Issue::new();
+    issue.file("boot.log.b64", BASE64_LOG);
+    issue.file("main.log", b"loose\n");
+    let options = ExtractOptions { stdout: true, entries: vec!["main.log".into()], ..ExtractOptions::default() };
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, base64s()), &options).unwrap();
+
+    assert_eq!(report.planned, [("main.log".to_string(), 6)]);
+}
+
+#[test]
+fn reports_archives_that_cannot_be_opened() {
+    let issue = Issue::new();
+    issue.file("broken.zip", b"PK\x03\x04 truncated");
+    issue.zip("good.zip", &[("ok.log", b"ok\n")]);
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["ok.log"]);
+    assert_eq!(report.failed_archives.len(), 1);
+    assert!(report.failed_archives[0].starts_with("broken.zip"));
+}
+
+#[test]
+fn entry_selects_exact_paths() {
+    let issue = Issue::new();
+    issue.zip("bundle.zip", &[("a/app.log", b"a\n"), ("b/app.log", b"b\n"), ("b/other.bin", b"bin")]);
+    let options = ExtractOptions { entries: vec!["b/app.log".into(), "b/other.bin".into()], ..ExtractOptions::default() };
+
+    extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &options).unwrap();
+
+    assert_eq!(issue.extracted(), ["app.log", "other.bin"]);
+    assert_eq!(issue.read("app.log"), "b\n");
+}
+
+#[test]
+fn dry_run_writes_nothing() {
+    let issue = Issue::new();
+    issue.file("main.log", b"loose\n");
+    issue.zip("bundle.zip", &[("logs/app.log", b"app\n")]);
+    let options = ExtractOptions { dry_run: true, ..ExtractOptions::default() };
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &options).unwrap();
+
+    assert!(!issue.dest.exists());
+    let planned: Vec<&str> = report.planned.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(planned, ["bundle.zip: logs/app.log", "main.log"]);
+}
+
+#[test]
+fn keeps_the_first_of_rotated_logs() {
+    let issue = Issue::new();
+    issue.file("app.log", b"current\n");
+    issue.zip("bundle.zip", &[("logs/app.1.log", b"1\n"), ("logs/app.2.log", b"2\n"), ("logs/app.10.log", b"10\n"), ("logs/other.log", b"other\n")]);
+    let config = config(&issue.src, json!({ "rotated_logs": { "pattern": r"^(?P<group>app)(\.(?P<order>\d+))?\.log$" } }));
+
+    let report = extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["app.log", "other.log"]);
+    assert_eq!(issue.read("app.log"), "current\n");
+    assert_eq!(report.sources[1].skipped(), 3);
+}
+
+#[test]
+fn keeps_the_last_of_rotated_logs_per_group() {
+    let issue = Issue::new();
+    issue.zip("bundle.zip", &[
+        ("sys-2024-01-05.log", b"sys jan\n"),
+        ("sys-2024-03-01.log", b"sys mar\n"),
+        ("net-2024-02-01.log", b"net feb\n"),
+        ("net-2023-12-31.log", b"net dec\n"),
+    ]);
+    let rotation = json!({ "pattern": r"^(?P<group>\w+)-(?P<order>[\d-]+)\.log$", "keep": "last" });
+    let config = config(&issue.src, json!({ "rotated_logs": rotation }));
+
+    extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["net-2024-02-01.log", "sys-2024-03-01.log"]);
+}
+
+#[test]
+fn rerun_skips_files_already_extracted() {
+    let issue = Issue::new();
+    issue.file("main.log", b"loose\n");
+    issue.zip("bundle.zip", &[("logs/app.log", b"app\n")]);
+    let config = config(&issue.src, json!({}));
+
+    let first = extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+    assert_eq!(first.unchanged, 0);
+
+    // Same size, other content: only found by comparing hashes
+    fs::write(issue.dest.join("app.log"), b"xyz\n").unwrap();
+    let rerun = extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+    assert_eq!(rerun.unchanged, 2);
+    assert_eq!(issue.read("app.log"), "xyz\n");
+
+    let verified = extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions { verify: true, ..ExtractOptions::default() }).unwrap();
+    assert_eq!(verified.unchanged, 1);
+    assert_eq!(issue.read("app.log"), "app\n");
+    assert_eq!(verified.sources[1].matched, 1);
+}
+
+#[test]
+fn force_extracts_unchanged_files_again() {
+    let issue = Issue::new();
+    issue.file("main.log", b"loose\n");
+    let config = config(&issue.src, json!({}));
+
+    extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+    fs::write(issue.dest.join("main.log"), b"LOOSE\n").unwrap();
+    let forced = extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions { force: true, ..ExtractOptions::default() }).unwrap();
+
+    assert_eq!(forced.unchanged, 0);
+    assert_eq!(issue.read("main.log"), "loose\n");
+}
+
+#[test]
+fn exclude_regex_overrides_the_include_patterns() {
+    let issue = Issue::new();
+    issue.file("main.txt", b"main\n");
+    issue.file("readme.txt", b"read me\n");
+    issue.zip("bundle.zip", &[("docs/notes.txt", b"notes\n"), ("logs/app.log", b"app\n")]);
+    let config = config(&issue.src, json!({ "exclude_regex": r"(^|/)(readme|notes)\.txt$" }));
+
+    let report = extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions::default()).unwrap();
+
+    assert_eq!(issue.extracted(), ["app.log", "main.txt"]);
+    let counts: Vec<_> = report.sources.iter().map(|c| (c.seen, c.matched)).collect();
+    assert_eq!(counts, [(2, 1), (2, 1)]);
+}
+
+#[test]
+fn refuses_invalid_name_patterns() {
+    let issue = Issue::new();
+    issue.file("main.log", b"loose\n");
+
+    for (field, extra) in [("logfile_regex", json!({ "logfile_regex": "(" })), ("archive_regex", json!({ "archive_regex": "[" }))] {
+        let err = extract_logs(&issue.src, &issue.dest, &config(&issue.src, extra), &ExtractOptions::default()).unwrap_err();
+        assert!(err.to_string().contains(field), "{}", err);
+    }
+    assert!(issue.extracted().is_empty());
+}
+
+/// Options keeping only files with a line matching `pattern`.
+fn grep(pattern: &str) -> ExtractOptions {
+    ExtractOptions { content_regex: Some(regex::bytes::Regex::new(pattern).unwrap()), ..ExtractOptions::default() }
+}
+
+#[test]
+fn keeps_loose_files_and_entries_with_matching_content() {
+    let issue = Issue::new();
+    issue.file("crash.log", b"boot\nrunning\nSERIAL=R58M1X\nshutdown\n");
+    issue.file("quiet.log", b"boot\nshutdown\n");
+    issue.zip("bundle.zip", &[("a.log", b"x\nSERIAL=R58M2Y\n"), ("b.log", b"SERIAL=unknown\n")]);
+    fs::write(issue.src.join("more.tar.gz"), tar_gz_bytes(&[("c.log", b"SERIAL=R58M3Z"), ("d.log", b"nothing\n")])).unwrap();
+
+    let report = extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &grep("SERIAL=R58M[0-9A-Z]+")).unwrap();
+
+    assert_eq!(issue.extracted(), ["a.log", "c.log", "crash.log"]);
+    assert_eq!(issue.read("crash.log"), "boot\nrunning\nSERIAL=R58M1X\nshutdown\n");
+    assert_eq!(issue.read("a.log"), "x\nSERIAL=R58M2Y\n");
+    // The counts are of names matching logfile_regex
+    let counts: Vec<_> = report.sources.iter().map(|c| (c.archive.as_deref(), c.seen, c.matched)).collect();
+    assert_eq!(counts, [(None, 2, 2), (Some("bundle.zip"), 2, 2), (Some("more.tar.gz"), 2, 2)]);
+}
+
+#[test]
+fn searches_only_the_first_content_lines() {
+    let issue = Issue::new();
+    issue.file("main.log", b"one\ntwo\nwatchdog\n");
+    issue.zip("bundle.zip", &[("entry.log", b"one\ntwo\nwatchdog\n")]);
+    let config = config(&issue.src, json!({}));
+
+    extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions { content_lines: Some(2), ..grep("watchdog") }).unwrap();
+    assert!(!issue.dest.exists() || issue.extracted().is_empty(), "{:?}", issue.extracted());
+
+    extract_logs(&issue.src, &issue.dest, &config, &ExtractOptions { content_lines: Some(3), ..grep("watchdog") }).unwrap();
+    assert_eq!(issue.extracted(), ["entry.log", "main.log"]);
+}
+
+#[test]
+fn searches_lines_longer_than_the_buffer_in_pieces() {
+    let issue = Issue::new();
+    let long = format!("{}watchdog{}\nend\n", "a".repeat(1000), "b".repeat(1000));
+    issue.file("long.log", long.as_bytes());
+    issue.zip("bundle.zip", &[("long-entry.log", long.as_bytes())]);
+
+    extract_logs(&issue.src, &issue.dest, &config(&issue.src, json!({})), &ExtractOptions { buffer_size: 64, ..grep("watchdog") }).unwrap();
+
+    assert_eq!(issue.extracted(), ["long-entry.log", "long.log"]);
+    assert_eq!(issue.read("long.log"), long);
+    assert_eq!(issue.read("long-entry.log"), long);
+}
@@ -0,0 +1,72 @@
+//! The library fetch API run against a Jira server answered by [`serve`].
+
+use std::{io::{BufRead, BufReader, Write}, net::TcpListener, thread};
+use getlogs::{config::Config, fetch::fetch_attachments_to_memory};
+use serde_json::json;
+
+/// Answers each request on a local port with the status and body `route` gives for its path,
+/// returning the base URL.
+fn serve(route: fn(&str, &str) -> (u16, String)) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let host = base.clone();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = route(&host, path);
+            let response = format!("HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+
+    base
+}
+
+fn config(jira_url: &str) -> Config {
+    serde_json::from_value(json!({
+        "default_path": std::env::temp_dir(),
+        "jira_url": jira_url,
+        "bearer_token": "unused",
+        "logfile_regex": r".*\.log$",
+    })).unwrap()
+}
+
+#[tokio::test]
+async fn fetches_attachments_into_memory() {
+    let jira = serve(|host, path| match path {
+        p if p.starts_with("/rest/api/2/issue/MEM-1?") => (200, json!({ "fields": { "attachment": [
+            { "id": "1", "filename": "main.log", "content": format!("{}/att/1", host), "size": 5 },
+            { "id": "2", "filename": "trace.dlt", "content": "/att/2", "size": 3 },
+        ] } }).to_string()),
+        "/att/1" => (200, "main\n".to_string()),
+        "/att/2" => (200, "DLT".to_string()),
+        _ => (404, String::new()),
+    });
+
+    let files = fetch_attachments_to_memory(&config(&jira), "MEM-1").await.unwrap();
+
+    let files: Vec<(&str, &[u8])> = files.iter().map(|(name, content)| (name.as_str(), content.as_ref())).collect();
+    assert_eq!(files, [("main.log", &b"main\n"[..]), ("trace.dlt", b"DLT")]);
+}
+
+#[tokio::test]
+async fn fails_when_the_attachment_field_is_hidden() {
+    let jira = serve(|_, path| match path {
+        p if p.starts_with("/rest/api/2/issue/MEM-2?") => (200, json!({ "fields": { "summary": "Crash" } }).to_string()),
+        _ => (404, String::new()),
+    });
+
+    let err = fetch_attachments_to_memory(&config(&jira), "MEM-2").await.unwrap_err();
+
+    assert!(err.to_string().contains("The attachment field of MEM-2 is not visible"), "{}", err);
+}
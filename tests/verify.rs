@@ -0,0 +1,104 @@
+//! `write_checksums` and `verify_issue` run on issue folders with a manifest written by hand.
+
+use std::{fs, path::{Path, PathBuf}};
+use getlogs::{layout::Layout, manifest::{CHECKSUMS_FILE, Manifest, hash_reader}, verify::{verify_issue, write_checksums}};
+use serde_json::json;
+use tempfile::TempDir;
+
+/// An issue folder `KEY-1` with the downloads `files`, recorded in its manifest with their hash
+/// if `hashed`.
+fn issue(files: &[(&str, &[u8], bool)]) -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let issue = dir.path().join("KEY-1");
+    fs::create_dir(&issue).unwrap();
+
+    let mut attachments = serde_json::Map::new();
+    for (index, (name, content, hashed)) in files.iter().enumerate() {
+        fs::write(issue.join(name), content).unwrap();
+        attachments.insert(index.to_string(), json!({
+            "filename": name,
+            "size": content.len(),
+            "status": "downloaded",
+            "error": null,
+            "sha256": hashed.then(|| sha256(content)),
+        }));
+    }
+    attachments.insert("gone".to_string(), json!({ "filename": "gone.log", "size": null, "status": "failed", "error": "HTTP 410" }));
+    serde_json::from_value::<Manifest>(json!({ "attachments": attachments })).unwrap().save(&issue).unwrap();
+
+    (dir, issue)
+}
+
+fn sha256(content: &[u8]) -> String {
+    hash_reader(content).unwrap()
+}
+
+fn checksums(issue: &Path) -> String {
+    fs::read_to_string(issue.join(CHECKSUMS_FILE)).unwrap()
+}
+
+#[test]
+fn writes_the_recorded_hashes_in_sha256sum_format() {
+    let (_dir, issue) = issue(&[("main.log", b"main\n", true), ("trace.dlt", b"DLT", false), ("b.log", b"b\n", true)]);
+
+    assert_eq!(write_checksums(&issue, Layout::Flat).unwrap(), 2);
+    assert_eq!(checksums(&issue), format!("{}  b.log\n{}  main.log\n", sha256(b"b\n"), sha256(b"main\n")));
+}
+
+#[cfg(unix)]
+#[test]
+fn escapes_names_like_sha256sum() {
+    let (_dir, issue) = issue(&[("odd\\name\n.log", b"odd\n", true)]);
+
+    write_checksums(&issue, Layout::Flat).unwrap();
+    assert_eq!(checksums(&issue), format!("\\{}  odd\\\\name\\n.log\n", sha256(b"odd\n")));
+
+    let verification = verify_issue(&issue, Layout::Flat).unwrap();
+    assert_eq!(verification.intact, ["odd\\name\n.log"]);
+    assert!(verification.is_ok(), "{:?}", verification);
+}
+
+#[test]
+fn checks_the_downloads_with_the_hashes_of_both_files() {
+    let (_dir, issue) = issue(&[("main.log", b"main\n", true), ("trace.dlt", b"DLT", false), ("old.log", b"old\n", false)]);
+    fs::write(issue.join("extra.log"), "extra\n").unwrap();
+    fs::write(issue.join(CHECKSUMS_FILE), format!("{} *trace.dlt\n{}  extra.log\n", sha256(b"DLT").to_uppercase(), sha256(b"extra\n"))).unwrap();
+
+    let verification = verify_issue(&issue, Layout::Flat).unwrap();
+    let mut intact = verification.intact.clone();
+    intact.sort();
+    assert_eq!(intact, ["extra.log", "main.log", "old.log", "trace.dlt"]);
+    assert_eq!(verification.unhashed, ["old.log"]);
+    assert!(verification.is_ok(), "{:?}", verification);
+
+    fs::write(issue.join("trace.dlt"), "DLT!").unwrap();
+    fs::remove_file(issue.join("extra.log")).unwrap();
+    let verification = verify_issue(&issue, Layout::Flat).unwrap();
+    assert_eq!(verification.corrupted, ["trace.dlt"]);
+    assert_eq!(verification.missing, ["extra.log"]);
+}
+
+#[test]
+fn ignores_the_files_getlogs_writes_itself() {
+    let (_dir, issue) = issue(&[("main.log", b"main\n", true)]);
+    write_checksums(&issue, Layout::Flat).unwrap();
+    for own in ["KEY-1-logs.tar.gz", "KEY-1.combined.log", ".getlogs-state.json", "big.zip.part", "big.zip.sample"] {
+        fs::write(issue.join(own), "x").unwrap();
+    }
+    fs::create_dir(issue.join("logs-extracted")).unwrap();
+    fs::write(issue.join("logs-extracted/main.log"), "main\n").unwrap();
+    fs::write(issue.join("stray.bin"), "?").unwrap();
+
+    let verification = verify_issue(&issue, Layout::Flat).unwrap();
+    assert_eq!(verification.intact, ["main.log"]);
+    assert_eq!(verification.unexpected, ["stray.bin"]);
+}
+
+#[test]
+fn refuses_malformed_checksum_files() {
+    let (_dir, issue) = issue(&[("main.log", b"main\n", true)]);
+    fs::write(issue.join(CHECKSUMS_FILE), format!("{}  main.log\nnot a hash  x.log\n", sha256(b"main\n"))).unwrap();
+
+    let err = verify_issue(&issue, Layout::Flat).unwrap_err();
+    assert!(err.to_string().contains("line 2 is not a sha256sum line"), "{}", err);
+}